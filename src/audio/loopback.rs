@@ -2,63 +2,307 @@
 //! Captures audio from output devices (e.g., Speakers) using Windows Audio Session API
 
 use anyhow::{Context, Result};
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use ringbuf::traits::Producer;
-use rubato::{SincFixedIn, SincInterpolationParameters, SincInterpolationType, WindowFunction, Resampler};
+use rubato::{FastFixedIn, PolynomialDegree, SincFixedIn, SincInterpolationParameters, SincInterpolationType, VecResampler, WindowFunction};
 use std::ptr;
-use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
 use std::sync::Arc;
 use std::thread;
-use tracing::{info, error, warn};
-use crate::config::ChannelSource;
-use crate::dsp::{DspChain, SharedLevels};
-use super::ChannelSettings;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, error, warn};
+use crate::config::{CaptureOverrides, CaptureSampleType, ChannelSource, MissingChannelBehavior, ResamplerQuality, SourceFormatCache, SourceRole, SwapScope};
+use crate::dsp::{balance_multipliers, db_to_linear, output_channel_names, route_pair, slew_gain, DspChain, FrameAligner, PairGains, SharedLevels, SmoothedValue, ThreeBandEq, TpdfDither};
+use super::{stereo_passthrough, ChannelMap, ChannelSettings, EventBus, RouterEvent};
 
-use windows::core::PCWSTR;
+use windows::core::{HSTRING, PCWSTR};
+use windows::Win32::Devices::FunctionDiscovery::PKEY_Device_FriendlyName;
 use windows::Win32::Foundation::WAIT_OBJECT_0;
 use windows::Win32::Media::Audio::*;
 use windows::Win32::Media::Audio::Endpoints::IAudioEndpointVolume;
 use windows::Win32::System::Com::*;
 use windows::Win32::System::Threading::*;
 
+/// WASAPI loopback capture buffer duration
+pub const CAPTURE_BUFFER_MS: f32 = 20.0;
+/// How long `start` waits for the capture thread to open a direct-mode target
+const DIRECT_INIT_TIMEOUT: Duration = Duration::from_secs(3);
+/// Silence on the source before checking whether it is held in exclusive mode
+const EXCLUSIVE_PROBE_INTERVAL: Duration = Duration::from_secs(5);
+/// Longest gap between exclusive-mode probes while the source stays silent
+const EXCLUSIVE_PROBE_MAX_INTERVAL: Duration = Duration::from_secs(600);
+/// Extra wait past the preset fade-out before applying settings regardless
+const PRESET_HOLD_SLACK: Duration = Duration::from_millis(100);
+/// How often a paused capture thread checks whether to resume
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// Gap-free capture needed for one source clock measurement
+const CLOCK_WINDOW_SECS: f64 = 10.0;
+/// Source clock deviation beyond which resampling can't sound right
+const CLOCK_DRIFT_WARN_PPM: f64 = 5000.0;
+/// Volume change level safety requests on sustained clipping
+const LEVEL_SAFETY_STEP_DB: f32 = -3.0;
+
+/// Resampling stage of the running session, for status display
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResamplerStatus {
+    pub source_rate: u32,
+    pub target_rate: u32,
+    /// target / source rate, None when the rates match
+    pub ratio: Option<f64>,
+    /// How far the source's real frame rate is from its nominal rate
+    pub source_drift_ppm: Option<f64>,
+}
+
+impl std::fmt::Display for ResamplerStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.ratio {
+            Some(ratio) => write!(f, "{} -> {} Hz (ratio {:.6})", self.source_rate, self.target_rate, ratio)?,
+            None => write!(f, "off ({} Hz)", self.target_rate)?,
+        }
+        if let Some(ppm) = self.source_drift_ppm {
+            write!(f, ", source clock {:+.0} ppm", ppm)?;
+        }
+        Ok(())
+    }
+}
+
+/// Measures the source's real frame rate from packet QPC timestamps (100 ns
+/// units) over gap-free stretches; loopback delivers nothing while idle
+struct ClockMonitor {
+    nominal_rate: f64,
+    /// QPC of the first packet in the window and frames delivered since
+    window: Option<(u64, u64)>,
+}
+
+impl ClockMonitor {
+    fn new(nominal_rate: u32) -> Self {
+        Self { nominal_rate: nominal_rate as f64, window: None }
+    }
+
+    /// Record a packet; returns the drift in ppm when a window completes
+    fn packet(&mut self, qpc: u64, frames: u32, discontinuity: bool) -> Option<f64> {
+        let Some((start, counted)) = self.window.filter(|_| !discontinuity) else {
+            self.window = Some((qpc, frames as u64));
+            return None;
+        };
+        let elapsed = qpc.saturating_sub(start) as f64 / 1e7;
+        // A pause in delivery (idle source) starts a new window
+        if elapsed > counted as f64 / self.nominal_rate + 0.1 {
+            self.window = Some((qpc, frames as u64));
+            return None;
+        }
+        if elapsed < CLOCK_WINDOW_SECS {
+            self.window = Some((start, counted + frames as u64));
+            return None;
+        }
+        self.window = Some((qpc, frames as u64));
+        Some((counted as f64 / elapsed / self.nominal_rate - 1.0) * 1e6)
+    }
+}
+
 /// DSP configuration for loopback capture
 #[derive(Clone)]
 pub struct DspConfig {
+    /// Gain on the captured samples before routing and DSP
+    pub input_gain_db: Arc<RwLock<f32>>,
     pub delay_ms: Arc<RwLock<f32>>,
     pub eq_enabled: Arc<RwLock<bool>>,
     pub eq_low: Arc<RwLock<f32>>,
     pub eq_mid: Arc<RwLock<f32>>,
     pub eq_high: Arc<RwLock<f32>>,
+    pub ms_eq_enabled: Arc<RwLock<bool>>,
+    /// Mid/side EQ gains as [low, mid, high] dB
+    pub ms_eq_mid: Arc<RwLock<[f32; 3]>>,
+    pub ms_eq_side: Arc<RwLock<[f32; 3]>>,
+    /// Shelf slope (S) for the EQ low/high bands
+    pub eq_shelf_slope: Arc<RwLock<f32>>,
+    /// EQ (low, mid, high) band frequencies in Hz
+    pub eq_freqs: Arc<RwLock<(f32, f32, f32)>>,
+    /// Complementary pre/de-emphasis shelves and their (frequency, dB)
+    pub emphasis_enabled: Arc<RwLock<bool>>,
+    pub emphasis: Arc<RwLock<(f32, f32)>>,
+    /// Mask sample-level glitches in the captured signal
+    pub declick: Arc<RwLock<bool>>,
     pub upmix_enabled: Arc<RwLock<bool>>,
+    /// Feed the quad rear pair with the upmix alone (`UpmixMode::FullSurround`)
+    pub upmix_full_surround: Arc<RwLock<bool>>,
     pub upmix_strength: Arc<RwLock<f32>>,
+    pub upmix_ambience_ms: Arc<RwLock<f32>>,
+    /// A-weight the level meter
+    pub meter_a_weighting: Arc<RwLock<bool>>,
+    /// Interval between level meter publishes
+    pub meter_update_ms: Arc<RwLock<f32>>,
+    /// Last seen source endpoint/format, seeded from config and refreshed by the capture thread
+    pub source_format_cache: Arc<RwLock<Option<SourceFormatCache>>>,
+    /// DSP dry/wet mix (0.0 = dry, 1.0 = wet)
+    pub dry_wet: Arc<RwLock<f32>>,
+    /// Loudness leveler and its (attack, release) times in ms
+    pub compander_enabled: Arc<RwLock<bool>>,
+    /// Protective high-pass on the output and its cutoff in Hz
+    pub subsonic_filter: Arc<RwLock<bool>>,
+    pub subsonic_cutoff_hz: Arc<RwLock<f32>>,
+    pub compander_times: Arc<RwLock<(f32, f32)>>,
+    /// Slow auto-gain and its target RMS level in dBFS
+    pub agc_enabled: Arc<RwLock<bool>>,
+    pub agc_target_db: Arc<RwLock<f32>>,
+    /// Output limiter, stereo-linked unless per-channel
+    pub limiter_enabled: Arc<RwLock<bool>>,
+    pub limiter_per_channel: Arc<RwLock<bool>>,
+    pub limiter_ceiling_db: Arc<RwLock<f32>>,
+    pub upmix_rear_delay_ms: Arc<RwLock<f32>>,
+    /// Soft-knee limit on each routed channel before the upmix sum
+    pub per_channel_limit: Arc<RwLock<bool>>,
+    /// Auto-attenuate when front+rear sum exceeds unity
+    pub normalize_upmix: Arc<RwLock<bool>>,
+    /// TPDF dither on the upmix tail: enabled, bit depth, noise shaping
+    pub upmix_dither: Arc<RwLock<bool>>,
+    pub upmix_dither_bits: Arc<RwLock<u32>>,
+    pub upmix_dither_shaping: Arc<RwLock<bool>>,
+    /// Centre extraction for 5.1 output: enabled, share moved from the fronts
+    pub upmix_center_enabled: Arc<RwLock<bool>>,
+    pub upmix_center_level: Arc<RwLock<f32>>,
     pub shared_levels: Arc<SharedLevels>,
     /// Master volume from source device (0.0-1.0)
     pub master_volume: Arc<RwLock<f32>>,
     pub sync_master_volume: Arc<RwLock<bool>>,
+    /// Fastest the synced master volume may move, in dB per second (0 = instant)
+    pub volume_slew_db_per_sec: Arc<RwLock<f32>>,
     /// Master mute state from source device
     pub master_muted: Arc<RwLock<bool>>,
+    /// Follow the source's mute even when volume sync is off
+    pub respect_system_mute: Arc<RwLock<bool>>,
+    /// Emergency mute of all output, overriding every other setting
+    pub panic_muted: Arc<RwLock<bool>>,
+    /// Silence held around a switch of the followed default device (0 = off)
+    pub device_change_mute_ms: Arc<RwLock<f32>>,
+    /// Output stays silent until then, set when the default device changes
+    pub device_change_guard: Arc<RwLock<Option<Instant>>>,
+    /// Set by the capture thread when the default device for the source role
+    /// changed; the thread exits and the router re-routes to the new device
+    pub default_device_changed: Arc<AtomicBool>,
+    /// Skip the final hard clamp (for external limiting)
+    pub disable_output_clamp: Arc<RwLock<bool>>,
+    /// Clamp both channels by the same factor to preserve the stereo image
+    pub linked_clamp: Arc<RwLock<bool>>,
+    /// Auto-reduce volume on sustained clipping
+    pub level_safety: Arc<RwLock<bool>>,
+    /// Resampler filter delay in ms (0 when not resampling)
+    pub resampler_delay_ms: Arc<RwLock<f32>>,
+    /// Resampling stage and measured source clock of the current session
+    pub resampler: Arc<RwLock<Option<ResamplerStatus>>>,
+    /// Friendly name of the endpoint the capture thread opened, which can
+    /// differ from the requested name when matching picked another device
+    pub capture_device_name: Arc<RwLock<Option<String>>>,
+    /// Detected loopback format for display (e.g. "6ch / 48000 Hz / 32-bit float")
+    pub source_format: Arc<RwLock<Option<String>>>,
+    /// Source channel positions, from the capture format's speaker mask
+    pub channel_map: Arc<RwLock<ChannelMap>>,
+    /// Policy when a configured source channel is missing from the stream
+    pub missing_channel_behavior: Arc<RwLock<MissingChannelBehavior>>,
+    /// Channel count the source should deliver, and whether to go silent when it doesn't
+    pub expected_source_channels: Arc<RwLock<Option<u16>>>,
+    pub pause_on_channel_mismatch: Arc<RwLock<bool>>,
+    /// Source delivers fewer channels than expected
+    pub channel_mismatch: Arc<AtomicBool>,
+    /// Source is silent because another app holds it in exclusive mode
+    pub source_exclusive: Arc<AtomicBool>,
+    /// Buffers with dropped or missing samples since routing started
+    pub dropouts: Arc<AtomicU64>,
+    /// Diagnostic overrides from the tray (never persisted)
+    pub diag_phase_flip_left: Arc<RwLock<bool>>,
+    pub diag_mono: Arc<RwLock<bool>>,
+    pub dsp_bypass: Arc<RwLock<bool>>,
+    /// Set by `AudioRouter::begin_preset_change`: fade out before the next
+    /// settings are applied, then back in over `preset_fade_ms`
+    pub preset_pending: Arc<AtomicBool>,
+    pub preset_fade_ms: Arc<RwLock<f32>>,
+    /// Router event subscribers (see `AudioRouter::subscribe`)
+    pub events: EventBus,
 }
 
 impl DspConfig {
     pub fn new() -> Self {
         Self {
+            input_gain_db: Arc::new(RwLock::new(0.0)),
             delay_ms: Arc::new(RwLock::new(0.0)),
             eq_enabled: Arc::new(RwLock::new(false)),
             eq_low: Arc::new(RwLock::new(0.0)),
             eq_mid: Arc::new(RwLock::new(0.0)),
             eq_high: Arc::new(RwLock::new(0.0)),
+            ms_eq_enabled: Arc::new(RwLock::new(false)),
+            ms_eq_mid: Arc::new(RwLock::new([0.0; 3])),
+            ms_eq_side: Arc::new(RwLock::new([0.0; 3])),
+            eq_shelf_slope: Arc::new(RwLock::new(1.0)),
+            eq_freqs: Arc::new(RwLock::new(ThreeBandEq::DEFAULT_FREQS)),
+            emphasis_enabled: Arc::new(RwLock::new(false)),
+            emphasis: Arc::new(RwLock::new((3200.0, 6.0))),
+            declick: Arc::new(RwLock::new(false)),
             upmix_enabled: Arc::new(RwLock::new(false)),
+            upmix_full_surround: Arc::new(RwLock::new(false)),
             upmix_strength: Arc::new(RwLock::new(0.5)),
+            upmix_ambience_ms: Arc::new(RwLock::new(10.0)),
+            meter_a_weighting: Arc::new(RwLock::new(false)),
+            meter_update_ms: Arc::new(RwLock::new(DspChain::DEFAULT_METER_UPDATE_MS)),
+            source_format_cache: Arc::new(RwLock::new(None)),
+            dry_wet: Arc::new(RwLock::new(1.0)),
+            compander_enabled: Arc::new(RwLock::new(false)),
+            subsonic_filter: Arc::new(RwLock::new(false)),
+            subsonic_cutoff_hz: Arc::new(RwLock::new(DspChain::SUBSONIC_DEFAULT_HZ)),
+            compander_times: Arc::new(RwLock::new((10.0, 300.0))),
+            agc_enabled: Arc::new(RwLock::new(false)),
+            agc_target_db: Arc::new(RwLock::new(-20.0)),
+            limiter_enabled: Arc::new(RwLock::new(false)),
+            limiter_per_channel: Arc::new(RwLock::new(false)),
+            limiter_ceiling_db: Arc::new(RwLock::new(0.0)),
+            upmix_rear_delay_ms: Arc::new(RwLock::new(0.0)),
+            per_channel_limit: Arc::new(RwLock::new(false)),
+            normalize_upmix: Arc::new(RwLock::new(true)),
+            upmix_dither: Arc::new(RwLock::new(false)),
+            upmix_dither_bits: Arc::new(RwLock::new(TpdfDither::DEFAULT_BITS)),
+            upmix_dither_shaping: Arc::new(RwLock::new(true)),
+            upmix_center_enabled: Arc::new(RwLock::new(false)),
+            upmix_center_level: Arc::new(RwLock::new(1.0)),
             shared_levels: SharedLevels::new(),
             master_volume: Arc::new(RwLock::new(1.0)),
             sync_master_volume: Arc::new(RwLock::new(true)),
+            volume_slew_db_per_sec: Arc::new(RwLock::new(60.0)),
             master_muted: Arc::new(RwLock::new(false)),
+            respect_system_mute: Arc::new(RwLock::new(true)),
+            panic_muted: Arc::new(RwLock::new(false)),
+            device_change_mute_ms: Arc::new(RwLock::new(300.0)),
+            device_change_guard: Arc::new(RwLock::new(None)),
+            default_device_changed: Arc::new(AtomicBool::new(false)),
+            disable_output_clamp: Arc::new(RwLock::new(false)),
+            linked_clamp: Arc::new(RwLock::new(false)),
+            level_safety: Arc::new(RwLock::new(false)),
+            resampler_delay_ms: Arc::new(RwLock::new(0.0)),
+            resampler: Arc::new(RwLock::new(None)),
+            capture_device_name: Arc::new(RwLock::new(None)),
+            source_format: Arc::new(RwLock::new(None)),
+            channel_map: Arc::new(RwLock::new(ChannelMap::positional(2))),
+            missing_channel_behavior: Arc::new(RwLock::new(MissingChannelBehavior::Fallback)),
+            expected_source_channels: Arc::new(RwLock::new(None)),
+            pause_on_channel_mismatch: Arc::new(RwLock::new(false)),
+            channel_mismatch: Arc::new(AtomicBool::new(false)),
+            source_exclusive: Arc::new(AtomicBool::new(false)),
+            dropouts: Arc::new(AtomicU64::new(0)),
+            diag_phase_flip_left: Arc::new(RwLock::new(false)),
+            diag_mono: Arc::new(RwLock::new(false)),
+            dsp_bypass: Arc::new(RwLock::new(false)),
+            preset_pending: Arc::new(AtomicBool::new(false)),
+            preset_fade_ms: Arc::new(RwLock::new(20.0)),
+            events: EventBus::default(),
         }
     }
 }
 
 pub struct LoopbackCapture {
     running: Arc<AtomicBool>,
+    /// Client stopped but kept initialized, see `pause`
+    paused: Arc<AtomicBool>,
+    /// Set by the capture loop on every iteration, cleared by `take_heartbeat`
+    heartbeat: Arc<AtomicBool>,
     capture_thread: Option<thread::JoinHandle<()>>,
 }
 
@@ -66,43 +310,104 @@ impl LoopbackCapture {
     pub fn new() -> Self {
         Self {
             running: Arc::new(AtomicBool::new(false)),
+            paused: Arc::new(AtomicBool::new(false)),
+            heartbeat: Arc::new(AtomicBool::new(false)),
             capture_thread: None,
         }
     }
 
+    /// Stop the WASAPI client without tearing down the thread, COM or the
+    /// device handles, so `resume` restarts capture without reinitializing
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// The thread's run flag; clearing it makes the capture loop exit on its own
+    pub fn running_flag(&self) -> Arc<AtomicBool> {
+        self.running.clone()
+    }
+
+    /// Returns whether the capture loop has run since the last call
+    pub fn take_heartbeat(&self) -> bool {
+        self.heartbeat.swap(false, Ordering::Relaxed)
+    }
+
+    /// Whether the capture thread has exited
+    pub fn is_finished(&self) -> bool {
+        self.capture_thread.as_ref().map(|h| h.is_finished()).unwrap_or(true)
+    }
+
+    /// Start the capture thread. With `source_role` set, the source is the
+    /// Windows default render device for that role rather than `device_name`.
+    /// With `direct_target` set, the thread renders to that device itself
+    /// instead of feeding `producer`, and this waits until the render client
+    /// is open so failures can fall back.
     pub fn start<P: Producer<Item = f32> + Send + 'static>(
         &mut self,
         device_name: &str,
+        source_role: Option<SourceRole>,
         target_sample_rate: u32,
+        output_channels: u16,
+        direct_target: Option<&str>,
+        resampler_quality: ResamplerQuality,
+        overrides: CaptureOverrides,
         mut producer: P,
         current_channels: Arc<AtomicU32>,
         volume: Arc<RwLock<f32>>,
         swap_channels: Arc<RwLock<bool>>,
+        swap_scope: Arc<RwLock<SwapScope>>,
         balance: Arc<RwLock<f32>>,
         left_channel: Arc<RwLock<ChannelSettings>>,
         right_channel: Arc<RwLock<ChannelSettings>>,
         dsp_config: DspConfig,
+        dsp_state: Arc<Mutex<Option<DspChain>>>,
+        preserve_dsp_state: bool,
     ) -> Result<()> {
         self.stop();
 
         let running = self.running.clone();
         running.store(true, Ordering::Relaxed);
+        let paused = self.paused.clone();
+        paused.store(false, Ordering::Relaxed);
+        let heartbeat = self.heartbeat.clone();
+        heartbeat.store(true, Ordering::Relaxed);
 
         let device_name = device_name.to_string();
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let direct = direct_target.map(|target| (target.to_string(), ready_tx));
+        let wait_for_direct = direct.is_some();
 
         let handle = thread::spawn(move || {
             if let Err(e) = capture_loop(
                 &device_name,
+                source_role,
                 target_sample_rate,
+                output_channels,
+                direct,
+                resampler_quality,
+                overrides,
                 &mut producer,
                 &running,
+                &paused,
+                &heartbeat,
                 &current_channels,
                 &volume,
                 &swap_channels,
+                &swap_scope,
                 &balance,
                 &left_channel,
                 &right_channel,
                 &dsp_config,
+                &dsp_state,
+                preserve_dsp_state,
             ) {
                 error!("Loopback capture error: {}", e);
             }
@@ -110,6 +415,15 @@ impl LoopbackCapture {
         });
 
         self.capture_thread = Some(handle);
+
+        if wait_for_direct {
+            let ready = ready_rx.recv_timeout(DIRECT_INIT_TIMEOUT)
+                .unwrap_or_else(|_| Err("capture thread did not open the target".to_string()));
+            if let Err(e) = ready {
+                self.stop();
+                anyhow::bail!(e);
+            }
+        }
         Ok(())
     }
 
@@ -121,6 +435,141 @@ impl LoopbackCapture {
     }
 }
 
+/// Whether another app holds `device` in exclusive mode: a new shared-mode
+/// client can't be initialized while it does
+fn device_in_exclusive_use(device: &IMMDevice) -> bool {
+    unsafe {
+        let Ok(client) = device.Activate::<IAudioClient>(CLSCTX_ALL, None) else {
+            return false;
+        };
+        let Ok(format_ptr) = client.GetMixFormat() else {
+            return false;
+        };
+        let result = client.Initialize(AUDCLNT_SHAREMODE_SHARED, 0, 100_000, 0, format_ptr, None);
+        CoTaskMemFree(Some(format_ptr as *const _ as *const _));
+        matches!(result, Err(e) if e.code() == AUDCLNT_E_DEVICE_IN_USE)
+    }
+}
+
+fn role_to_erole(role: SourceRole) -> ERole {
+    match role {
+        SourceRole::Media => eMultimedia,
+        SourceRole::Comms => eCommunications,
+    }
+}
+
+/// Default render endpoint for a WASAPI role
+fn default_render_device(role: SourceRole) -> Result<IMMDevice> {
+    unsafe {
+        let enumerator: IMMDeviceEnumerator = CoCreateInstance(
+            &MMDeviceEnumerator,
+            None,
+            CLSCTX_ALL,
+        )?;
+        enumerator
+            .GetDefaultAudioEndpoint(eRender, role_to_erole(role))
+            .with_context(|| format!("No default {:?} render device", role))
+    }
+}
+
+/// Friendly name of a device, as listed by cpal
+fn device_friendly_name(device: &IMMDevice) -> Option<String> {
+    unsafe {
+        let store = device.OpenPropertyStore(STGM_READ).ok()?;
+        let name = store.GetValue(&PKEY_Device_FriendlyName).ok()?;
+        Some(name.to_string())
+    }
+}
+
+/// Friendly name of the default render device for `role`
+pub fn default_render_device_name(role: SourceRole) -> Option<String> {
+    unsafe {
+        // Called from the UI thread, which cpal has already initialized as STA
+        let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+        device_friendly_name(&default_render_device(role).ok()?)
+    }
+}
+
+/// (friendly name, endpoint ID) of every active render device
+pub fn render_endpoint_ids() -> Vec<(String, String)> {
+    let mut endpoints = Vec::new();
+    unsafe {
+        let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+        let Ok(enumerator) = CoCreateInstance::<_, IMMDeviceEnumerator>(&MMDeviceEnumerator, None, CLSCTX_ALL) else {
+            return endpoints;
+        };
+        let Ok(collection) = enumerator.EnumAudioEndpoints(eRender, DEVICE_STATE_ACTIVE) else {
+            return endpoints;
+        };
+        for i in 0..collection.GetCount().unwrap_or(0) {
+            let Ok(device) = collection.Item(i) else { continue };
+            let id = device.GetId().ok().and_then(|id| id.to_string().ok());
+            if let (Some(name), Some(id)) = (device_friendly_name(&device), id) {
+                endpoints.push((name, id));
+            }
+        }
+    }
+    endpoints
+}
+
+/// Endpoint ID of a device, or "?" if it can't be read
+fn device_id(device: &IMMDevice) -> String {
+    unsafe {
+        device.GetId().ok().and_then(|id| id.to_string().ok()).unwrap_or_else(|| "?".to_string())
+    }
+}
+
+/// Debug dump of every active render endpoint, to see why a name matched the wrong one
+fn log_render_endpoints(collection: &IMMDeviceCollection) {
+    unsafe {
+        let count = collection.GetCount().unwrap_or(0);
+        debug!("{} active render endpoint(s):", count);
+        for i in 0..count {
+            let Ok(device) = collection.Item(i) else { continue };
+            let format = device.Activate::<IAudioClient>(CLSCTX_ALL, None)
+                .and_then(|client| client.GetMixFormat())
+                .map(|ptr| {
+                    let format = *ptr;
+                    CoTaskMemFree(Some(ptr as *const _ as *const _));
+                    format!("{} ch, {} Hz", format.nChannels, format.nSamplesPerSec)
+                })
+                .unwrap_or_else(|_| "format unavailable".to_string());
+            debug!(
+                "  [{}] {} | {} | {}",
+                i,
+                device_friendly_name(&device).unwrap_or_else(|| "?".to_string()),
+                device_id(&device),
+                format,
+            );
+        }
+    }
+}
+
+/// Log which endpoint was picked for `name` and by which matching rule
+fn select_device(device: IMMDevice, name: &str, rule: &str) -> Result<IMMDevice> {
+    debug!(
+        "Selected '{}' ({}) for '{}' by {}",
+        device_friendly_name(&device).unwrap_or_else(|| "?".to_string()),
+        device_id(&device),
+        name,
+        rule,
+    );
+    Ok(device)
+}
+
+/// Open a previously selected endpoint by ID, skipping the enumeration and
+/// name matching. None when it is gone or no longer active.
+fn find_device_by_id(id: &str, name: &str) -> Option<IMMDevice> {
+    unsafe {
+        let enumerator: IMMDeviceEnumerator = CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL).ok()?;
+        let device = enumerator.GetDevice(&HSTRING::from(id)).ok()?;
+        if device.GetState().ok()? != DEVICE_STATE_ACTIVE {
+            return None;
+        }
+        select_device(device, name, "cached endpoint ID").ok()
+    }
+}
+
 fn find_device_by_name(name: &str) -> Result<IMMDevice> {
     unsafe {
         let enumerator: IMMDeviceEnumerator = CoCreateInstance(
@@ -131,6 +580,9 @@ fn find_device_by_name(name: &str) -> Result<IMMDevice> {
 
         let collection = enumerator.EnumAudioEndpoints(eRender, DEVICE_STATE_ACTIVE)?;
         let count = collection.GetCount()?;
+        if tracing::enabled!(tracing::Level::DEBUG) {
+            log_render_endpoints(&collection);
+        }
         
         // Collect all device IDs and find best match
         let name_lower = name.to_lowercase();
@@ -150,7 +602,7 @@ fn find_device_by_name(name: &str) -> Result<IMMDevice> {
                     let matches = name_parts.iter().any(|part| id_lower.contains(part));
                     if matches {
                         info!("Found device: {} (ID contains match)", id);
-                        return Ok(device);
+                        return select_device(device, name, "ID keyword match");
                     }
                 }
             }
@@ -175,10 +627,10 @@ fn find_device_by_name(name: &str) -> Result<IMMDevice> {
                     
                     // If looking for Speakers with 4ch, prioritize that
                     if name.contains("4 ch") && num_channels >= 4 {
-                        return Ok(device);
+                        return select_device(device, name, "ID/name match with 4+ channels");
                     }
                     if name.contains("2 ch") && num_channels == 2 {
-                        return Ok(device);
+                        return select_device(device, name, "ID/name match with 2 channels");
                     }
                 }
             }
@@ -197,18 +649,19 @@ fn find_device_by_name(name: &str) -> Result<IMMDevice> {
                 // Match by channel count as hint
                 if name.contains("Speakers") && num_channels >= 4 {
                     info!("Found device by channel count: {} channels", num_channels);
-                    return Ok(device);
+                    return select_device(device, name, "channel count (Speakers, 4+ ch)");
                 }
                 if (name.contains("2nd") || name.contains("HD Audio 2nd")) && num_channels == 2 {
                     info!("Found 2nd output device");
-                    return Ok(device);
+                    return select_device(device, name, "channel count (2nd output, 2 ch)");
                 }
             }
         }
 
         // Last resort: return first device
         if count > 0 {
-            return Ok(collection.Item(0)?);
+            warn!("No endpoint matched '{}', falling back to the first render device", name);
+            return select_device(collection.Item(0)?, name, "fallback to index 0");
         }
 
         anyhow::bail!("Device not found: {}", name)
@@ -217,16 +670,26 @@ fn find_device_by_name(name: &str) -> Result<IMMDevice> {
 
 fn capture_loop<P: Producer<Item = f32>>(
     device_name: &str,
+    source_role: Option<SourceRole>,
     target_sample_rate: u32,
+    output_channels: u16,
+    direct: Option<(String, Sender<Result<(), String>>)>,
+    resampler_quality: ResamplerQuality,
+    overrides: CaptureOverrides,
     producer: &mut P,
     running: &AtomicBool,
+    paused: &AtomicBool,
+    heartbeat: &AtomicBool,
     current_channels: &AtomicU32,
     volume: &RwLock<f32>,
     swap_channels: &RwLock<bool>,
+    swap_scope: &RwLock<SwapScope>,
     balance: &RwLock<f32>,
     left_channel: &RwLock<ChannelSettings>,
     right_channel: &RwLock<ChannelSettings>,
     dsp_config: &DspConfig,
+    dsp_state: &Mutex<Option<DspChain>>,
+    preserve_dsp_state: bool,
 ) -> Result<()> {
     // Track buffer overflow warnings (only log once per 1000 drops)
     let mut overflow_counter: u32 = 0;
@@ -237,8 +700,34 @@ fn capture_loop<P: Producer<Item = f32>>(
             .ok()
             .context("Failed to initialize COM")?;
 
-        let device = find_device_by_name(device_name)?;
-        info!("Found loopback device: {}", device_name);
+        // The cache only covers a source selected by name; a role follows the default
+        let cached = dsp_config.source_format_cache.read().clone()
+            .filter(|cache| source_role.is_none() && cache.device == device_name);
+        let device = match source_role {
+            Some(role) => select_device(default_render_device(role)?, device_name, &format!("default {:?} role", role))?,
+            None => match cached.as_ref().and_then(|cache| find_device_by_id(&cache.endpoint_id, device_name)) {
+                Some(device) => device,
+                None => find_device_by_name(device_name)?,
+            },
+        };
+        let capture_name = device_friendly_name(&device);
+        match capture_name {
+            Some(ref actual) if source_role.is_none() && actual != device_name => {
+                warn!("Requested source '{}' matched a different endpoint, capturing '{}'", device_name, actual);
+            }
+            Some(ref actual) => info!("Found loopback device: {}", actual),
+            None => info!("Found loopback device: {} (friendly name unavailable)", device_name),
+        }
+        *dsp_config.capture_device_name.write() = capture_name;
+
+        // Following a role: watch for Windows switching the default device
+        let role_watch = match source_role {
+            Some(role) => {
+                let enumerator: IMMDeviceEnumerator = CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
+                Some((enumerator, role_to_erole(role), device_id(&device)))
+            }
+            None => None,
+        };
 
         let client: IAudioClient = device.Activate(CLSCTX_ALL, None)?;
         
@@ -249,14 +738,100 @@ fn capture_loop<P: Producer<Item = f32>>(
         // Get mix format
         let format_ptr = client.GetMixFormat()?;
         let format = *format_ptr;
-        let channels = format.nChannels;
-        let sample_rate = format.nSamplesPerSec;
         let bits_per_sample = format.wBitsPerSample;
         let block_align = format.nBlockAlign;
+        info!("Loopback format: {} ch, {} Hz, {} bits", format.nChannels, format.nSamplesPerSec, bits_per_sample);
+        if source_role.is_none() {
+            let live = SourceFormatCache {
+                device: device_name.to_string(),
+                endpoint_id: device_id(&device),
+                channels: format.nChannels,
+                sample_rate: format.nSamplesPerSec,
+                bits_per_sample,
+            };
+            if let Some(cache) = cached.as_ref().filter(|cache| **cache != live) {
+                info!("Source changed since the last start (cached {}, now {}), updating the cache", cache, live);
+            }
+            if cached.as_ref() != Some(&live) {
+                *dsp_config.source_format_cache.write() = Some(live);
+            }
+        }
+
+        // Apply advanced overrides for devices that misreport their format
+        let channels = match overrides.channels {
+            Some(ch) => {
+                warn!("OVERRIDE ACTIVE: treating capture as {} ch (device reports {} ch)", ch, format.nChannels);
+                ch
+            }
+            None => format.nChannels,
+        };
+        let sample_rate = match overrides.sample_rate {
+            Some(rate) => {
+                warn!("OVERRIDE ACTIVE: treating capture as {} Hz (device reports {} Hz)", rate, format.nSamplesPerSec);
+                rate
+            }
+            None => format.nSamplesPerSec,
+        };
+        // Auto-detect: 32-bit shared-mode mix formats are float
+        let is_float = match overrides.sample_type {
+            Some(sample_type) => {
+                warn!("OVERRIDE ACTIVE: treating capture samples as {:?}", sample_type);
+                sample_type == CaptureSampleType::Float
+            }
+            None => bits_per_sample == 32,
+        };
         
+        if channels == 0 {
+            anyhow::bail!("Source mix format reports 0 channels");
+        }
         current_channels.store(channels as u32, Ordering::Relaxed);
-        info!("Loopback format: {} ch, {} Hz, {} bits", channels, sample_rate, bits_per_sample);
-        info!("Target sample rate: {} Hz", target_sample_rate);
+
+        // Resolve named channels through the speaker mask when the format has
+        // one; some devices don't put the rears at 2/3 (e.g. LFE first)
+        const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+        let channel_mask = (format.wFormatTag == WAVE_FORMAT_EXTENSIBLE && format.cbSize >= 22)
+            .then(|| ptr::read_unaligned(format_ptr as *const WAVEFORMATEXTENSIBLE).dwChannelMask);
+        let channel_map = match channel_mask.and_then(|mask| ChannelMap::from_mask(mask, channels).map(|map| (mask, map))) {
+            Some((mask, map)) => {
+                info!("Channel mask 0x{:X}: {}", mask, map);
+                map
+            }
+            None => {
+                let map = ChannelMap::positional(channels);
+                info!("No usable channel mask, assuming positional layout: {}", map);
+                map
+            }
+        };
+        *dsp_config.channel_map.write() = channel_map;
+        let sample_kind = if is_float { "float" } else { "PCM" };
+        *dsp_config.source_format.write() = Some(format!(
+            "{}ch / {} Hz / {}-bit {}", channels, sample_rate, bits_per_sample, sample_kind
+        ));
+        info!("Target sample rate: {} Hz, {} output channels", target_sample_rate, output_channels);
+
+        // Windows can silently renegotiate a surround source down to stereo
+        let expected_channels = *dsp_config.expected_source_channels.read();
+        let mismatch = expected_channels.is_some_and(|expected| channels < expected);
+        dsp_config.channel_mismatch.store(mismatch, Ordering::Relaxed);
+        if let (true, Some(expected)) = (mismatch, expected_channels) {
+            warn!(
+                "Source delivers {} ch but {} ch are expected (the format may have been renegotiated){}",
+                channels, expected,
+                if *dsp_config.pause_on_channel_mismatch.read() { ", routing paused" } else { "" }
+            );
+            dsp_config.events.emit(RouterEvent::ChannelMismatch { expected, actual: channels });
+        }
+        // process_channels emits the routed pair and interleave_surround adds
+        // the fronts (and centre/LFE), so the stream must be 2, 4 or 6 wide
+        if !matches!(output_channels, 2 | 4 | 6) {
+            anyhow::bail!("Unsupported output channel count {} (expected 2, 4 or 6)", output_channels);
+        }
+        let surround = output_channels >= 4;
+        if surround && channels < 4 {
+            info!("{}ch output from a {}ch source: rear pair uses upmix", output_channels, channels);
+        } else if *dsp_config.upmix_full_surround.read() {
+            warn!("Full surround upmix needs surround output from a stereo source, adding to the routed channels instead");
+        }
 
         // Initialize for loopback capture
         // AUDCLNT_STREAMFLAGS_LOOPBACK = 0x00020000
@@ -264,7 +839,7 @@ fn capture_loop<P: Producer<Item = f32>>(
         const AUDCLNT_STREAMFLAGS_EVENTCALLBACK: u32 = 0x00040000;
         
         // 20ms buffer for low latency (200000 * 100ns = 20ms)
-        let buffer_duration = 200_000i64;
+        let buffer_duration = (CAPTURE_BUFFER_MS * 10_000.0) as i64;
         
         client.Initialize(
             AUDCLNT_SHAREMODE_SHARED,
@@ -283,70 +858,201 @@ fn capture_loop<P: Producer<Item = f32>>(
 
         // Initialize resampler if sample rates differ
         let needs_resample = sample_rate != target_sample_rate;
-        let mut resampler: Option<SincFixedIn<f32>> = if needs_resample {
-            let params = SincInterpolationParameters {
-                sinc_len: 256,
-                f_cutoff: 0.95,
-                interpolation: SincInterpolationType::Linear,
-                oversampling_factor: 256,
-                window: WindowFunction::BlackmanHarris2,
-            };
+        let mut resampler: Option<Box<dyn VecResampler<f32>>> = if needs_resample {
             let resample_ratio = target_sample_rate as f64 / sample_rate as f64;
-            info!("Resampler initialized: {} Hz -> {} Hz (ratio: {:.4})", sample_rate, target_sample_rate, resample_ratio);
-            Some(SincFixedIn::<f32>::new(
-                resample_ratio,
-                2.0,  // max relative ratio
-                params,
-                1024, // chunk size
-                2,    // 2 channels (stereo output)
-            )?)
+            info!(
+                "Resampler initialized: {} Hz -> {} Hz (ratio: {:.4}, quality: {:?} - {})",
+                sample_rate, target_sample_rate, resample_ratio,
+                resampler_quality, resampler_quality.description()
+            );
+            Some(build_resampler(resampler_quality, resample_ratio, output_channels as usize)?)
         } else {
             None
         };
+        *dsp_config.resampler_delay_ms.write() = resampler.as_ref()
+            .map(|rs| rs.output_delay() as f32 * 1000.0 / target_sample_rate as f32)
+            .unwrap_or(0.0);
+        *dsp_config.resampler.write() = Some(ResamplerStatus {
+            source_rate: sample_rate,
+            target_rate: target_sample_rate,
+            ratio: needs_resample.then(|| target_sample_rate as f64 / sample_rate as f64),
+            source_drift_ppm: None,
+        });
+        let mut clock = ClockMonitor::new(sample_rate);
+        let mut clock_warned = false;
 
         // Buffers for resampling
-        let mut resample_input: Vec<Vec<f32>> = vec![Vec::new(); 2];
+        let out_ch = output_channels as usize;
+
+        // Direct mode: render from this thread on the capture clock
+        let direct_render = match direct {
+            Some((target, ready)) => {
+                let opened = if resampler.is_some() {
+                    Err(anyhow::anyhow!("source and target sample rates differ"))
+                } else {
+                    DirectRender::open(&target, sample_rate, out_ch)
+                };
+                match opened {
+                    Ok(render) => {
+                        let _ = ready.send(Ok(()));
+                        Some(render)
+                    }
+                    Err(e) => {
+                        let _ = ready.send(Err(e.to_string()));
+                        return Err(e);
+                    }
+                }
+            }
+            None => None,
+        };
+        let mut resample_input: Vec<Vec<f32>> = vec![Vec::new(); out_ch];
+
+        // Reuse the router-owned DSP chain so filter and delay state carry over
+        // a restart; rebuild it when the rate changed or preservation is off.
+        // The chain is taken out of the slot rather than locked for the whole
+        // session, so a stalled previous thread can't block this one.
+        let mut dsp_lease = DspLease::take(dsp_state);
+        if !preserve_dsp_state || dsp_lease.chain.as_ref().is_none_or(|c| c.sample_rate() != target_sample_rate) {
+            dsp_lease.chain = Some(DspChain::new(target_sample_rate, dsp_config.shared_levels.clone()));
+        } else {
+            info!("Reusing DSP state from previous capture session");
+        }
+        let dsp_chain = dsp_lease.chain.as_mut().expect("DSP chain initialized above");
+        
+        // Carries partial frames between buffers so channels never drift
+        let mut frame_aligner = FrameAligner::new(channels as usize);
 
-        // Initialize DSP chain
-        let mut dsp_chain = DspChain::new(target_sample_rate, dsp_config.shared_levels.clone());
+        // Per-channel mute ramps (~15ms) to avoid clicks when toggling mutes
+        let mute_ramp_samples = (sample_rate as f32 * 0.015) as usize;
+        let initial_left = if left_channel.read().muted { 0.0 } else { 1.0 };
+        let initial_right = if right_channel.read().muted { 0.0 } else { 1.0 };
+        let mut left_mute_gain = SmoothedValue::new(initial_left, mute_ramp_samples);
+        let mut right_mute_gain = SmoothedValue::new(initial_right, mute_ramp_samples);
         
+        // Log a missing source channel once per source/policy change
+        let mut missing_warned: Option<(ChannelSource, ChannelSource, MissingChannelBehavior)> = None;
+
         // Counter for master volume updates (every ~100ms instead of every loop)
         let mut master_vol_counter: u32 = 0;
+        // Last synced master volume update; the first one applies without a ramp
+        let mut master_vol_synced: Option<Instant> = None;
+
+        // Next exclusive-mode probe during silence (None once one found it
+        // held), and the back-off after probes that found plain silence.
+        // Both reset when the source is audible again.
+        let mut next_exclusive_probe = Some(Instant::now() + EXCLUSIVE_PROBE_INTERVAL);
+        let mut exclusive_probe_backoff = EXCLUSIVE_PROBE_INTERVAL;
+
+        // Settings (and volume) stay frozen until a preset fade-out reaches silence.
+        // A new session applies everything directly.
+        let mut preset_hold: Option<Instant> = None;
+        let mut vol = *volume.read();
+        dsp_config.preset_pending.store(false, Ordering::Relaxed);
+        dsp_chain.preset_fade.set_target(1.0);
 
         client.Start()?;
+        let mut capturing = true;
         info!("Loopback capture started");
 
         while running.load(Ordering::Relaxed) {
-            // Update DSP settings from config
-            let delay = *dsp_config.delay_ms.read();
-            if (delay - dsp_chain.delay_ms).abs() > 0.1 {
-                dsp_chain.set_delay_ms(delay);
+            heartbeat.store(true, Ordering::Relaxed);
+
+            // Warm pause: the client stays initialized, only the stream stops.
+            // Reset drops what was captured so a resume doesn't replay it.
+            if paused.load(Ordering::Relaxed) {
+                if capturing {
+                    client.Stop()?;
+                    client.Reset()?;
+                    capturing = false;
+                    info!("Loopback capture paused");
+                }
+                thread::sleep(PAUSE_POLL_INTERVAL);
+                continue;
             }
-            dsp_chain.eq_enabled = *dsp_config.eq_enabled.read();
-            if dsp_chain.eq_enabled {
-                dsp_chain.set_eq(
-                    *dsp_config.eq_low.read(),
-                    *dsp_config.eq_mid.read(),
-                    *dsp_config.eq_high.read(),
-                );
+            if !capturing {
+                client.Start()?;
+                capturing = true;
+                info!("Loopback capture resumed");
+            }
+
+            // Preset switches fade out, swap every setting at silence and fade back in
+            if dsp_config.preset_pending.swap(false, Ordering::Relaxed) {
+                let fade_ms = *dsp_config.preset_fade_ms.read();
+                if fade_ms > 0.0 {
+                    dsp_chain.preset_fade.set_ramp_samples((target_sample_rate as f32 * fade_ms / 1000.0) as usize);
+                    dsp_chain.preset_fade.set_target(0.0);
+                    // Loopback delivers no buffers while the source is idle, so don't wait forever
+                    preset_hold = Some(Instant::now() + Duration::from_secs_f32(fade_ms / 1000.0) + PRESET_HOLD_SLACK);
+                }
             }
-            dsp_chain.upmix_enabled = *dsp_config.upmix_enabled.read();
-            dsp_chain.upmixer.set_strength(*dsp_config.upmix_strength.read());
+            if preset_hold.is_some_and(|deadline| dsp_chain.preset_fade.value() == 0.0 || Instant::now() >= deadline) {
+                preset_hold = None;
+                dsp_chain.preset_fade.set_target(1.0);
+            }
+            if preset_hold.is_none() {
+                apply_dsp_config(dsp_chain, dsp_config, surround, channels);
+                vol = *volume.read();
+            }
+            dsp_chain.panic_muted = *dsp_config.panic_muted.read();
             
             // Update master volume and mute state from source device (every ~100ms)
             master_vol_counter += 1;
             if master_vol_counter >= 5 {  // ~100ms at 20ms buffer
                 master_vol_counter = 0;
                 let sync_master = *dsp_config.sync_master_volume.read();
-                if sync_master {
-                    if let Some(ref ep_vol) = endpoint_volume {
+                let follow_mute = sync_master || *dsp_config.respect_system_mute.read();
+                // Mute at once and hand over to the router, which re-routes to the new default
+                let default_changed = role_watch.as_ref().is_some_and(|(enumerator, role, id)| {
+                    enumerator.GetDefaultAudioEndpoint(eRender, *role).is_ok_and(|d| device_id(&d) != *id)
+                });
+                if default_changed {
+                    let guard_ms = *dsp_config.device_change_mute_ms.read();
+                    if guard_ms > 0.0 {
+                        *dsp_config.device_change_guard.write() = Some(Instant::now() + Duration::from_secs_f32(guard_ms / 1000.0));
+                    }
+                    info!("Default {:?} device changed, leaving '{}'", source_role, device_name);
+                    dsp_config.default_device_changed.store(true, Ordering::Relaxed);
+                    break;
+                }
+                if let (true, Some(ep_vol)) = (follow_mute, endpoint_volume.as_ref()) {
+                    if sync_master {
                         if let Ok(master_vol) = ep_vol.GetMasterVolumeLevelScalar() {
-                            *dsp_config.master_volume.write() = master_vol;
-                        }
-                        if let Ok(muted) = ep_vol.GetMute() {
-                            *dsp_config.master_muted.write() = muted.as_bool();
+                            // Slew-limit large jumps (e.g. a held media key) so they ramp instead of thumping
+                            let now = Instant::now();
+                            let mut synced = dsp_config.master_volume.write();
+                            *synced = match master_vol_synced {
+                                Some(last) => {
+                                    let max_step_db = *dsp_config.volume_slew_db_per_sec.read() * (now - last).as_secs_f32();
+                                    slew_gain(*synced, master_vol, max_step_db)
+                                }
+                                None => master_vol,
+                            };
+                            master_vol_synced = Some(now);
                         }
                     }
+                    if let Ok(muted) = ep_vol.GetMute() {
+                        *dsp_config.master_muted.write() = muted.as_bool();
+                    }
+                }
+            }
+
+            // Loopback only sees shared-mode streams: a game holding the source
+            // in exclusive mode shows up here as sustained silence. Probing opens
+            // a client, so an idle source is probed with a growing back-off.
+            if next_exclusive_probe.is_some_and(|at| Instant::now() >= at) {
+                if device_in_exclusive_use(&device) {
+                    next_exclusive_probe = None;
+                    if !dsp_config.source_exclusive.swap(true, Ordering::Relaxed) {
+                        warn!(
+                            "Source '{}' is in exclusive use by another application. WASAPI loopback cannot \
+                             capture exclusive-mode streams, so routing is silent until it is released",
+                            device_name
+                        );
+                        dsp_config.events.emit(RouterEvent::SourceExclusive);
+                    }
+                } else {
+                    exclusive_probe_backoff = (exclusive_probe_backoff * 2).min(EXCLUSIVE_PROBE_MAX_INTERVAL);
+                    next_exclusive_probe = Some(Instant::now() + exclusive_probe_backoff);
                 }
             }
 
@@ -360,28 +1066,63 @@ fn capture_loop<P: Producer<Item = f32>>(
                 let mut buffer_ptr: *mut u8 = ptr::null_mut();
                 let mut frames_available: u32 = 0;
                 let mut flags: u32 = 0;
+                let mut qpc_position: u64 = 0;
 
                 let hr = capture_client.GetBuffer(
                     &mut buffer_ptr,
                     &mut frames_available,
                     &mut flags,
                     None,
-                    None,
+                    Some(&mut qpc_position),
                 );
 
                 if hr.is_err() || frames_available == 0 {
                     break;
                 }
 
+                // A source clock far off its nominal rate (or a wrong rate
+                // override) makes the resampled output off-pitch
+                let discontinuity = flags & AUDCLNT_BUFFERFLAGS_DATA_DISCONTINUITY.0 as u32 != 0;
+                if let Some(ppm) = clock.packet(qpc_position, frames_available, discontinuity) {
+                    if let Some(status) = dsp_config.resampler.write().as_mut() {
+                        status.source_drift_ppm = Some(ppm);
+                    }
+                    let off = ppm.abs() > CLOCK_DRIFT_WARN_PPM;
+                    if off && !clock_warned {
+                        warn!(
+                            "Source delivers {:.0} Hz against a nominal {} Hz ({:+.0} ppm): clock problem or wrong sample rate override, expect off-pitch audio",
+                            sample_rate as f64 * (1.0 + ppm / 1e6), sample_rate, ppm
+                        );
+                    } else if !off && clock_warned {
+                        info!("Source clock back within bounds ({:+.0} ppm)", ppm);
+                    }
+                    clock_warned = off;
+                }
+
                 // Process audio data
-                let vol = *volume.read();
-                let swap = *swap_channels.read();
+                let overflow_before = overflow_counter;
+                let swap = *swap_channels.read() && swap_scope.read().applies_to_routing();
                 let bal = *balance.read();
                 let left_ch = left_channel.read().clone();
                 let right_ch = right_channel.read().clone();
                 let master_vol = *dsp_config.master_volume.read();
                 let master_muted = *dsp_config.master_muted.read();
                 let sync_master = *dsp_config.sync_master_volume.read();
+                let missing = *dsp_config.missing_channel_behavior.read();
+
+                let key = (left_ch.source, right_ch.source, missing);
+                if missing != MissingChannelBehavior::Fallback && missing_warned != Some(key) {
+                    for source in [left_ch.source, right_ch.source] {
+                        if !channel_map.present(source) {
+                            match missing {
+                                MissingChannelBehavior::Mute => warn!("{:?} not present in {}ch source, muting that output", source, channels),
+                                MissingChannelBehavior::Passthrough => warn!("{:?} not present in {}ch source, passing stereo through", source, channels),
+                                _ => warn!("{:?} not present in {}ch source, playing front channel instead", source, channels),
+                            }
+                        }
+                    }
+                    missing_warned = Some(key);
+                }
 
                 // Convert buffer to f32 samples
                 let bytes_per_sample = (bits_per_sample / 8) as usize;
@@ -390,22 +1131,52 @@ fn capture_loop<P: Producer<Item = f32>>(
                     frames_available as usize * block_align as usize,
                 );
 
-                let samples = bytes_to_f32(data_slice, bytes_per_sample);
-                // Apply master volume and mute if sync enabled
-                let effective_vol = if sync_master {
-                    if master_muted { 0.0 } else { vol * master_vol }
+                let mut samples = frame_aligner.push(&bytes_to_f32(data_slice, bytes_per_sample, is_float));
+                let silent_flag = flags & AUDCLNT_BUFFERFLAGS_SILENT.0 as u32 != 0;
+                if !silent_flag && samples.iter().any(|s| s.abs() > 1e-6) {
+                    next_exclusive_probe = Some(Instant::now() + EXCLUSIVE_PROBE_INTERVAL);
+                    exclusive_probe_backoff = EXCLUSIVE_PROBE_INTERVAL;
+                    if dsp_config.source_exclusive.swap(false, Ordering::Relaxed) {
+                        info!("Source audio resumed");
+                    }
+                }
+                // Input gain: gain staging on the raw capture, ahead of everything else
+                let input_gain = db_to_linear(*dsp_config.input_gain_db.read());
+                if input_gain != 1.0 {
+                    samples.iter_mut().for_each(|s| *s *= input_gain);
+                }
+                // Apply master mute (synced or respected) and master volume if sync enabled
+                let follow_mute = sync_master || *dsp_config.respect_system_mute.read();
+                let paused = mismatch && *dsp_config.pause_on_channel_mismatch.read();
+                let guarded = dsp_config.device_change_guard.read().is_some_and(|until| Instant::now() < until);
+                let effective_vol = if dsp_chain.panic_muted || paused || guarded || (master_muted && follow_mute) {
+                    0.0
+                } else if sync_master {
+                    vol * master_vol
                 } else { 
                     vol 
                 };
-                let stereo_output = process_channels(&samples, channels, effective_vol, swap, bal, &left_ch, &right_ch, &mut dsp_chain);
+                left_mute_gain.set_target(if left_ch.muted { 0.0 } else { 1.0 });
+                right_mute_gain.set_target(if right_ch.muted { 0.0 } else { 1.0 });
+                // Full surround: the rear pair carries only the upmix
+                let rear_upmix_only = surround && channels < 4 && *dsp_config.upmix_full_surround.read();
+                let mut output = process_channels(
+                    &samples, channels, &channel_map, effective_vol, swap, bal,
+                    &left_ch, &right_ch, missing, rear_upmix_only,
+                    &mut left_mute_gain, &mut right_mute_gain,
+                    dsp_chain,
+                );
+                if surround {
+                    output = interleave_surround(&samples, channels, &channel_map, effective_vol, bal, &output, out_ch, dsp_chain);
+                }
 
                 // Apply resampling if needed
                 if let Some(ref mut rs) = resampler {
-                    // Split stereo into separate channels
-                    for frame in stereo_output.chunks(2) {
-                        if frame.len() == 2 {
-                            resample_input[0].push(frame[0]);
-                            resample_input[1].push(frame[1]);
+                    debug_assert_eq!(output.len() % out_ch, 0, "routed output is not {}-channel", out_ch);
+                    // Split interleaved output into separate channels
+                    for frame in output.chunks_exact(out_ch) {
+                        for (buf, &sample) in resample_input.iter_mut().zip(frame) {
+                            buf.push(sample);
                         }
                     }
 
@@ -413,49 +1184,65 @@ fn capture_loop<P: Producer<Item = f32>>(
                     let chunk_size = rs.input_frames_next();
                     while resample_input[0].len() >= chunk_size {
                         // Take chunk_size samples from each channel
-                        let left_chunk: Vec<f32> = resample_input[0].drain(..chunk_size).collect();
-                        let right_chunk: Vec<f32> = resample_input[1].drain(..chunk_size).collect();
-                        
-                        let input_chunk = vec![left_chunk, right_chunk];
+                        let input_chunk: Vec<Vec<f32>> = resample_input.iter_mut()
+                            .map(|buf| buf.drain(..chunk_size).collect())
+                            .collect();
                         
                         if let Ok(resampled) = rs.process(&input_chunk, None) {
                             // Apply DSP and push to producer
-                            let frames = resampled[0].len();
-                            for i in 0..frames {
-                                let (l, r) = dsp_chain.process(resampled[0][i], resampled[1][i]);
-                                if producer.try_push(l).is_err() {
-                                    overflow_counter += 1;
-                                    if overflow_counter == 1 || overflow_counter % 10000 == 0 {
-                                        warn!("Buffer overflow: {} samples dropped (output not consuming fast enough)", overflow_counter);
-                                    }
-                                }
-                                if producer.try_push(r).is_err() {
-                                    overflow_counter += 1;
+                            let mut frame = vec![0.0; out_ch];
+                            for i in 0..resampled[0].len() {
+                                for (ch, sample) in frame.iter_mut().enumerate() {
+                                    *sample = resampled[ch][i];
                                 }
+                                push_frame(producer, dsp_chain, &mut frame, &mut overflow_counter);
                             }
                         }
                     }
+                } else if let Some(ref render) = direct_render {
+                    // Direct mode: apply DSP and render without the ring buffer
+                    for frame in output.chunks_exact_mut(out_ch) {
+                        process_routed_pair(dsp_chain, frame);
+                    }
+                    let dropped = render.write(&output, out_ch)?;
+                    if dropped > 0 {
+                        let before = overflow_counter;
+                        overflow_counter += dropped;
+                        if before == 0 || before / 10000 != overflow_counter / 10000 {
+                            warn!("Direct render overflow: {} frames dropped", overflow_counter);
+                        }
+                    }
                 } else {
                     // No resampling needed, apply DSP and push directly
-                    for frame in stereo_output.chunks(2) {
-                        if frame.len() == 2 {
-                            let (l, r) = dsp_chain.process(frame[0], frame[1]);
-                            if producer.try_push(l).is_err() {
-                                overflow_counter += 1;
-                                if overflow_counter == 1 || overflow_counter % 10000 == 0 {
-                                    warn!("Buffer overflow: {} samples dropped", overflow_counter);
-                                }
-                            }
-                            let _ = producer.try_push(r);
-                        }
+                    for frame in output.chunks_exact_mut(out_ch) {
+                        push_frame(producer, dsp_chain, frame, &mut overflow_counter);
                     }
                 }
 
+                if overflow_counter != overflow_before {
+                    dsp_config.dropouts.fetch_add(1, Ordering::Relaxed);
+                }
+
                 capture_client.ReleaseBuffer(frames_available)?;
             }
+
+            // Runaway level protection
+            if dsp_chain.clip_watchdog.take_triggered() {
+                dsp_config.events.emit(RouterEvent::ClipDetected);
+                if *dsp_config.level_safety.read() {
+                    // The owner of the volume setting applies it, so the UI
+                    // and saved config follow instead of being overwritten later
+                    dsp_config.events.emit(RouterEvent::LevelSafetyReduction { db: LEVEL_SAFETY_STEP_DB });
+                    warn!("Sustained clipping detected, requesting a {} dB volume reduction", -LEVEL_SAFETY_STEP_DB);
+                } else {
+                    warn!("Sustained clipping detected, consider lowering volume or upmix strength");
+                }
+            }
         }
 
-        client.Stop()?;
+        if capturing {
+            client.Stop()?;
+        }
         let _ = windows::Win32::Foundation::CloseHandle(event);
         CoTaskMemFree(Some(format_ptr as *const _ as *const _));
         CoUninitialize();
@@ -464,14 +1251,350 @@ fn capture_loop<P: Producer<Item = f32>>(
     }
 }
 
-fn bytes_to_f32(data: &[u8], bytes_per_sample: usize) -> Vec<f32> {
+/// The capture session's DSP chain, taken out of the router's slot at start
+/// and put back when the session ends, on any exit path
+struct DspLease<'a> {
+    slot: &'a Mutex<Option<DspChain>>,
+    chain: Option<DspChain>,
+}
+
+impl<'a> DspLease<'a> {
+    fn take(slot: &'a Mutex<Option<DspChain>>) -> Self {
+        let chain = slot.lock().take();
+        Self { slot, chain }
+    }
+}
+
+impl Drop for DspLease<'_> {
+    fn drop(&mut self) {
+        if let Some(chain) = self.chain.take() {
+            *self.slot.lock() = Some(chain);
+        }
+    }
+}
+
+/// Copy the live DSP settings into the chain (once per capture loop iteration)
+fn apply_dsp_config(dsp_chain: &mut DspChain, dsp_config: &DspConfig, surround: bool, channels: u16) {
+    let delay = *dsp_config.delay_ms.read();
+    if (delay - dsp_chain.delay_ms).abs() > 0.1 {
+        dsp_chain.set_delay_ms(delay);
+    }
+    dsp_chain.eq_enabled = *dsp_config.eq_enabled.read();
+    if dsp_chain.eq_enabled {
+        dsp_chain.set_eq(
+            *dsp_config.eq_low.read(),
+            *dsp_config.eq_mid.read(),
+            *dsp_config.eq_high.read(),
+        );
+    }
+    dsp_chain.set_eq_shelf_slope(*dsp_config.eq_shelf_slope.read());
+    dsp_chain.set_eq_freqs(*dsp_config.eq_freqs.read());
+    dsp_chain.ms_eq_enabled = *dsp_config.ms_eq_enabled.read();
+    if dsp_chain.ms_eq_enabled {
+        dsp_chain.set_ms_eq(*dsp_config.ms_eq_mid.read(), *dsp_config.ms_eq_side.read());
+    }
+    dsp_chain.emphasis_enabled = *dsp_config.emphasis_enabled.read();
+    if dsp_chain.emphasis_enabled {
+        let (freq, gain_db) = *dsp_config.emphasis.read();
+        dsp_chain.emphasis.set(freq, gain_db);
+    }
+    dsp_chain.declick_enabled = *dsp_config.declick.read();
+    // Surround output uses real rear channels when present and upmix only as a fallback
+    dsp_chain.upmix_enabled = if surround { channels < 4 } else { *dsp_config.upmix_enabled.read() };
+    dsp_chain.upmix_center_enabled = *dsp_config.upmix_center_enabled.read();
+    dsp_chain.upmix_center_level = *dsp_config.upmix_center_level.read();
+    dsp_chain.upmixer.set_strength(*dsp_config.upmix_strength.read());
+    dsp_chain.upmixer.set_ambience_ms(*dsp_config.upmix_ambience_ms.read());
+    dsp_chain.upmixer.set_rear_delay_ms(*dsp_config.upmix_rear_delay_ms.read());
+    dsp_chain.per_channel_limit = *dsp_config.per_channel_limit.read();
+    dsp_chain.normalize_upmix = *dsp_config.normalize_upmix.read();
+    dsp_chain.upmix_dither = *dsp_config.upmix_dither.read();
+    let (bits, shaping) = (*dsp_config.upmix_dither_bits.read(), *dsp_config.upmix_dither_shaping.read());
+    for dither in [&mut dsp_chain.upmix_dither_l, &mut dsp_chain.upmix_dither_r] {
+        dither.set_bits(bits);
+        dither.noise_shaping = shaping;
+    }
+    dsp_chain.output_clamp = !*dsp_config.disable_output_clamp.read();
+    dsp_chain.linked_clamp = *dsp_config.linked_clamp.read();
+    dsp_chain.dry_wet = *dsp_config.dry_wet.read();
+    dsp_chain.compander_enabled = *dsp_config.compander_enabled.read();
+    if dsp_chain.compander_enabled {
+        let (attack_ms, release_ms) = *dsp_config.compander_times.read();
+        dsp_chain.compander.set_times(attack_ms, release_ms);
+    }
+    dsp_chain.subsonic_enabled = *dsp_config.subsonic_filter.read();
+    dsp_chain.set_subsonic_cutoff(*dsp_config.subsonic_cutoff_hz.read());
+    dsp_chain.agc_enabled = *dsp_config.agc_enabled.read();
+    dsp_chain.agc.set_target_db(*dsp_config.agc_target_db.read());
+    dsp_chain.limiter_enabled = *dsp_config.limiter_enabled.read();
+    dsp_chain.limiter_per_channel = *dsp_config.limiter_per_channel.read();
+    dsp_chain.set_limiter_ceiling_db(*dsp_config.limiter_ceiling_db.read());
+    dsp_chain.set_meter_a_weighting(*dsp_config.meter_a_weighting.read());
+    dsp_chain.set_meter_update_ms(*dsp_config.meter_update_ms.read());
+    dsp_chain.phase_flip_left = *dsp_config.diag_phase_flip_left.read();
+    dsp_chain.mono = *dsp_config.diag_mono.read();
+    dsp_chain.bypass = *dsp_config.dsp_bypass.read();
+}
+
+/// Test signal of `analyze_chain`
+const ANALYSIS_FREQ_HZ: f32 = 1000.0;
+const ANALYSIS_LEVEL_DB: f32 = -20.0;
+/// Signal run through the chain before measuring, so filters, delay and
+/// the leveler settle
+const ANALYSIS_SETTLE_SECS: f32 = 1.0;
+const ANALYSIS_MEASURE_SECS: f32 = 0.2;
+
+/// Output level per channel for a sine fed to every source channel
+#[derive(Debug, Clone)]
+pub struct ChainAnalysis {
+    pub input_db: f32,
+    /// Sine peak level in dBFS, in output channel order
+    pub output_db: Vec<f32>,
+}
+
+impl std::fmt::Display for ChainAnalysis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names = output_channel_names(self.output_db.len());
+        write!(f, "{}kHz @ {}dBFS → ", ANALYSIS_FREQ_HZ / 1000.0, self.input_db)?;
+        for (i, db) in self.output_db.iter().enumerate() {
+            let sep = if i == 0 { "" } else { ", " };
+            let name = names.get(i).map_or_else(|| format!("{}", i + 1), |n| n.to_string());
+            write!(f, "{}{} {:.1} dBFS", sep, name, db)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run a -20 dBFS 1 kHz sine through a fresh DSP chain with the current
+/// settings and routing, offline, and measure what comes out. The capture
+/// session is untouched; its meters and DSP state are not used.
+#[allow(clippy::too_many_arguments)]
+pub fn analyze_chain(
+    dsp_config: &DspConfig,
+    sample_rate: u32,
+    output_channels: u16,
+    volume: f32,
+    swap: bool,
+    balance: f32,
+    left_ch: &ChannelSettings,
+    right_ch: &ChannelSettings,
+) -> ChainAnalysis {
+    let map = *dsp_config.channel_map.read();
+    let channels = map.channels();
+    let out_ch = if matches!(output_channels, 4 | 6) { output_channels as usize } else { 2 };
+    let surround = out_ch >= 4;
+
+    let mut dsp = DspChain::new(sample_rate, SharedLevels::new());
+    apply_dsp_config(&mut dsp, dsp_config, surround, channels);
+    let missing = *dsp_config.missing_channel_behavior.read();
+    let upmix_only = surround && channels < 4 && *dsp_config.upmix_full_surround.read();
+    let mut left_mute = SmoothedValue::new(if left_ch.muted { 0.0 } else { 1.0 }, 1);
+    let mut right_mute = SmoothedValue::new(if right_ch.muted { 0.0 } else { 1.0 }, 1);
+
+    let amplitude = db_to_linear(ANALYSIS_LEVEL_DB);
+    let step = 2.0 * std::f64::consts::PI * ANALYSIS_FREQ_HZ as f64 / sample_rate as f64;
+    let settle = (sample_rate as f32 * ANALYSIS_SETTLE_SECS) as usize;
+    let measure = (sample_rate as f32 * ANALYSIS_MEASURE_SECS) as usize;
+    let block = 480;
+    let mut sum_sq = vec![0.0f64; out_ch];
+    let mut measured = 0usize;
+
+    let mut n = 0;
+    while n < settle + measure {
+        let input: Vec<f32> = (n..n + block)
+            .flat_map(|i| std::iter::repeat_n(amplitude * (step * i as f64).sin() as f32, channels as usize))
+            .collect();
+        let mut output = process_channels(
+            &input, channels, &map, volume, swap, balance, left_ch, right_ch, missing, upmix_only,
+            &mut left_mute, &mut right_mute, &mut dsp,
+        );
+        if surround {
+            output = interleave_surround(&input, channels, &map, volume, balance, &output, out_ch, &mut dsp);
+        }
+        for (i, frame) in output.chunks_exact_mut(out_ch).enumerate() {
+            process_routed_pair(&mut dsp, frame);
+            if n + i >= settle {
+                measured += 1;
+                for (acc, &sample) in sum_sq.iter_mut().zip(frame.iter()) {
+                    *acc += (sample as f64).powi(2);
+                }
+            }
+        }
+        n += block;
+    }
+
+    // RMS of a sine is its peak -3.01 dB; report the peak so unity reads as the input level
+    let output_db = sum_sq.iter()
+        .map(|&sq| (20.0 * ((sq / measured as f64).sqrt() * std::f64::consts::SQRT_2).log10()) as f32)
+        .collect();
+    ChainAnalysis { input_db: ANALYSIS_LEVEL_DB, output_db }
+}
+
+/// Run the routed pair (the last two channels of the frame) through the DSP
+/// chain, then meter the whole output frame. Panic mute silences the whole
+/// frame here, after any delay/DSP tail, and the preset fade scales it.
+fn process_routed_pair(dsp: &mut DspChain, frame: &mut [f32]) {
+    let n = frame.len();
+    let (l, r) = dsp.process(frame[n - 2], frame[n - 1]);
+    frame[n - 2] = l;
+    frame[n - 1] = r;
+    // The one clamp in the chain: everything upstream stays unclamped f32
+    for pair in frame.chunks_exact_mut(2) {
+        (pair[0], pair[1]) = dsp.clamp_output(pair[0], pair[1]);
+    }
+    if dsp.panic_muted {
+        frame.fill(0.0);
+    }
+    let fade = dsp.preset_fade.next();
+    if fade < 1.0 {
+        frame.iter_mut().for_each(|s| *s *= fade);
+    }
+    dsp.meter_output(frame);
+}
+
+/// Process the routed pair and push the whole frame to the ring buffer
+fn push_frame<P: Producer<Item = f32>>(
+    producer: &mut P,
+    dsp: &mut DspChain,
+    frame: &mut [f32],
+    overflow_counter: &mut u32,
+) {
+    process_routed_pair(dsp, frame);
+    for &sample in frame.iter() {
+        if producer.try_push(sample).is_err() {
+            *overflow_counter += 1;
+            if *overflow_counter == 1 || *overflow_counter % 10000 == 0 {
+                warn!("Buffer overflow: {} samples dropped (output not consuming fast enough)", overflow_counter);
+            }
+        }
+    }
+}
+
+/// Shared-mode WASAPI render client driven from the capture thread (direct mode)
+struct DirectRender {
+    client: IAudioClient,
+    render_client: IAudioRenderClient,
+    channels: usize,
+    buffer_frames: u32,
+}
+
+impl DirectRender {
+    /// Open `target` for rendering. Its mix format must be 32-bit float at
+    /// `sample_rate` with at least `out_ch` channels.
+    fn open(target: &str, sample_rate: u32, out_ch: usize) -> Result<Self> {
+        unsafe {
+            let device = find_device_by_name(target)?;
+            let client: IAudioClient = device.Activate(CLSCTX_ALL, None)?;
+            let format_ptr = client.GetMixFormat()?;
+            let format = *format_ptr;
+            if format.nSamplesPerSec != sample_rate || format.wBitsPerSample != 32 || (format.nChannels as usize) < out_ch {
+                CoTaskMemFree(Some(format_ptr as *const _ as *const _));
+                anyhow::bail!(
+                    "target mix format {} ch / {} Hz / {}-bit is not compatible",
+                    format.nChannels, format.nSamplesPerSec, format.wBitsPerSample
+                );
+            }
+
+            // Two capture periods of headroom
+            let buffer_duration = (CAPTURE_BUFFER_MS * 2.0 * 10_000.0) as i64;
+            let init = client.Initialize(AUDCLNT_SHAREMODE_SHARED, 0, buffer_duration, 0, format_ptr, None);
+            CoTaskMemFree(Some(format_ptr as *const _ as *const _));
+            init?;
+
+            let render_client: IAudioRenderClient = client.GetService()?;
+            let buffer_frames = client.GetBufferSize()?;
+            client.Start()?;
+            info!("Direct render opened: {} ch, {} Hz, {} frame buffer", format.nChannels, sample_rate, buffer_frames);
+
+            Ok(Self { client, render_client, channels: format.nChannels as usize, buffer_frames })
+        }
+    }
+
+    /// Write interleaved `out_ch`-channel frames into the render buffer, zeroing
+    /// any extra device channels. Returns the number of frames that didn't fit.
+    fn write(&self, data: &[f32], out_ch: usize) -> Result<u32> {
+        unsafe {
+            let total = (data.len() / out_ch) as u32;
+            let padding = self.client.GetCurrentPadding()?;
+            let frames = total.min(self.buffer_frames.saturating_sub(padding));
+            if frames > 0 {
+                let buffer = self.render_client.GetBuffer(frames)? as *mut f32;
+                let dst = std::slice::from_raw_parts_mut(buffer, frames as usize * self.channels);
+                for (dst_frame, src_frame) in dst.chunks_exact_mut(self.channels).zip(data.chunks_exact(out_ch)) {
+                    dst_frame.fill(0.0);
+                    dst_frame[..out_ch].copy_from_slice(src_frame);
+                }
+                self.render_client.ReleaseBuffer(frames, 0)?;
+            }
+            Ok(total - frames)
+        }
+    }
+}
+
+impl Drop for DirectRender {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = self.client.Stop();
+        }
+    }
+}
+
+/// Build a resampler with `channels` channels for the given quality profile
+fn build_resampler(quality: ResamplerQuality, resample_ratio: f64, channels: usize) -> Result<Box<dyn VecResampler<f32>>> {
+    const CHUNK_SIZE: usize = 1024;
+    const MAX_RELATIVE_RATIO: f64 = 2.0;
+
+    let sinc_params = |sinc_len, f_cutoff, oversampling_factor, window| SincInterpolationParameters {
+        sinc_len,
+        f_cutoff,
+        interpolation: SincInterpolationType::Linear,
+        oversampling_factor,
+        window,
+    };
+
+    let resampler: Box<dyn VecResampler<f32>> = match quality {
+        ResamplerQuality::Fast => Box::new(FastFixedIn::<f32>::new(
+            resample_ratio,
+            MAX_RELATIVE_RATIO,
+            PolynomialDegree::Cubic,
+            CHUNK_SIZE,
+            channels,
+        )?),
+        ResamplerQuality::Balanced => Box::new(SincFixedIn::<f32>::new(
+            resample_ratio,
+            MAX_RELATIVE_RATIO,
+            sinc_params(64, 0.91, 128, WindowFunction::Blackman2),
+            CHUNK_SIZE,
+            channels,
+        )?),
+        ResamplerQuality::High => Box::new(SincFixedIn::<f32>::new(
+            resample_ratio,
+            MAX_RELATIVE_RATIO,
+            sinc_params(256, 0.95, 256, WindowFunction::BlackmanHarris2),
+            CHUNK_SIZE,
+            channels,
+        )?),
+    };
+    Ok(resampler)
+}
+
+fn bytes_to_f32(data: &[u8], bytes_per_sample: usize, is_float: bool) -> Vec<f32> {
     match bytes_per_sample {
-        4 => {
+        4 if is_float => {
             // 32-bit float
             data.chunks_exact(4)
                 .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
                 .collect()
         }
+        4 => {
+            // 32-bit int
+            data.chunks_exact(4)
+                .map(|b| {
+                    let sample = i32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+                    sample as f32 / 2147483648.0
+                })
+                .collect()
+        }
         2 => {
             // 16-bit int
             data.chunks_exact(2)
@@ -494,16 +1617,62 @@ fn bytes_to_f32(data: &[u8], bytes_per_sample: usize) -> Vec<f32> {
     }
 }
 
-/// Extract channels from multichannel audio with per-channel control
+/// Build quad (FL, FR, RL, RR) or 5.1 (FL, FR, C, LFE, RL, RR) output frames
+/// from the source fronts and the routed pair produced by `process_channels`,
+/// which goes to the rear. The steered centre is taken out of the fronts and,
+/// on 5.1, sent to C; the LFE stays silent
+#[allow(clippy::too_many_arguments)]
+fn interleave_surround(
+    input: &[f32],
+    channels: u16,
+    map: &ChannelMap,
+    volume: f32,
+    balance: f32,
+    rear: &[f32],
+    out_ch: usize,
+    dsp: &mut DspChain,
+) -> Vec<f32> {
+    let (left_mult, right_mult) = balance_multipliers(balance);
+
+    let mut output = Vec::with_capacity(rear.len() / 2 * out_ch);
+    for (frame, rear) in input.chunks_exact(channels as usize).zip(rear.chunks_exact(2)) {
+        let (fl_idx, fr_idx) = map.front();
+        let fl = frame.get(fl_idx).copied().unwrap_or(0.0);
+        let fr = frame.get(fr_idx).copied().unwrap_or(0.0);
+        // Quad has no centre speaker to move it to
+        let center = if out_ch == 6 { dsp.get_center(fl, fr) } else { 0.0 };
+        let (fl, fr) = ((fl - center) * volume * left_mult, (fr - center) * volume * right_mult);
+        if out_ch == 6 {
+            output.extend_from_slice(&[fl, fr, center * volume, 0.0, rear[0], rear[1]]);
+        } else {
+            output.extend_from_slice(&[fl, fr, rear[0], rear[1]]);
+        }
+    }
+    output
+}
+
+/// Extract channels from multichannel audio with per-channel control, mixed
+/// in the signal-flow order documented on `dsp::route_pair`
 /// Balance: -1.0 = full left, 0.0 = center, 1.0 = full right
+/// Mutes are applied through the ramped `left_mute`/`right_mute` gains
+/// `missing` decides whether a source channel absent from the stream falls back,
+/// is muted, or switches both outputs to plain stereo passthrough
+/// `upmix_only` outputs the upmix alone instead of adding it to the routed channels
+/// Output is always stereo; a mono source is duplicated to both sides through
+/// `ChannelMap::positional`
 fn process_channels(
     input: &[f32], 
     channels: u16, 
+    map: &ChannelMap,
     volume: f32, 
     swap: bool, 
     balance: f32,
     left_ch: &ChannelSettings,
     right_ch: &ChannelSettings,
+    missing: MissingChannelBehavior,
+    upmix_only: bool,
+    left_mute: &mut SmoothedValue,
+    right_mute: &mut SmoothedValue,
     dsp: &mut DspChain,
 ) -> Vec<f32> {
     if input.is_empty() || channels == 0 {
@@ -513,59 +1682,141 @@ fn process_channels(
     let frames = input.len() / channels as usize;
     let mut output = Vec::with_capacity(frames * 2);
 
-    // Calculate balance multipliers
-    let left_mult = if balance > 0.0 { 1.0 - balance } else { 1.0 };
-    let right_mult = if balance < 0.0 { 1.0 + balance } else { 1.0 };
+    // Silence sources the stream doesn't have when the policy says so
+    let present_gain = |source| {
+        if missing == MissingChannelBehavior::Mute && !map.present(source) { 0.0 } else { 1.0 }
+    };
+    let mut gains = PairGains {
+        volume: (left_ch.volume, right_ch.volume),
+        mute: (1.0, 1.0),
+        present: (present_gain(left_ch.source), present_gain(right_ch.source)),
+        upmix_only,
+        channel_limit: dsp.per_channel_limit && !dsp.bypass,
+        swap,
+        balance,
+        // Calibration trims per physical output, independent of master and per-channel volume
+        trim: (db_to_linear(left_ch.trim_db), db_to_linear(right_ch.trim_db)),
+        master: volume,
+    };
 
-    // Channel indices: FL=0, FR=1, RL=2, RR=3
-    let get_channel_idx = |source: ChannelSource, channels: u16| -> usize {
-        match source {
-            ChannelSource::FL => 0,  // Front Left - always index 0
-            ChannelSource::FR => 1,  // Front Right - always index 1
-            ChannelSource::RL => if channels >= 4 { 2 } else { 0 },
-            ChannelSource::RR => if channels >= 4 { 3 } else { 1 },
-        }
+    // Passthrough: if the configured layout can't be satisfied, route FL/FR as-is
+    let (left_idx, right_idx) = if stereo_passthrough(left_ch.source, right_ch.source, missing, map) {
+        map.front()
+    } else {
+        (map.index(left_ch.source), map.index(right_ch.source))
     };
+    let (fl_idx, fr_idx) = map.front();
 
     for frame in 0..frames {
         let base = frame * channels as usize;
         
-        // Get front channels for upmix (FL=0, FR=1)
-        let fl = input.get(base).copied().unwrap_or(0.0);
-        let fr = input.get(base + 1).copied().unwrap_or(0.0);
+        // Get front channels for upmix
+        let fl = input.get(base + fl_idx).copied().unwrap_or(0.0);
+        let fr = input.get(base + fr_idx).copied().unwrap_or(0.0);
         
         // Get upmix contribution (pseudo surround from front channels)
-        let (upmix_l, upmix_r) = dsp.get_upmix(fl, fr);
-        
-        // Get source samples based on channel settings
-        let left_idx = get_channel_idx(left_ch.source, channels);
-        let right_idx = get_channel_idx(right_ch.source, channels);
-        
-        let mut left = if left_ch.muted { 
-            0.0 
-        } else { 
-            input.get(base + left_idx).copied().unwrap_or(0.0) * left_ch.volume
-        };
-        
-        let mut right = if right_ch.muted { 
-            0.0 
-        } else { 
-            input.get(base + right_idx).copied().unwrap_or(0.0) * right_ch.volume
-        };
-        
-        // Add upmix contribution
-        left += upmix_l;
-        right += upmix_r;
-        
-        if swap {
-            std::mem::swap(&mut left, &mut right);
-        }
-        
-        // Apply final volume and clamp to prevent clipping
-        let out_l = (left * volume * left_mult).clamp(-1.0, 1.0);
-        let out_r = (right * volume * right_mult).clamp(-1.0, 1.0);
+        let upmix = dsp.get_upmix(fl, fr);
+
+        let source = (
+            input.get(base + left_idx).copied().unwrap_or(0.0),
+            input.get(base + right_idx).copied().unwrap_or(0.0),
+        );
+        gains.mute = (left_mute.next(), right_mute.next());
+        let (out_l, out_r) = route_pair(source, upmix, &gains);
+
+        // Keep the front+rear sum in range; overs are left for the limiter and
+        // the final clamp in `process_routed_pair`
+        let (out_l, out_r) = dsp.normalize_upmix_sum(out_l, out_r);
         output.push(out_l);
         output.push(out_r);
     }
     output
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mono_source_duplicated_to_stereo() {
+        let input = [0.1, -0.2, 0.3, 0.4];
+        let mut dsp = DspChain::new(48000, SharedLevels::new());
+        let (mut left_mute, mut right_mute) = (SmoothedValue::new(1.0, 1), SmoothedValue::new(1.0, 1));
+        let routed = |source| ChannelSettings { source, ..ChannelSettings::default() };
+
+        for (left, right) in [(ChannelSource::FL, ChannelSource::FR), (ChannelSource::RL, ChannelSource::RR)] {
+            for missing in [MissingChannelBehavior::Fallback, MissingChannelBehavior::Passthrough] {
+                let output = process_channels(
+                    &input, 1, &ChannelMap::positional(1), 1.0, false, 0.0,
+                    &routed(left), &routed(right), missing, false,
+                    &mut left_mute, &mut right_mute, &mut dsp,
+                );
+                let expected: Vec<f32> = input.iter().flat_map(|&s| [s, s]).collect();
+                assert_eq!(output, expected, "{:?}/{:?} with {:?}", left, right, missing);
+            }
+        }
+    }
+
+    #[test]
+    fn test_process_channels_leaves_overs_to_final_stage() {
+        let input = [0.8, 0.4];
+        let mut dsp = DspChain::new(48000, SharedLevels::new());
+        let (mut left_mute, mut right_mute) = (SmoothedValue::new(1.0, 1), SmoothedValue::new(1.0, 1));
+        let routed = |source| ChannelSettings { source, volume: 2.0, ..ChannelSettings::default() };
+
+        let mut frame = process_channels(
+            &input, 2, &ChannelMap::positional(2), 1.0, false, 0.0,
+            &routed(ChannelSource::FL), &routed(ChannelSource::FR), MissingChannelBehavior::Fallback, false,
+            &mut left_mute, &mut right_mute, &mut dsp,
+        );
+        assert_eq!(frame, [1.6, 0.8]);
+
+        process_routed_pair(&mut dsp, &mut frame);
+        assert_eq!(frame, [1.0, 0.8]);
+    }
+
+    #[test]
+    fn test_chain_analysis_labels_every_channel() {
+        let analysis = |output_db: Vec<f32>| ChainAnalysis { input_db: -20.0, output_db }.to_string();
+        assert!(analysis(vec![-20.0; 2]).ends_with("L -20.0 dBFS, R -20.0 dBFS"));
+        assert!(analysis(vec![-20.0; 4]).ends_with("FL -20.0 dBFS, FR -20.0 dBFS, RL -20.0 dBFS, RR -20.0 dBFS"));
+        assert!(analysis(vec![-6.0, -6.0, -3.0, f32::NEG_INFINITY, -20.0, -20.0]).ends_with(
+            "FL -6.0 dBFS, FR -6.0 dBFS, C -3.0 dBFS, LFE -inf dBFS, RL -20.0 dBFS, RR -20.0 dBFS"
+        ));
+    }
+
+    /// Feed `count` 480-frame packets spaced `interval_qpc` apart (100 ns units)
+    /// from `start`; returns the first completed drift measurement, if any
+    fn feed_packets(clock: &mut ClockMonitor, start: f64, interval_qpc: f64, count: usize) -> Option<f64> {
+        (0..count).find_map(|i| clock.packet((start + i as f64 * interval_qpc).round() as u64, 480, false))
+    }
+
+    #[test]
+    fn test_clock_monitor_drift() {
+        // 480 frames every 10 ms is exactly 48 kHz
+        let mut clock = ClockMonitor::new(48000);
+        let ppm = feed_packets(&mut clock, 0.0, 100_000.0, 1001).expect("window completes");
+        assert!(ppm.abs() < 1.0, "nominal {} ppm", ppm);
+
+        // A source clock 1% fast delivers the same packets 1% sooner
+        let mut clock = ClockMonitor::new(48000);
+        let ppm = feed_packets(&mut clock, 0.0, 100_000.0 / 1.01, 1100).expect("window completes");
+        assert!((ppm - 10_000.0).abs() < 10.0, "fast {} ppm", ppm);
+    }
+
+    #[test]
+    fn test_clock_monitor_gap_restarts_window() {
+        let mut clock = ClockMonitor::new(48000);
+        assert_eq!(feed_packets(&mut clock, 0.0, 100_000.0, 900), None);
+        // One second of silence from an idle source: the 9 s so far don't count
+        let resume = 900.0 * 100_000.0 + 1e7;
+        assert_eq!(feed_packets(&mut clock, resume, 100_000.0, 900), None);
+        assert!(feed_packets(&mut clock, resume + 900.0 * 100_000.0, 100_000.0, 101).is_some());
+
+        // A discontinuity flag restarts it as well
+        let mut clock = ClockMonitor::new(48000);
+        assert_eq!(feed_packets(&mut clock, 0.0, 100_000.0, 900), None);
+        assert_eq!(clock.packet(900 * 100_000, 480, true), None);
+        assert_eq!(feed_packets(&mut clock, 901.0 * 100_000.0, 100_000.0, 900), None);
+    }
+}
@@ -2,16 +2,78 @@ mod loopback;
 
 use anyhow::{Context, Result};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Device, Stream, StreamConfig};
-use parking_lot::RwLock;
-use ringbuf::{HeapRb, traits::{Consumer, Split}};
+use cpal::{BufferSize, Device, SampleFormat, SampleRate, Stream, StreamConfig, SupportedBufferSize};
+use parking_lot::{Mutex, RwLock};
+use ringbuf::{HeapCons, HeapRb, traits::{Consumer, Observer, Split}};
 use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::Arc;
-use tracing::{info, error};
-use crate::config::{ChannelConfig, ChannelSource};
-use crate::dsp::SharedLevels;
+use std::time::{Duration, Instant};
+use tracing::{info, error, warn};
+use crate::config::{CaptureOverrides, ChannelConfig, ChannelSource, LimiterMode, MeterWeighting, MissingChannelBehavior, ResamplerQuality, SourceFormatCache, SourceRole, SwapScope, UpmixMode};
+use crate::dsp::{db_to_linear, DspChain, PinkNoise, SharedLevels, ThreeBandEq, Upmixer};
 
-pub use loopback::{LoopbackCapture, DspConfig};
+pub use loopback::{default_render_device_name, render_endpoint_ids, LoopbackCapture, DspConfig};
+use loopback::CAPTURE_BUFFER_MS;
+
+const RECONNECT_BACKOFF_INITIAL: Duration = Duration::from_secs(1);
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(30);
+/// Minimum time between capture thread heartbeat checks
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+/// Input gain above which a hot source is likely to clip inside the chain
+const INPUT_GAIN_WARN_DB: f32 = 12.0;
+/// Typical WASAPI shared-mode device period used by the output stream
+/// when no explicit buffer size is configured
+const OUTPUT_BUFFER_MS: f32 = 10.0;
+/// Extra time `stop` waits for the output callback to finish its stop ramp,
+/// covering a device period or two before the stream is dropped regardless
+const STOP_RAMP_GRACE: Duration = Duration::from_millis(50);
+/// Longest a source crossfade waits for the new capture to fill its buffer;
+/// loopback delivers nothing while the new source is idle
+const CROSSFADE_MAX_WAIT_MS: f32 = 500.0;
+
+/// Routing state transitions, for embedders that want to react instead of poll
+#[allow(dead_code)] // The tray binary only handles a few of these
+#[derive(Debug, Clone, PartialEq)]
+pub enum RouterEvent {
+    Started { source: String, target: String },
+    Stopped,
+    /// Routing was (re)started with a different source or target device
+    DeviceChanged { source: String, target: String },
+    /// Sustained clipping detected by the capture thread
+    ClipDetected,
+    /// Level safety asks for the master volume to be lowered by `db` (negative)
+    LevelSafetyReduction { db: f32 },
+    OutputFailed,
+    /// The source is held in exclusive mode by another app, so capture is silent
+    SourceExclusive,
+    /// The capture thread died or stalled and routing was restarted
+    CaptureRestarted,
+    /// The source delivers fewer channels than `expected_source_channels`
+    ChannelMismatch { expected: u16, actual: u16 },
+}
+
+/// Fans out `RouterEvent`s to all subscribers; disconnected receivers are pruned
+#[derive(Clone, Default)]
+pub struct EventBus {
+    senders: Arc<Mutex<Vec<Sender<RouterEvent>>>>,
+}
+
+impl EventBus {
+    pub fn subscribe(&self) -> Receiver<RouterEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.senders.lock().push(tx);
+        rx
+    }
+
+    pub fn emit(&self, event: RouterEvent) {
+        let mut senders = self.senders.lock();
+        if senders.is_empty() {
+            return;
+        }
+        senders.retain(|tx| tx.send(event.clone()).is_ok());
+    }
+}
 
 pub struct AudioDevice {
     pub name: String,
@@ -23,39 +85,65 @@ pub struct AudioDevice {
 pub struct TestTonePlayer {
     host: cpal::Host,
     swap_channels: Arc<RwLock<bool>>,
+    swap_scope: Arc<RwLock<SwapScope>>,
     target_device_name: Option<String>,
+    output_channels: u16,
 }
 
 impl TestTonePlayer {
+    /// Point the player at a target without starting routing (CLI one-shot)
+    pub fn with_target(mut self, target_name: &str) -> Self {
+        self.target_device_name = Some(target_name.to_string());
+        self
+    }
+
     fn find_output_device(&self, name: &str) -> Option<Device> {
         self.host.output_devices().ok()?.find(|d| {
             d.name().map(|n| n.contains(name)).unwrap_or(false)
         })
     }
 
+    /// Test the routed output. The routed pair is the last two output
+    /// channels (0/1 in stereo, 2/3 in quad) with the physically-left speaker
+    /// first (as in `process_channels`), so the swap only changes the result
+    /// when its scope includes test tones.
     pub fn play_test_tone_sub(&self, left_channel: bool) -> Result<()> {
         let target_name = self.target_device_name.as_ref()
             .context("No target device configured. Start routing first.")?;
         
-        let swap = *self.swap_channels.read();
+        let swap = *self.swap_channels.read() && self.swap_scope.read().applies_to_test();
         let actual_left = if swap { !left_channel } else { left_channel };
+        let rear_offset = match self.find_output_device(target_name) {
+            Some(device) => effective_output_channels(&device, self.output_channels) as usize - 2,
+            None => 0,
+        };
+        let channel_index = rear_offset + if actual_left { 0 } else { 1 };
         
-        self.play_tone_on_device(target_name, actual_left, "Sub", left_channel)
+        self.play_tone_on_device(target_name, channel_index, "Sub", left_channel)
     }
 
+    /// Test the main speakers, using the same channel index mapping as the
+    /// capture path so the tone drives FL/FR regardless of the device layout
     pub fn play_test_tone_main(&self, left_channel: bool, source_name: &str) -> Result<()> {
-        self.play_tone_on_device(source_name, left_channel, "Main", left_channel)
+        let output_device = self.find_output_device(source_name)
+            .context(format!("Output device not found: {}", source_name))?;
+        let channels = output_device.default_output_config()?.channels();
+        let source = if left_channel { ChannelSource::FL } else { ChannelSource::FR };
+        let channel_index = ChannelMap::positional(channels).index(source);
+        self.play_tone_on_device(source_name, channel_index, "Main", left_channel)
     }
 
-    fn play_tone_on_device(&self, device_name: &str, actual_left_channel: bool, label: &str, display_left: bool) -> Result<()> {
+    fn play_tone_on_device(&self, device_name: &str, channel_index: usize, label: &str, display_left: bool) -> Result<()> {
         let output_device = self.find_output_device(device_name)
             .context(format!("Output device not found: {}", device_name))?;
 
         let output_supported = output_device.default_output_config()?;
         let sample_rate = output_supported.sample_rate().0 as f32;
+        let channels = output_supported.channels().max(2);
+        let channel_index = channel_index.min(channels as usize - 1);
         
         let output_config = StreamConfig {
-            channels: 2,
+            channels,
             sample_rate: cpal::SampleRate(sample_rate as u32),
             buffer_size: cpal::BufferSize::Default,
         };
@@ -68,21 +156,14 @@ impl TestTonePlayer {
         let stream = output_device.build_output_stream(
             &output_config,
             move |data: &mut [f32], _: &_| {
-                for frame in data.chunks_mut(2) {
+                for frame in data.chunks_mut(channels as usize) {
                     let current = samples_total_clone.fetch_add(1, Ordering::Relaxed) as usize;
-                    if current >= duration_samples {
-                        frame[0] = 0.0;
-                        frame[1] = 0.0;
-                    } else {
+                    frame.fill(0.0);
+                    if current < duration_samples {
                         let t = current as f32 / sample_rate;
                         let sample = (t * freq * 2.0 * std::f32::consts::PI).sin() * 0.5;
-                        
-                        if actual_left_channel {
-                            frame[0] = sample;
-                            frame[1] = 0.0;
-                        } else {
-                            frame[0] = 0.0;
-                            frame[1] = sample;
+                        if let Some(out) = frame.get_mut(channel_index) {
+                            *out = sample;
                         }
                     }
                 }
@@ -103,11 +184,387 @@ impl TestTonePlayer {
     }
 }
 
+/// Continuous pink noise on one target channel, played from its own thread.
+/// Dropping the handle stops the stream and joins the thread.
+pub struct NoiseLoop {
+    stop: Arc<AtomicBool>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Drop for NoiseLoop {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl TestTonePlayer {
+    /// Start looping pink noise on `channel` of the target (mapped like a
+    /// routed source channel) at `level_db` dBFS RMS, until the returned
+    /// handle is dropped
+    pub fn start_noise(&self, channel: ChannelSource, level_db: f32) -> Result<NoiseLoop> {
+        let target_name = self.target_device_name.clone()
+            .context("No target device configured. Start routing first.")?;
+        let gain = db_to_linear(level_db.clamp(-60.0, -6.0));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let host = cpal::default_host();
+        let (ready_tx, ready_rx) = mpsc::channel();
+
+        // The stream isn't Send, so it lives entirely on the noise thread
+        let thread = std::thread::spawn(move || {
+            let open = || -> Result<Stream> {
+                let device = host.output_devices()?
+                    .find(|d| d.name().map(|n| n.contains(&target_name)).unwrap_or(false))
+                    .context(format!("Output device not found: {}", target_name))?;
+                let supported = device.default_output_config()?;
+                let channels = supported.channels().max(2);
+                let channel_index = ChannelMap::positional(channels).index(channel);
+                let config = StreamConfig {
+                    channels,
+                    sample_rate: supported.sample_rate(),
+                    buffer_size: BufferSize::Default,
+                };
+                let mut noise = PinkNoise::new(0x5117_5EED);
+                let stream = device.build_output_stream(
+                    &config,
+                    move |data: &mut [f32], _: &_| {
+                        for frame in data.chunks_mut(channels as usize) {
+                            frame.fill(0.0);
+                            frame[channel_index] = (noise.next_sample() * gain).clamp(-1.0, 1.0);
+                        }
+                    },
+                    move |err| error!("Noise stream error: {}", err),
+                    None,
+                )?;
+                stream.play()?;
+                info!("Playing pink noise on {} channel {} at {:.0} dBFS", target_name, channel_index, level_db);
+                Ok(stream)
+            };
+            let stream = match open() {
+                Ok(stream) => {
+                    let _ = ready_tx.send(Ok(()));
+                    stream
+                }
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e));
+                    return;
+                }
+            };
+            while !thread_stop.load(Ordering::Relaxed) {
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            drop(stream);
+            info!("Pink noise stopped");
+        });
+
+        ready_rx.recv().context("Noise thread exited")??;
+        Ok(NoiseLoop { stop, thread: Some(thread) })
+    }
+}
+
+/// Output latency measured with a loopback click
+#[derive(Debug, Clone, Copy)]
+pub struct LatencyMeasurement {
+    pub samples: u32,
+    pub sample_rate: u32,
+}
+
+impl LatencyMeasurement {
+    pub fn ms(&self) -> f32 {
+        self.samples as f32 * 1000.0 / self.sample_rate as f32
+    }
+}
+
+/// Length of the calibration click
+const LATENCY_CLICK_MS: f32 = 2.0;
+/// Silence before the click so the loopback stream is already running
+const LATENCY_LEAD_IN_MS: f32 = 200.0;
+/// How long to record the loopback after starting
+const LATENCY_RECORD_MS: u64 = 1000;
+
+impl TestTonePlayer {
+    /// Emit a short click on the target and capture it back through WASAPI
+    /// loopback on the same device. The click is located by correlation and
+    /// the result is the time from writing it to the output buffer until it
+    /// shows up in the capture, i.e. the buffering split51's own output adds.
+    /// Other audio playing on the target at the same time can skew the result.
+    pub fn measure_latency(&self) -> Result<LatencyMeasurement> {
+        let target_name = self.target_device_name.as_ref()
+            .context("No target device configured. Start routing first.")?;
+        let device = self.find_output_device(target_name)
+            .context(format!("Output device not found: {}", target_name))?;
+
+        let supported = device.default_output_config()?;
+        let sample_rate = supported.sample_rate().0;
+        let channels = supported.channels() as usize;
+        let config = StreamConfig {
+            channels: supported.channels(),
+            sample_rate: supported.sample_rate(),
+            buffer_size: BufferSize::Default,
+        };
+
+        // Hann-windowed 2 kHz burst: sharp correlation peak, no DC thump
+        let click_len = ((sample_rate as f32 * LATENCY_CLICK_MS / 1000.0) as usize).max(8);
+        let click: Arc<Vec<f32>> = Arc::new((0..click_len).map(|i| {
+            let t = i as f32 / sample_rate as f32;
+            let window = 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / (click_len - 1) as f32).cos();
+            (t * 2000.0 * 2.0 * std::f32::consts::PI).sin() * window * 0.5
+        }).collect());
+        let lead_in = (sample_rate as f32 * LATENCY_LEAD_IN_MS / 1000.0) as usize;
+
+        // Loopback recording: mono-summed samples plus the capture instant of each chunk
+        let recorded: Arc<Mutex<(Vec<f32>, Vec<(usize, cpal::StreamInstant)>)>> = Arc::default();
+        let recorded_clone = recorded.clone();
+        let input_stream = device.build_input_stream(
+            &config,
+            move |data: &[f32], info: &cpal::InputCallbackInfo| {
+                let mut rec = recorded_clone.lock();
+                let start = rec.0.len();
+                rec.1.push((start, info.timestamp().capture));
+                rec.0.extend(data.chunks(channels).map(|f| f.iter().sum::<f32>()));
+            },
+            move |err| error!("Latency capture error: {}", err),
+            None,
+        ).context("Failed to open loopback capture on target")?;
+
+        // The instant the click's first frame was handed to the output buffer
+        let emitted: Arc<Mutex<Option<cpal::StreamInstant>>> = Arc::default();
+        let emitted_clone = emitted.clone();
+        let output_click = click.clone();
+        let written = Arc::new(AtomicU32::new(0));
+        let output_stream = device.build_output_stream(
+            &config,
+            move |data: &mut [f32], info: &cpal::OutputCallbackInfo| {
+                for (i, frame) in data.chunks_mut(channels).enumerate() {
+                    let current = written.fetch_add(1, Ordering::Relaxed) as usize;
+                    let sample = current.checked_sub(lead_in)
+                        .and_then(|n| output_click.get(n))
+                        .copied()
+                        .unwrap_or(0.0);
+                    if current == lead_in {
+                        let offset = Duration::from_secs_f64(i as f64 / sample_rate as f64);
+                        *emitted_clone.lock() = info.timestamp().callback.add(offset);
+                    }
+                    frame.fill(sample);
+                }
+            },
+            move |err| error!("Latency click error: {}", err),
+            None,
+        )?;
+
+        input_stream.play()?;
+        output_stream.play()?;
+        info!("Measuring latency on {} ({} Hz)", target_name, sample_rate);
+        std::thread::sleep(Duration::from_millis(LATENCY_RECORD_MS));
+        drop(output_stream);
+        drop(input_stream);
+
+        let emitted = (*emitted.lock()).context("Click was never written to the output")?;
+        let recorded = recorded.lock();
+        let (samples, chunks) = &*recorded;
+
+        // Cross-correlate the recording with the click
+        let click_energy: f32 = click.iter().map(|s| s * s).sum();
+        let (best_index, best_score) = (0..samples.len().saturating_sub(click_len))
+            .map(|i| {
+                let score: f32 = click.iter().zip(&samples[i..]).map(|(c, s)| c * s).sum();
+                (i, score)
+            })
+            .fold((0, 0.0f32), |best, cur| if cur.1 > best.1 { cur } else { best });
+        if best_score < click_energy * 0.25 {
+            anyhow::bail!("Click not found in the loopback capture (is the target muted?)");
+        }
+
+        // Instant of the matched frame, from the chunk it landed in
+        let (chunk_start, chunk_instant) = chunks.iter()
+            .take_while(|(start, _)| *start <= best_index)
+            .last()
+            .copied()
+            .context("Loopback capture returned no data")?;
+        let offset = Duration::from_secs_f64((best_index - chunk_start) as f64 / sample_rate as f64);
+        let captured = chunk_instant.add(offset).context("Capture timestamp overflow")?;
+        let latency = captured.duration_since(&emitted)
+            .context("Click was captured before it was written (clock mismatch)")?;
+
+        Ok(LatencyMeasurement {
+            samples: (latency.as_secs_f64() * sample_rate as f64).round() as u32,
+            sample_rate,
+        })
+    }
+}
+
+// Speaker position bits of WAVEFORMATEXTENSIBLE.dwChannelMask
+const SPEAKER_FRONT_LEFT: u32 = 0x1;
+const SPEAKER_FRONT_RIGHT: u32 = 0x2;
+const SPEAKER_BACK_LEFT: u32 = 0x10;
+const SPEAKER_BACK_RIGHT: u32 = 0x20;
+const SPEAKER_SIDE_LEFT: u32 = 0x200;
+const SPEAKER_SIDE_RIGHT: u32 = 0x400;
+
+/// Where the named source channels sit in an interleaved frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ChannelMap {
+    channels: u16,
+    front: (usize, usize),
+    /// None when the stream has no rear pair
+    rear: Option<(usize, usize)>,
+}
+
+impl ChannelMap {
+    /// Positional layout: FL=0, FR=1, RL=2, RR=3 (rears need 4+ channels).
+    /// A mono stream's single channel is both fronts, so it plays on both sides.
+    pub(crate) fn positional(channels: u16) -> Self {
+        let front = if channels == 1 { (0, 0) } else { (0, 1) };
+        Self { channels, front, rear: (channels >= 4).then_some((2, 3)) }
+    }
+
+    /// Layout from a `dwChannelMask`, whose channels are interleaved in
+    /// ascending bit order (e.g. 5.1 is FL FR FC LFE BL BR). The rears are the
+    /// back pair, or the side pair when there is none. None when the mask
+    /// doesn't describe `channels` channels or has no front pair.
+    pub(crate) fn from_mask(mask: u32, channels: u16) -> Option<Self> {
+        if mask.count_ones() != channels as u32 {
+            return None;
+        }
+        let position = |bit: u32| (mask & bit != 0).then(|| (mask & (bit - 1)).count_ones() as usize);
+        let pair = |left, right| position(left).zip(position(right));
+        Some(Self {
+            channels,
+            front: pair(SPEAKER_FRONT_LEFT, SPEAKER_FRONT_RIGHT)?,
+            rear: pair(SPEAKER_BACK_LEFT, SPEAKER_BACK_RIGHT).or_else(|| pair(SPEAKER_SIDE_LEFT, SPEAKER_SIDE_RIGHT)),
+        })
+    }
+
+    pub(crate) fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    pub(crate) fn front(&self) -> (usize, usize) {
+        self.front
+    }
+
+    /// Interleaved index of a named source channel. Rear channels fall back
+    /// to the front pair when the stream has none.
+    pub(crate) fn index(&self, source: ChannelSource) -> usize {
+        match source {
+            ChannelSource::FL => self.front.0,
+            ChannelSource::FR => self.front.1,
+            ChannelSource::RL => self.rear.map_or(self.front.0, |rear| rear.0),
+            ChannelSource::RR => self.rear.map_or(self.front.1, |rear| rear.1),
+            ChannelSource::Index(i) => (i as usize).min(self.channels.max(1) as usize - 1),
+        }
+    }
+
+    /// Whether `source` exists in the stream, i.e. whether `index` maps it
+    /// without falling back to the fronts
+    pub(crate) fn present(&self, source: ChannelSource) -> bool {
+        match source {
+            ChannelSource::FL | ChannelSource::FR => true,
+            ChannelSource::RL | ChannelSource::RR => self.rear.is_some(),
+            ChannelSource::Index(i) => i < self.channels,
+        }
+    }
+}
+
+impl std::fmt::Display for ChannelMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "FL={} FR={}", self.front.0, self.front.1)?;
+        match self.rear {
+            Some((rl, rr)) => write!(f, " RL={} RR={}", rl, rr),
+            None => write!(f, " (no rears)"),
+        }
+    }
+}
+
+/// Whether the `Passthrough` policy replaces the routing with plain FL/FR
+/// because a configured source channel is absent
+pub(crate) fn stereo_passthrough(left: ChannelSource, right: ChannelSource, missing: MissingChannelBehavior, map: &ChannelMap) -> bool {
+    missing == MissingChannelBehavior::Passthrough && !(map.present(left) && map.present(right))
+}
+
+/// Output channel count to open on the target: 5.1 or quad only when
+/// requested and the device has that many channels, stepping down from 5.1
+/// to quad before stereo
+fn effective_output_channels(device: &Device, requested: u16) -> u16 {
+    if requested < 4 {
+        return 2;
+    }
+    match device.default_output_config() {
+        Ok(config) if config.channels() >= 6 && requested == 6 => 6,
+        Ok(config) if config.channels() >= 4 => 4,
+        _ => 2,
+    }
+}
+
+/// Channel count to open the target stream with: the routed count when the
+/// device accepts it as f32 at `rate`, otherwise its default layout (virtual
+/// cables often expose 8ch or other odd mix formats). The output callback
+/// pads the extra channels with silence.
+fn stream_channels(device: &Device, channels: u16, rate: SampleRate) -> u16 {
+    let supported = device.supported_output_configs().ok()
+        .into_iter()
+        .flatten()
+        .any(|c| c.channels() == channels
+            && c.sample_format() == SampleFormat::F32
+            && c.min_sample_rate() <= rate
+            && rate <= c.max_sample_rate());
+    if supported {
+        return channels;
+    }
+    match device.default_output_config() {
+        Ok(config) if config.channels() > channels => config.channels(),
+        _ => channels,
+    }
+}
+
+/// Name heuristic for virtual render devices (VB-Cable, VoiceMeeter, ...),
+/// used to mark them in device lists
+pub fn is_likely_virtual(name: &str) -> bool {
+    const HINTS: [&str; 5] = ["cable", "virtual", "voicemeeter", "vb-audio", "loopback"];
+    let name = name.to_lowercase();
+    HINTS.iter().any(|hint| name.contains(hint))
+}
+
+/// Clamp a requested output buffer size to the range the device reports for
+/// f32 configs with enough channels; unchanged if the range is unknown
+fn clamp_buffer_frames(device: &Device, channels: u16, requested: u32) -> u32 {
+    let range = device.supported_output_configs().ok()
+        .into_iter()
+        .flatten()
+        .filter(|c| c.channels() >= channels && c.sample_format() == SampleFormat::F32)
+        .find_map(|c| match *c.buffer_size() {
+            SupportedBufferSize::Range { min, max } => Some((min, max)),
+            SupportedBufferSize::Unknown => None,
+        });
+    match range {
+        Some((min, max)) => requested.clamp(min, max),
+        None => requested,
+    }
+}
+
+/// Device mix format for logs, e.g. "6ch / 48000 Hz / f32"
+fn describe_format(config: &cpal::SupportedStreamConfig) -> String {
+    format!("{}ch / {} Hz / {}", config.channels(), config.sample_rate().0, config.sample_format())
+}
+
+/// Pick the supported stereo f32 output rate closest to the source rate
+fn negotiate_output_rate(device: &Device, source_rate: SampleRate) -> Option<SampleRate> {
+    device.supported_output_configs().ok()?
+        .filter(|c| c.channels() >= 2 && c.sample_format() == SampleFormat::F32)
+        .map(|c| source_rate.0.clamp(c.min_sample_rate().0, c.max_sample_rate().0))
+        .min_by_key(|rate| rate.abs_diff(source_rate.0))
+        .map(SampleRate)
+}
+
 #[derive(Clone)]
 pub struct ChannelSettings {
     pub source: ChannelSource,
     pub volume: f32,
     pub muted: bool,
+    pub trim_db: f32,
 }
 
 impl Default for ChannelSettings {
@@ -116,7 +573,42 @@ impl Default for ChannelSettings {
             source: ChannelSource::RL,
             volume: 1.0,
             muted: false,
+            trim_db: 0.0,
+        }
+    }
+}
+
+/// Output-side fade from the running capture's buffer to a new capture's,
+/// handed to the output callback by `AudioRouter::switch_source`
+struct Crossfade {
+    consumer: HeapCons<f32>,
+    /// Cleared when the fade completes so the outgoing capture thread exits
+    outgoing_running: Arc<AtomicBool>,
+    frames: usize,
+    pos: usize,
+    /// Frames spent waiting for the new buffer to prime, up to `max_wait`
+    waited: usize,
+    max_wait: usize,
+}
+
+impl Crossfade {
+    /// Mix one frame of the new capture into `frame` (from the old one) on
+    /// an equal-power curve, once the new buffer holds `prefill` samples
+    fn mix(&mut self, frame: &mut [f32], prefill: usize) {
+        if self.pos == 0 && self.waited < self.max_wait && self.consumer.occupied_len() < prefill {
+            self.waited += 1;
+            return;
+        }
+        let t = (self.pos as f32 / self.frames as f32).min(1.0) * std::f32::consts::FRAC_PI_2;
+        let (old_gain, new_gain) = (t.cos(), t.sin());
+        for sample in frame.iter_mut() {
+            *sample = *sample * old_gain + self.consumer.try_pop().unwrap_or(0.0) * new_gain;
         }
+        self.pos += 1;
+    }
+
+    fn finished(&self) -> bool {
+        self.pos >= self.frames
     }
 }
 
@@ -128,11 +620,68 @@ pub struct AudioRouter {
     current_channels: Arc<AtomicU32>,
     volume: Arc<RwLock<f32>>,
     swap_channels: Arc<RwLock<bool>>,
+    swap_scope: Arc<RwLock<SwapScope>>,
     balance: Arc<RwLock<f32>>,
     left_channel: Arc<RwLock<ChannelSettings>>,
     right_channel: Arc<RwLock<ChannelSettings>>,
+    source_device_name: Option<String>,
     target_device_name: Option<String>,
     dsp_config: DspConfig,
+    resampler_quality: ResamplerQuality,
+    /// Open the target at the source rate when supported (no resampling)
+    match_source_rate: bool,
+    prefill_ms: f32,
+    /// Cosine fade-in at the start of each new output stream (0 = off)
+    soft_start_ms: f32,
+    /// Fade-out that brings the output to zero before the stream is dropped (0 = off)
+    stop_ramp_ms: f32,
+    /// Asks the output callback to ramp to zero; it sets `output_stopped` once silent
+    output_stopping: Arc<AtomicBool>,
+    output_stopped: Arc<AtomicBool>,
+    capture_overrides: CaptureOverrides,
+    /// Requested output channels: 2 (stereo fold) or 4 (discrete quad)
+    output_channels: u16,
+    /// Explicit output buffer size in frames (None = driver default)
+    output_buffer_frames: Option<u32>,
+    /// Output buffer duration of the running stream, for the latency estimate
+    output_buffer_ms: f32,
+    /// Experimental: render from the capture thread, bypassing the ring buffer
+    direct_mode: bool,
+    /// Whether the running session uses direct mode
+    direct_active: bool,
+    /// DSP chain kept across capture restarts for click-free switches
+    dsp_state: Arc<Mutex<Option<DspChain>>>,
+    preserve_dsp_state: bool,
+    /// Disable by pausing capture and output instead of tearing them down
+    keep_warm: bool,
+    /// Disable by pausing capture while the output stream keeps playing silence
+    keep_stream_alive: bool,
+    /// The target is a virtual cable other apps record from; implies keep_stream_alive
+    capturable_output: bool,
+    /// Tells the output callback to drop buffered audio and prime again
+    output_reset: Arc<AtomicBool>,
+    /// Tells the output callback to write silence (routing disabled, stream kept alive)
+    output_idle: Arc<AtomicBool>,
+    /// Source switches fade over this long instead of restarting (0 = off)
+    source_crossfade_ms: f32,
+    /// Pending crossfade for the output callback to pick up
+    crossfade_slot: Arc<Mutex<Option<Crossfade>>>,
+    /// Set by the output callback when a crossfade completed
+    crossfade_done: Arc<AtomicBool>,
+    /// Outgoing capture during a crossfade, joined by `poll_crossfade`
+    fading_capture: Option<LoopbackCapture>,
+    /// Rate and routed channels of the running buffered output stream
+    stream_format: Option<(u32, u16)>,
+    exclusive_fallback: bool,
+    /// Capture the default device for this role instead of matching the source name
+    source_role: Option<SourceRole>,
+    /// When the current routing session started; cleared by `stop`
+    started_at: Option<Instant>,
+    /// Set when the output stream fails; cleared by `stop`
+    output_failed: Arc<AtomicBool>,
+    next_reconnect: Option<Instant>,
+    reconnect_backoff: Duration,
+    last_health_check: Instant,
 }
 
 impl AudioRouter {
@@ -146,23 +695,154 @@ impl AudioRouter {
             current_channels: Arc::new(AtomicU32::new(2)),
             volume: Arc::new(RwLock::new(1.0)),
             swap_channels: Arc::new(RwLock::new(false)),
+            swap_scope: Arc::new(RwLock::new(SwapScope::All)),
             balance: Arc::new(RwLock::new(0.0)),
             left_channel: Arc::new(RwLock::new(ChannelSettings::default())),
             right_channel: Arc::new(RwLock::new(ChannelSettings {
                 source: ChannelSource::RR,
                 volume: 1.0,
                 muted: false,
+                trim_db: 0.0,
             })),
+            source_device_name: None,
             target_device_name: None,
             dsp_config: DspConfig::new(),
+            resampler_quality: ResamplerQuality::High,
+            match_source_rate: true,
+            prefill_ms: 50.0,
+            soft_start_ms: 30.0,
+            capture_overrides: CaptureOverrides::default(),
+            output_channels: 2,
+            output_buffer_frames: None,
+            output_buffer_ms: OUTPUT_BUFFER_MS,
+            direct_mode: false,
+            direct_active: false,
+            dsp_state: Arc::new(Mutex::new(None)),
+            preserve_dsp_state: true,
+            keep_warm: false,
+            keep_stream_alive: false,
+            capturable_output: false,
+            output_reset: Arc::new(AtomicBool::new(false)),
+            output_idle: Arc::new(AtomicBool::new(false)),
+            stop_ramp_ms: 5.0,
+            output_stopping: Arc::new(AtomicBool::new(false)),
+            output_stopped: Arc::new(AtomicBool::new(false)),
+            source_crossfade_ms: 0.0,
+            crossfade_slot: Arc::new(Mutex::new(None)),
+            crossfade_done: Arc::new(AtomicBool::new(false)),
+            fading_capture: None,
+            stream_format: None,
+            exclusive_fallback: false,
+            source_role: None,
+            started_at: None,
+            output_failed: Arc::new(AtomicBool::new(false)),
+            next_reconnect: None,
+            reconnect_backoff: RECONNECT_BACKOFF_INITIAL,
+            last_health_check: Instant::now(),
         })
     }
 
+    /// Subscribe to routing state events. Events are sent from the control
+    /// thread and the capture thread; dropping the receiver unsubscribes.
+    #[allow(dead_code)]
+    pub fn subscribe(&self) -> Receiver<RouterEvent> {
+        self.dsp_config.events.subscribe()
+    }
+
     /// Get shared level meter values
     pub fn get_shared_levels(&self) -> Arc<SharedLevels> {
         self.dsp_config.shared_levels.clone()
     }
 
+    /// Friendly name of the endpoint actually being captured, read from its
+    /// property store by the capture thread
+    pub fn capture_device_name(&self) -> Option<String> {
+        if !self.is_running() {
+            return None;
+        }
+        self.dsp_config.capture_device_name.read().clone()
+    }
+
+    /// Get the loopback format detected by the capture thread
+    pub fn get_source_format(&self) -> Option<String> {
+        self.dsp_config.source_format.read().clone()
+    }
+
+    /// The expected channel count while the source delivers fewer channels
+    pub fn source_channel_mismatch(&self) -> Option<u16> {
+        if self.is_running() && self.dsp_config.channel_mismatch.load(Ordering::Relaxed) {
+            *self.dsp_config.expected_source_channels.read()
+        } else {
+            None
+        }
+    }
+
+    /// Whether routing currently falls back to stereo passthrough because the
+    /// source lacks a configured channel
+    pub fn stereo_passthrough_active(&self) -> bool {
+        self.is_running() && stereo_passthrough(
+            self.left_channel.read().source,
+            self.right_channel.read().source,
+            *self.dsp_config.missing_channel_behavior.read(),
+            &self.dsp_config.channel_map.read(),
+        )
+    }
+
+    /// Whether the capture thread found the source held in exclusive mode
+    pub fn source_in_exclusive_use(&self) -> bool {
+        self.is_running() && self.dsp_config.source_exclusive.load(Ordering::Relaxed)
+    }
+
+    /// Uptime of the current routing session and the number of buffers that
+    /// dropped or missed samples since it started
+    pub fn uptime_status(&self) -> Option<(Duration, u64)> {
+        let started_at = self.started_at?;
+        Some((started_at.elapsed(), self.dsp_config.dropouts.load(Ordering::Relaxed)))
+    }
+
+    /// Resampling stage of the running session, with the measured source clock
+    pub fn resampler_status(&self) -> Option<loopback::ResamplerStatus> {
+        if !self.is_running() {
+            return None;
+        }
+        *self.dsp_config.resampler.read()
+    }
+
+    /// Measure the output level for a -20 dBFS 1 kHz tone through the DSP
+    /// chain and routing with the current settings (offline, see
+    /// `loopback::analyze_chain`). Uses the running session's rate and source
+    /// layout, or 48 kHz stereo when stopped.
+    pub fn analyze_chain(&self) -> loopback::ChainAnalysis {
+        let sample_rate = self.resampler_status().map_or(48000, |status| status.target_rate);
+        let swap = *self.swap_channels.read() && self.swap_scope.read().applies_to_routing();
+        loopback::analyze_chain(
+            &self.dsp_config,
+            sample_rate,
+            self.output_channels,
+            *self.volume.read(),
+            swap,
+            *self.balance.read(),
+            &self.left_channel.read(),
+            &self.right_channel.read(),
+        )
+    }
+
+    /// Estimated end-to-end latency in ms while routing: capture buffer,
+    /// ring buffer prefill, resampler delay, DSP delay and output buffer
+    pub fn latency_estimate_ms(&self) -> Option<f32> {
+        if !self.is_running() {
+            return None;
+        }
+        let prefill_ms = if self.direct_active { 0.0 } else { self.prefill_ms };
+        Some(
+            CAPTURE_BUFFER_MS
+                + prefill_ms
+                + *self.dsp_config.resampler_delay_ms.read()
+                + *self.dsp_config.delay_ms.read()
+                + self.output_buffer_ms,
+        )
+    }
+
     /// Set DSP delay in milliseconds
     pub fn set_delay_ms(&self, ms: f32) {
         *self.dsp_config.delay_ms.write() = ms.clamp(0.0, 200.0);
@@ -180,21 +860,377 @@ impl AudioRouter {
         *self.dsp_config.eq_high.write() = high.clamp(-12.0, 12.0);
     }
 
+    /// Set mid/side EQ enabled state
+    pub fn set_ms_eq_enabled(&self, enabled: bool) {
+        *self.dsp_config.ms_eq_enabled.write() = enabled;
+    }
+
+    /// Set EQ shelf slope S (0.1 to 2.0, 1.0 = default; above 1 overshoots)
+    pub fn set_eq_shelf_slope(&self, slope: f32) {
+        *self.dsp_config.eq_shelf_slope.write() = slope.clamp(0.1, 2.0);
+    }
+
+    /// Set the EQ band frequencies in Hz; None keeps a band's default
+    /// (200 Hz / 1 kHz / 4 kHz). The DSP chain keeps them below Nyquist.
+    pub fn set_eq_freqs(&self, low: Option<f32>, mid: Option<f32>, high: Option<f32>) {
+        let (default_low, default_mid, default_high) = ThreeBandEq::DEFAULT_FREQS;
+        let freq = |hz: Option<f32>, default: f32| hz.unwrap_or(default).clamp(20.0, 20000.0);
+        *self.dsp_config.eq_freqs.write() = (freq(low, default_low), freq(mid, default_mid), freq(high, default_high));
+    }
+
+    /// EQ (low, mid, high) band frequencies in Hz
+    pub fn eq_freqs(&self) -> (f32, f32, f32) {
+        *self.dsp_config.eq_freqs.read()
+    }
+
+    /// Set pre/de-emphasis enabled
+    pub fn set_emphasis_enabled(&self, enabled: bool) {
+        *self.dsp_config.emphasis_enabled.write() = enabled;
+    }
+
+    /// Set emphasis shelf frequency (20 to 20000 Hz) and amount (0 to +12 dB)
+    pub fn set_emphasis(&self, freq: f32, gain_db: f32) {
+        *self.dsp_config.emphasis.write() = (freq.clamp(20.0, 20000.0), gain_db.clamp(0.0, 12.0));
+    }
+
+    /// Set mid/side EQ gains as [low, mid, high] dB (-12 to +12)
+    pub fn set_ms_eq(&self, mid: [f32; 3], side: [f32; 3]) {
+        *self.dsp_config.ms_eq_mid.write() = mid.map(|db| db.clamp(-12.0, 12.0));
+        *self.dsp_config.ms_eq_side.write() = side.map(|db| db.clamp(-12.0, 12.0));
+    }
+
+    /// Follow the Windows default render device for `role` (applied on next start)
+    pub fn set_source_role(&mut self, role: Option<SourceRole>) {
+        self.source_role = role;
+    }
+
+    /// Switch the source to the default render device when the current one
+    /// is held in exclusive mode (see `poll_exclusive_source`)
+    pub fn set_exclusive_fallback(&mut self, enabled: bool) {
+        self.exclusive_fallback = enabled;
+    }
+
+    /// Set the channel count the source is expected to deliver (None = any)
+    pub fn set_expected_source_channels(&self, channels: Option<u16>) {
+        *self.dsp_config.expected_source_channels.write() = channels;
+    }
+
+    /// Silence routing while the source delivers fewer channels than expected
+    pub fn set_pause_on_channel_mismatch(&self, enabled: bool) {
+        *self.dsp_config.pause_on_channel_mismatch.write() = enabled;
+    }
+
+    /// Set what happens when a configured source channel is missing from the stream
+    pub fn set_missing_channel_behavior(&self, behavior: MissingChannelBehavior) {
+        *self.dsp_config.missing_channel_behavior.write() = behavior;
+    }
+
+    /// Invert the left output's phase (diagnostic, not persisted)
+    pub fn set_diag_phase_flip_left(&self, enabled: bool) {
+        *self.dsp_config.diag_phase_flip_left.write() = enabled;
+    }
+
+    /// Collapse the routed output to mono (diagnostic, not persisted)
+    pub fn set_diag_mono(&self, enabled: bool) {
+        *self.dsp_config.diag_mono.write() = enabled;
+    }
+
+    /// Skip every DSP stage for an A/B against the raw routing; the effect
+    /// settings themselves are left alone (not persisted)
+    pub fn set_dsp_bypass(&self, enabled: bool) {
+        *self.dsp_config.dsp_bypass.write() = enabled;
+    }
+
     /// Set upmix (pseudo-surround) enabled
     pub fn set_upmix_enabled(&self, enabled: bool) {
         *self.dsp_config.upmix_enabled.write() = enabled;
     }
 
+    /// Set how the upmix is used (full surround applies to quad output from stereo sources)
+    pub fn set_upmix_mode(&self, mode: UpmixMode) {
+        *self.dsp_config.upmix_full_surround.write() = mode == UpmixMode::FullSurround;
+    }
+
     /// Set upmix strength (1.0 to 10.0)
     pub fn set_upmix_strength(&self, strength: f32) {
         *self.dsp_config.upmix_strength.write() = strength.clamp(1.0, 10.0);
     }
 
+    /// Set upmix spaciousness delay in ms (0 to 30)
+    pub fn set_upmix_ambience_ms(&self, ms: f32) {
+        *self.dsp_config.upmix_ambience_ms.write() = ms.clamp(0.0, Upmixer::MAX_AMBIENCE_MS);
+    }
+
+    /// Set upmix rear alignment delay in ms (0 to 50)
+    pub fn set_upmix_rear_delay_ms(&self, ms: f32) {
+        *self.dsp_config.upmix_rear_delay_ms.write() = ms.clamp(0.0, Upmixer::MAX_REAR_DELAY_MS);
+    }
+
+    /// Set level meter weighting (affects metering only)
+    pub fn set_meter_weighting(&self, weighting: MeterWeighting) {
+        *self.dsp_config.meter_a_weighting.write() = weighting == MeterWeighting::A;
+    }
+
+    /// Set how often the level meter is published, 10 to 1000 ms
+    pub fn set_meter_update_ms(&self, ms: f32) {
+        *self.dsp_config.meter_update_ms.write() = ms.clamp(10.0, 1000.0);
+    }
+
+    /// Set DSP dry/wet mix (0.0 = unprocessed, 1.0 = fully processed)
+    pub fn set_dry_wet(&self, mix: f32) {
+        *self.dsp_config.dry_wet.write() = mix.clamp(0.0, 1.0);
+    }
+
+    /// Enable the protective subsonic high-pass on the output
+    pub fn set_subsonic_filter(&self, enabled: bool) {
+        *self.dsp_config.subsonic_filter.write() = enabled;
+    }
+
+    /// Set the subsonic high-pass cutoff (20 to 80 Hz)
+    pub fn set_subsonic_cutoff_hz(&self, hz: f32) {
+        *self.dsp_config.subsonic_cutoff_hz.write() = hz.clamp(20.0, 80.0);
+    }
+
+    /// Set loudness leveler (compander) enabled
+    pub fn set_compander_enabled(&self, enabled: bool) {
+        *self.dsp_config.compander_enabled.write() = enabled;
+    }
+
+    /// Set leveler envelope attack/release in ms
+    pub fn set_compander_times(&self, attack_ms: f32, release_ms: f32) {
+        *self.dsp_config.compander_times.write() = (attack_ms.clamp(0.1, 500.0), release_ms.clamp(1.0, 5000.0));
+    }
+
+    /// Set slow auto-gain (AGC) enabled
+    pub fn set_agc_enabled(&self, enabled: bool) {
+        *self.dsp_config.agc_enabled.write() = enabled;
+    }
+
+    /// Set AGC target RMS level in dBFS (-40 to -6)
+    pub fn set_agc_target_db(&self, db: f32) {
+        *self.dsp_config.agc_target_db.write() = db.clamp(-40.0, -6.0);
+    }
+
+    /// Set output limiter enabled
+    pub fn set_limiter_enabled(&self, enabled: bool) {
+        *self.dsp_config.limiter_enabled.write() = enabled;
+    }
+
+    /// Set the output limiter ceiling (-12 to 0 dBFS)
+    pub fn set_limiter_ceiling_db(&self, db: f32) {
+        *self.dsp_config.limiter_ceiling_db.write() = db.clamp(-12.0, 0.0);
+    }
+
+    /// Set output limiter placement (stereo bus or per channel)
+    pub fn set_limiter_mode(&self, mode: LimiterMode) {
+        *self.dsp_config.limiter_per_channel.write() = mode == LimiterMode::PerChannel;
+    }
+
+    /// Seed the source endpoint/format cache (from config at startup)
+    pub fn set_source_format_cache(&self, cache: Option<SourceFormatCache>) {
+        *self.dsp_config.source_format_cache.write() = cache;
+    }
+
+    /// Source endpoint/format cache as last verified by the capture thread
+    pub fn source_format_cache(&self) -> Option<SourceFormatCache> {
+        self.dsp_config.source_format_cache.read().clone()
+    }
+
+    /// Whether disabling keeps the output stream playing silence
+    fn stream_kept_alive(&self) -> bool {
+        self.keep_stream_alive || self.capturable_output
+    }
+
+    /// Expose the processed output as a capture source through a virtual
+    /// cable target: disabling keeps the cable streaming silence so apps
+    /// recording it see a continuous stream (applied on next disable)
+    pub fn set_capturable_output(&mut self, enabled: bool) {
+        self.capturable_output = enabled;
+        if !self.stream_kept_alive() && self.output_idle.load(Ordering::Relaxed) {
+            self.stop();
+        }
+    }
+
+    /// Set the soft-knee limit on each routed channel (ahead of the upmix sum)
+    pub fn set_per_channel_limit(&self, enabled: bool) {
+        *self.dsp_config.per_channel_limit.write() = enabled;
+    }
+
+    /// Set upmix headroom normalization enabled
+    pub fn set_normalize_upmix(&self, enabled: bool) {
+        *self.dsp_config.normalize_upmix.write() = enabled;
+    }
+
+    /// Set the dither on the upmix tail (bit depth 8-24, noise shaping on/off)
+    pub fn set_upmix_dither(&self, enabled: bool, bits: u32, noise_shaping: bool) {
+        *self.dsp_config.upmix_dither.write() = enabled;
+        *self.dsp_config.upmix_dither_bits.write() = bits.clamp(8, 24);
+        *self.dsp_config.upmix_dither_shaping.write() = noise_shaping;
+    }
+
+    /// Set the steered centre channel for 5.1 output (level 0.0-1.0)
+    pub fn set_upmix_center(&self, enabled: bool, level: f32) {
+        *self.dsp_config.upmix_center_enabled.write() = enabled;
+        *self.dsp_config.upmix_center_level.write() = level.clamp(0.0, 1.0);
+    }
+
     /// Set master volume sync enabled
     pub fn set_sync_master_volume(&self, enabled: bool) {
         *self.dsp_config.sync_master_volume.write() = enabled;
     }
 
+    /// Limit how fast the synced master volume follows Windows, in dB per
+    /// second (0 = follow instantly)
+    pub fn set_volume_slew_db_per_sec(&self, db_per_sec: f32) {
+        *self.dsp_config.volume_slew_db_per_sec.write() = db_per_sec.clamp(0.0, 1000.0);
+    }
+
+    /// Emergency mute of all routed output (applied on the next capture buffer)
+    pub fn set_panic_muted(&self, muted: bool) {
+        *self.dsp_config.panic_muted.write() = muted;
+    }
+
+    /// Set the input gain in dB (-12 to +24), applied to the captured signal
+    /// before source selection and all DSP
+    pub fn set_input_gain_db(&self, db: f32) {
+        let db = db.clamp(-12.0, 24.0);
+        if db > INPUT_GAIN_WARN_DB {
+            warn!("Input gain {:+} dB leaves little headroom: loud passages may clip before the limiter", db);
+        }
+        *self.dsp_config.input_gain_db.write() = db;
+    }
+
+    /// Mask sudden sample-to-sample jumps (clicks) in the captured signal
+    pub fn set_declick(&self, enabled: bool) {
+        *self.dsp_config.declick.write() = enabled;
+    }
+
+    /// Call before changing several settings at once (e.g. a profile switch):
+    /// the capture thread fades out, applies everything set after this call
+    /// together, and fades back in
+    pub fn begin_preset_change(&self) {
+        self.dsp_config.preset_pending.store(true, Ordering::Relaxed);
+    }
+
+    /// Silence held when the followed default device changes, covering the
+    /// re-route (0 to 5000 ms, 0 = off)
+    pub fn set_device_change_mute_ms(&self, ms: f32) {
+        *self.dsp_config.device_change_mute_ms.write() = ms.clamp(0.0, 5000.0);
+    }
+
+    /// Set the fade-out/fade-in length for preset switches (0 to 200 ms, 0 = instant)
+    pub fn set_preset_fade_ms(&self, ms: f32) {
+        *self.dsp_config.preset_fade_ms.write() = ms.clamp(0.0, 200.0);
+    }
+
+    /// Silence routing while the source is muted in Windows, even without volume sync
+    pub fn set_respect_system_mute(&self, enabled: bool) {
+        *self.dsp_config.respect_system_mute.write() = enabled;
+    }
+
+    /// Set how much audio (ms) to buffer before output starts (applied on next start)
+    pub fn set_prefill_ms(&mut self, ms: f32) {
+        self.prefill_ms = ms.clamp(0.0, 100.0);
+    }
+
+    /// Set the output fade-in length in ms (0 to 500, applied on next start)
+    pub fn set_soft_start_ms(&mut self, ms: f32) {
+        self.soft_start_ms = ms.clamp(0.0, 500.0);
+    }
+
+    /// Set the fade-to-zero length on stop in ms (0 to 100, applied on next start)
+    pub fn set_stop_ramp_ms(&mut self, ms: f32) {
+        self.stop_ramp_ms = ms.clamp(0.0, 100.0);
+    }
+
+    /// Prefer the source rate for the target stream, or always use the
+    /// target's own mix rate (applied on next start)
+    pub fn set_match_source_rate(&mut self, enabled: bool) {
+        self.match_source_rate = enabled;
+    }
+
+    /// Set capture format overrides (applied on next start)
+    pub fn set_capture_overrides(&mut self, overrides: CaptureOverrides) {
+        self.capture_overrides = overrides;
+    }
+
+    /// Set an explicit output buffer size in frames, None for the driver
+    /// default (applied on next start)
+    pub fn set_output_buffer_frames(&mut self, frames: Option<u32>) {
+        self.output_buffer_frames = frames;
+    }
+
+    /// Experimental direct mode: process and render on the capture thread
+    /// without the ring buffer when source and target rates match
+    /// (applied on next start). Best-effort, may glitch on drifting clocks.
+    pub fn set_direct_mode(&mut self, enabled: bool) {
+        self.direct_mode = enabled;
+    }
+
+    /// Keep filter and delay state across capture restarts (applied on next start)
+    pub fn set_preserve_dsp_state(&mut self, enabled: bool) {
+        self.preserve_dsp_state = enabled;
+    }
+
+    /// Keep the output stream running on silence while routing is disabled
+    /// (capture is paused as with keep_warm), for amplifiers that click or
+    /// take seconds to wake when their input stream stops
+    pub fn set_keep_stream_alive(&mut self, enabled: bool) {
+        self.keep_stream_alive = enabled;
+        if !self.stream_kept_alive() && self.output_idle.load(Ordering::Relaxed) {
+            self.stop();
+        }
+    }
+
+    /// Keep the capture thread, its WASAPI client and the output stream open
+    /// while routing is disabled, so re-enabling is immediate. Holds the
+    /// device handles for as long as the app runs.
+    pub fn set_keep_warm(&mut self, enabled: bool) {
+        self.keep_warm = enabled;
+        if !enabled && !self.stream_kept_alive() && self.loopback.as_ref().is_some_and(|l| l.is_paused()) {
+            self.stop();
+        }
+    }
+
+    /// Set output channel count, 2, 4 or 6 (applied on next start). Quad puts
+    /// FL/FR on the front pair and the routed channels on the rear pair; 5.1
+    /// adds the steered centre and a silent LFE between them.
+    pub fn set_output_channels(&mut self, channels: u16) {
+        self.output_channels = match channels {
+            2 | 4 | 6 => channels,
+            other => {
+                warn!("Unsupported output_channels = {}, using 2", other);
+                2
+            }
+        };
+    }
+
+    /// Disable the final hard clamp (output may exceed full scale)
+    pub fn set_disable_output_clamp(&self, disabled: bool) {
+        if disabled {
+            warn!("!!! Output clamp DISABLED: samples may exceed full scale. Use only with external limiting !!!");
+        }
+        *self.dsp_config.disable_output_clamp.write() = disabled;
+    }
+
+    /// Clamp overloads with one shared gain for both channels instead of per channel
+    pub fn set_linked_clamp(&self, linked: bool) {
+        *self.dsp_config.linked_clamp.write() = linked;
+    }
+
+    /// Set auto volume reduction on sustained clipping. The router only emits
+    /// `RouterEvent::LevelSafetyReduction`; the subscriber owning the volume
+    /// setting lowers it (see `subscribe`).
+    pub fn set_level_safety(&self, enabled: bool) {
+        *self.dsp_config.level_safety.write() = enabled;
+    }
+
+    /// Set resampler quality profile (applied on next start)
+    pub fn set_resampler_quality(&mut self, quality: ResamplerQuality) {
+        self.resampler_quality = quality;
+    }
+
+    /// List output devices sorted by name so menu positions and `--list` indices are stable
     pub fn list_output_devices(&self) -> Result<Vec<AudioDevice>> {
         let mut devices = Vec::new();
         for device in self.host.output_devices().context("Failed to get output devices")? {
@@ -208,9 +1244,36 @@ impl AudioRouter {
                 }
             }
         }
+        // cpal/WASAPI enumeration order is not stable across runs
+        devices.sort_by(|a, b| a.name.cmp(&b.name));
         Ok(devices)
     }
 
+    /// Wait up to `timeout` for an output device matching `name` to appear,
+    /// polling with the reconnect backoff. Returns whether it was found.
+    pub fn wait_for_output_device(&self, name: &str, timeout: Duration) -> bool {
+        if self.find_output_device(name).is_some() {
+            return true;
+        }
+        info!("Waiting up to {:?} for output device '{}' to appear", timeout, name);
+        let deadline = Instant::now() + timeout;
+        let mut backoff = RECONNECT_BACKOFF_INITIAL;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                warn!("Output device '{}' did not appear within {:?}", name, timeout);
+                return false;
+            }
+            std::thread::sleep(backoff.min(deadline - now));
+            backoff = (backoff * 2).min(RECONNECT_BACKOFF_MAX);
+            if self.find_output_device(name).is_some() {
+                info!("Output device '{}' is ready", name);
+                return true;
+            }
+        }
+    }
+
+    /// List input devices sorted by name (see `list_output_devices`)
     pub fn list_input_devices(&self) -> Result<Vec<AudioDevice>> {
         let mut devices = Vec::new();
         for device in self.host.input_devices().context("Failed to get input devices")? {
@@ -224,6 +1287,8 @@ impl AudioRouter {
                 }
             }
         }
+        // cpal/WASAPI enumeration order is not stable across runs
+        devices.sort_by(|a, b| a.name.cmp(&b.name));
         Ok(devices)
     }
 
@@ -235,6 +1300,10 @@ impl AudioRouter {
         *self.swap_channels.write() = swap;
     }
 
+    pub fn set_swap_scope(&self, scope: SwapScope) {
+        *self.swap_scope.write() = scope;
+    }
+
     pub fn set_balance(&self, balance: f32) {
         *self.balance.write() = balance.clamp(-1.0, 1.0);
     }
@@ -258,7 +1327,9 @@ impl AudioRouter {
         TestTonePlayer {
             host: cpal::default_host(),
             swap_channels: self.swap_channels.clone(),
+            swap_scope: self.swap_scope.clone(),
             target_device_name: self.target_device_name.clone(),
+            output_channels: self.output_channels,
         }
     }
 
@@ -286,12 +1357,21 @@ impl AudioRouter {
         self.right_channel.write().volume = volume;
     }
 
+    /// Set left output calibration trim (in dB, -6 to +6)
+    pub fn set_left_trim_db(&self, db: f32) {
+        self.left_channel.write().trim_db = db.clamp(-6.0, 6.0);
+    }
+
+    /// Set right output calibration trim (in dB, -6 to +6)
+    pub fn set_right_trim_db(&self, db: f32) {
+        self.right_channel.write().trim_db = db.clamp(-6.0, 6.0);
+    }
+
     #[allow(dead_code)]
     pub fn get_current_channels(&self) -> u32 {
         self.current_channels.load(Ordering::Relaxed)
     }
 
-    #[allow(dead_code)]
     pub fn is_running(&self) -> bool {
         self.running.load(Ordering::Relaxed)
     }
@@ -306,82 +1386,700 @@ impl AudioRouter {
     /// source_name: Output device to capture from (e.g., "Speakers")
     /// target_name: Output device to play to (e.g., "2nd output")
     pub fn start_loopback(&mut self, source_name: &str, target_name: &str) -> Result<()> {
+        if self.resume_warm(source_name, target_name) {
+            return Ok(());
+        }
         self.stop();
         
         info!("Starting loopback routing: {} -> {}", source_name, target_name);
 
-        // Store target device name for test tones
-        self.target_device_name = Some(target_name.to_string());
-
         // Find output device for playback
         let output_device = self.find_output_device(target_name)
             .context(format!("Output device not found: {}", target_name))?;
+        let output_name = output_device.name()?;
 
-        info!("Output device: {}", output_device.name()?);
+        info!("Output device: {}", output_name);
 
-        // Get output config
-        let output_supported = output_device.default_output_config()?;
-        let sample_rate = output_supported.sample_rate();
+        // Rendering into the captured endpoint feeds the output back into
+        // itself (e.g. capturing and targeting CABLE Input). Compare the
+        // endpoints the names resolve to, since both match by substring
+        if self.find_output_device(source_name).and_then(|d| d.name().ok()).is_some_and(|s| s == output_name) {
+            anyhow::bail!("Source and target are the same device: {}", output_name);
+        }
+
+        let device_changed = self.source_device_name.as_ref().is_some_and(|s| s != source_name)
+            || self.target_device_name.as_ref().is_some_and(|t| t != target_name);
+
+        // Store device names for test tones and reconnection
+        self.source_device_name = Some(source_name.to_string());
+        self.target_device_name = Some(target_name.to_string());
+
+        // Get output config: with match_source_rate, the source rate when the
+        // target supports it, else the supported rate closest to it; otherwise
+        // (or when nothing matches) the device default
+        // A cached source format skips enumerating the source; the capture
+        // thread verifies it and resamples if the live rate differs
+        let cached_source = self.dsp_config.source_format_cache.read().clone()
+            .filter(|cache| self.source_role.is_none() && cache.device == source_name);
+        let source_config = match cached_source {
+            Some(_) => None,
+            None => self.find_output_device(source_name).and_then(|d| d.default_output_config().ok()),
+        };
+        let source_rate = match cached_source {
+            Some(ref cache) => Some(SampleRate(cache.sample_rate)),
+            None => source_config.as_ref().map(|c| c.sample_rate()),
+        };
+        let target_config = output_device.default_output_config()?;
+        let negotiated = source_rate
+            .filter(|_| self.match_source_rate)
+            .and_then(|rate| negotiate_output_rate(&output_device, rate));
+        let sample_rate = match negotiated {
+            Some(rate) if Some(rate) == source_rate => {
+                info!("Output opened at the source rate: {} Hz (no resampling)", rate.0);
+                rate
+            }
+            Some(rate) => {
+                info!(
+                    "Target does not support the source rate ({} Hz), negotiated {} Hz",
+                    source_rate.map(|r| r.0).unwrap_or(0), rate.0
+                );
+                rate
+            }
+            None => {
+                let rate = target_config.sample_rate();
+                info!("Using default output sample rate: {} Hz", rate.0);
+                rate
+            }
+        };
         
+        let output_channels = effective_output_channels(&output_device, self.output_channels);
+        if output_channels != self.output_channels {
+            warn!("Target has fewer than {} channels, falling back to {}ch output", self.output_channels, output_channels);
+        }
+        let buffer_size = match self.output_buffer_frames {
+            Some(requested) => {
+                let granted = clamp_buffer_frames(&output_device, output_channels, requested);
+                info!("Output buffer: requested {} frames, granted {} frames", requested, granted);
+                self.output_buffer_ms = granted as f32 * 1000.0 / sample_rate.0 as f32;
+                BufferSize::Fixed(granted)
+            }
+            None => {
+                self.output_buffer_ms = OUTPUT_BUFFER_MS;
+                BufferSize::Default
+            }
+        };
+        let device_channels = stream_channels(&output_device, output_channels, sample_rate);
+        if device_channels != output_channels {
+            info!(
+                "Target does not accept {} ch f32 at {} Hz, opening its {} ch layout (extra channels silent)",
+                output_channels, sample_rate.0, device_channels
+            );
+        }
         let output_config = StreamConfig {
-            channels: 2, // Always output stereo
+            channels: device_channels,
             sample_rate,
-            buffer_size: cpal::BufferSize::Default,
+            buffer_size,
         };
+        info!(
+            "Format chain: source {} -> stream {}ch / {} Hz / f32 -> target mix {}{}",
+            cached_source.as_ref().map(|cache| format!("{} (cached)", cache))
+                .or_else(|| source_config.as_ref().map(describe_format))
+                .unwrap_or_else(|| "unknown".to_string()),
+            device_channels, sample_rate.0,
+            describe_format(&target_config),
+            match source_rate {
+                Some(rate) if rate != sample_rate => format!(" (resampling {} -> {} Hz)", rate.0, sample_rate.0),
+                _ => String::new(),
+            }
+        );
+
+        self.running.store(true, Ordering::Relaxed);
+
+        // Direct mode needs both devices on the same rate; otherwise use the buffered path
+        if self.direct_mode {
+            if source_rate == Some(sample_rate) {
+                if let Some(loopback) = self.start_direct(source_name, target_name, sample_rate.0, output_channels) {
+                    self.loopback = Some(loopback);
+                    self.direct_active = true;
+                    self.output_buffer_ms = CAPTURE_BUFFER_MS;
+                    self.announce_started(source_name, target_name, device_changed);
+                    return Ok(());
+                }
+            } else {
+                warn!("Direct mode needs matching source and target sample rates, using buffered output");
+            }
+        }
 
         // Create ring buffer - 100ms buffer for low latency
-        let buffer_samples = (sample_rate.0 as f32 * 0.1) as usize * 2; // 100ms stereo
+        let buffer_samples = (sample_rate.0 as f32 * 0.1) as usize * output_channels as usize;
         let ring_buffer = HeapRb::<f32>::new(buffer_samples);
         let (producer, mut consumer) = ring_buffer.split();
 
-        self.running.store(true, Ordering::Relaxed);
+        // Output silence until the ring buffer is primed to avoid startup underruns
+        let prefill_samples = ((sample_rate.0 as f32 * self.prefill_ms / 1000.0) as usize * output_channels as usize).min(buffer_samples);
+        let mut primed = prefill_samples == 0;
+
+        // Fade in the first frames of the new stream to avoid an amp pop
+        let soft_start_frames = (sample_rate.0 as f32 * self.soft_start_ms / 1000.0) as usize;
+        let mut soft_start_pos = 0;
 
         // Start loopback capture thread
         let mut loopback = LoopbackCapture::new();
         loopback.start(
             source_name,
+            self.source_role,
             sample_rate.0,  // Pass target sample rate for resampling
+            output_channels,
+            None,
+            self.resampler_quality,
+            self.capture_overrides,
             producer,
             self.current_channels.clone(),
             self.volume.clone(),
             self.swap_channels.clone(),
+            self.swap_scope.clone(),
             self.balance.clone(),
             self.left_channel.clone(),
             self.right_channel.clone(),
             self.dsp_config.clone(),
+            self.dsp_state.clone(),
+            self.preserve_dsp_state,
         )?;
 
-        // Build output stream
-        let output_stream = output_device.build_output_stream(
-            &output_config,
-            move |data: &mut [f32], _: &_| {
-                for sample in data.iter_mut() {
-                    *sample = consumer.try_pop().unwrap_or(0.0);
-                }
-            },
-            move |err| error!("Output stream error: {}", err),
-            None,
-        )?;
-
-        output_stream.play()?;
+        // Build output stream. Failures (e.g. target held in exclusive mode by
+        // another app) are flagged so `poll_reconnect` can resume routing later.
+        let output_failed = self.output_failed.clone();
+        let events = self.dsp_config.events.clone();
+        let dropouts = self.dsp_config.dropouts.clone();
+        let output_reset = self.output_reset.clone();
+        output_reset.store(false, Ordering::Relaxed);
+        let output_idle = self.output_idle.clone();
+        output_idle.store(false, Ordering::Relaxed);
+        let crossfade_slot = self.crossfade_slot.clone();
+        let crossfade_done = self.crossfade_done.clone();
+        let mut crossfade: Option<Crossfade> = None;
+        let output_stopping = self.output_stopping.clone();
+        let output_stopped = self.output_stopped.clone();
+        output_stopping.store(false, Ordering::Relaxed);
+        output_stopped.store(false, Ordering::Relaxed);
+        let stop_frames = (sample_rate.0 as f32 * self.stop_ramp_ms / 1000.0) as usize;
+        let mut stop_pos = 0usize;
+        let build_result = (|| -> Result<Stream> {
+            let stream = output_device.build_output_stream(
+                &output_config,
+                move |data: &mut [f32], _: &_| {
+                    let stopping = output_stopping.load(Ordering::Relaxed);
+                    if !stopping {
+                        // Resumed after a pause: the next pause ramps again
+                        stop_pos = 0;
+                    }
+                    if stopping && stop_pos >= stop_frames {
+                        data.fill(0.0);
+                        output_stopped.store(true, Ordering::Relaxed);
+                        return;
+                    }
+                    if output_idle.load(Ordering::Relaxed) {
+                        data.fill(0.0);
+                        if stopping {
+                            stop_pos = stop_frames;  // Already silent, nothing to ramp
+                        }
+                        return;
+                    }
+                    if output_reset.swap(false, Ordering::Relaxed) {
+                        consumer.clear();
+                        primed = prefill_samples == 0;
+                        soft_start_pos = 0;
+                    }
+                    if !primed {
+                        if consumer.occupied_len() < prefill_samples {
+                            data.fill(0.0);
+                            if stopping {
+                                stop_pos = stop_frames;
+                            }
+                            return;
+                        }
+                        primed = true;
+                    }
+                    // Never block the audio thread: a busy slot is picked up next time
+                    if crossfade.is_none() {
+                        crossfade = crossfade_slot.try_lock().and_then(|mut slot| slot.take());
+                    }
+                    let mut underrun = false;
+                    for frame in data.chunks_mut(device_channels as usize) {
+                        let (routed, extra) = frame.split_at_mut((output_channels as usize).min(frame.len()));
+                        for sample in routed.iter_mut() {
+                            *sample = consumer.try_pop().unwrap_or_else(|| {
+                                underrun = true;
+                                0.0
+                            });
+                        }
+                        if let Some(ref mut fade) = crossfade {
+                            fade.mix(routed, prefill_samples);
+                            if fade.finished() {
+                                // The new capture's buffer becomes the stream's own
+                                let fade = crossfade.take().expect("crossfade checked above");
+                                fade.outgoing_running.store(false, Ordering::Relaxed);
+                                consumer = fade.consumer;
+                                crossfade_done.store(true, Ordering::Relaxed);
+                            }
+                        }
+                        if soft_start_pos < soft_start_frames {
+                            let t = soft_start_pos as f32 / soft_start_frames as f32;
+                            let gain = 0.5 - 0.5 * (std::f32::consts::PI * t).cos();
+                            routed.iter_mut().for_each(|s| *s *= gain);
+                            soft_start_pos += 1;
+                        }
+                        if stopping {
+                            // Cosine fade-out that ends exactly at zero
+                            let gain = if stop_pos < stop_frames {
+                                0.5 + 0.5 * (std::f32::consts::PI * stop_pos as f32 / stop_frames as f32).cos()
+                            } else {
+                                0.0
+                            };
+                            routed.iter_mut().for_each(|s| *s *= gain);
+                            stop_pos += 1;
+                        }
+                        extra.fill(0.0);
+                    }
+                    if underrun {
+                        dropouts.fetch_add(1, Ordering::Relaxed);
+                    }
+                },
+                move |err| {
+                    error!("Output stream error: {}", err);
+                    if !output_failed.swap(true, Ordering::Relaxed) {
+                        events.emit(RouterEvent::OutputFailed);
+                    }
+                },
+                None,
+            )?;
+            stream.play()?;
+            Ok(stream)
+        })();
+        let output_stream = match build_result {
+            Ok(stream) => stream,
+            Err(e) => {
+                loopback.stop();
+                self.running.store(false, Ordering::Relaxed);
+                self.output_failed.store(true, Ordering::Relaxed);
+                self.dsp_config.events.emit(RouterEvent::OutputFailed);
+                return Err(e);
+            }
+        };
 
         self.output_stream = Some(output_stream);
         self.loopback = Some(loopback);
+        self.stream_format = Some((sample_rate.0, output_channels));
 
-        info!("Loopback routing started successfully");
+        self.announce_started(source_name, target_name, device_changed);
         Ok(())
     }
 
-    pub fn stop(&mut self) {
+    /// Switch routing to another source. With `source_crossfade_ms` set and a
+    /// buffered session running on `target_name`, a second capture starts
+    /// next to the running one (resampled to the current stream rate, with a
+    /// fresh DSP chain) and the output crossfades to it; the old capture
+    /// exits when the fade completes and `poll_crossfade` joins it.
+    /// Otherwise this restarts routing on the new source.
+    pub fn switch_source(&mut self, source_name: &str, target_name: &str) -> Result<()> {
+        let can_fade = self.source_crossfade_ms > 0.0
+            && self.is_running()
+            && !self.direct_active
+            && self.fading_capture.is_none()
+            && self.target_device_name.as_deref() == Some(target_name);
+        let (Some((sample_rate, output_channels)), true) = (self.stream_format, can_fade) else {
+            self.stop();
+            return self.start_loopback(source_name, target_name);
+        };
+
+        let buffer_samples = (sample_rate as f32 * 0.1) as usize * output_channels as usize;
+        let (producer, consumer) = HeapRb::<f32>::new(buffer_samples).split();
+        let dsp_state = Arc::new(Mutex::new(None));
+        let mut loopback = LoopbackCapture::new();
+        let started = loopback.start(
+            source_name,
+            self.source_role,
+            sample_rate,
+            output_channels,
+            None,
+            self.resampler_quality,
+            self.capture_overrides,
+            producer,
+            self.current_channels.clone(),
+            self.volume.clone(),
+            self.swap_channels.clone(),
+            self.swap_scope.clone(),
+            self.balance.clone(),
+            self.left_channel.clone(),
+            self.right_channel.clone(),
+            self.dsp_config.clone(),
+            dsp_state.clone(),
+            false,
+        );
+        if let Err(e) = started {
+            warn!("Could not start the new capture for a crossfade ({}), restarting routing", e);
+            self.stop();
+            return self.start_loopback(source_name, target_name);
+        }
+
+        let Some(outgoing) = self.loopback.replace(loopback) else {
+            unreachable!("a running buffered session has a capture");
+        };
+        self.crossfade_done.store(false, Ordering::Relaxed);
+        *self.crossfade_slot.lock() = Some(Crossfade {
+            consumer,
+            outgoing_running: outgoing.running_flag(),
+            frames: ((sample_rate as f32 * self.source_crossfade_ms / 1000.0) as usize).max(1),
+            pos: 0,
+            waited: 0,
+            max_wait: (sample_rate as f32 * CROSSFADE_MAX_WAIT_MS / 1000.0) as usize,
+        });
+        self.fading_capture = Some(outgoing);
+        self.dsp_state = dsp_state;
+        self.source_device_name = Some(source_name.to_string());
+        info!("Crossfading source to {} over {} ms", source_name, self.source_crossfade_ms);
+        self.announce_started(source_name, target_name, true);
+        Ok(())
+    }
+
+    /// Join the outgoing capture once a source crossfade has completed
+    pub fn poll_crossfade(&mut self) {
+        if self.crossfade_done.swap(false, Ordering::Relaxed) {
+            if let Some(mut outgoing) = self.fading_capture.take() {
+                outgoing.stop();
+                info!("Source crossfade complete");
+            }
+        }
+    }
+
+    /// Fade between sources over this long on a source switch (0 = restart)
+    pub fn set_source_crossfade_ms(&mut self, ms: f32) {
+        self.source_crossfade_ms = ms.clamp(0.0, 5000.0);
+    }
+
+    /// Start capture with the capture thread rendering directly to the target.
+    /// Returns None (after logging) if the target can't be opened for it.
+    fn start_direct(&mut self, source_name: &str, target_name: &str, sample_rate: u32, output_channels: u16) -> Option<LoopbackCapture> {
+        // The ring buffer is never used in direct mode
+        let (producer, _consumer) = HeapRb::<f32>::new(1).split();
+        let mut loopback = LoopbackCapture::new();
+        let result = loopback.start(
+            source_name,
+            self.source_role,
+            sample_rate,
+            output_channels,
+            Some(target_name),
+            self.resampler_quality,
+            self.capture_overrides,
+            producer,
+            self.current_channels.clone(),
+            self.volume.clone(),
+            self.swap_channels.clone(),
+            self.swap_scope.clone(),
+            self.balance.clone(),
+            self.left_channel.clone(),
+            self.right_channel.clone(),
+            self.dsp_config.clone(),
+            self.dsp_state.clone(),
+            self.preserve_dsp_state,
+        );
+        match result {
+            Ok(()) => {
+                warn!("Direct mode active (experimental): no ring buffer, may glitch if device clocks drift");
+                Some(loopback)
+            }
+            Err(e) => {
+                warn!("Direct mode unavailable ({}), using buffered output", e);
+                None
+            }
+        }
+    }
+
+    /// Resume a session paused by `pause` when it is for the same devices.
+    /// Returns false when a full start is needed.
+    fn resume_warm(&mut self, source_name: &str, target_name: &str) -> bool {
+        let paused = self.loopback.as_ref().is_some_and(|l| l.is_paused() && !l.is_finished());
+        if !paused
+            || self.source_device_name.as_deref() != Some(source_name)
+            || self.target_device_name.as_deref() != Some(target_name)
+        {
+            return false;
+        }
+        let Some(ref stream) = self.output_stream else {
+            return false;
+        };
+        // Drop the audio buffered before the pause and soft-start again
+        self.output_reset.store(true, Ordering::Relaxed);
+        if let Err(e) = stream.play() {
+            warn!("Failed to resume output stream ({}), reopening", e);
+            return false;
+        }
+        self.output_idle.store(false, Ordering::Relaxed);
+        if let Some(ref loopback) = self.loopback {
+            loopback.resume();
+        }
+        self.running.store(true, Ordering::Relaxed);
+        info!("Resuming loopback routing: {} -> {}", source_name, target_name);
+        self.announce_started(source_name, target_name, false);
+        true
+    }
+
+    fn announce_started(&mut self, source_name: &str, target_name: &str, device_changed: bool) {
+        info!("Loopback routing started successfully");
+        self.started_at = Some(Instant::now());
+        self.dsp_config.dropouts.store(0, Ordering::Relaxed);
+        self.dsp_config.source_exclusive.store(false, Ordering::Relaxed);
+        if device_changed {
+            self.dsp_config.events.emit(RouterEvent::DeviceChanged {
+                source: source_name.to_string(),
+                target: target_name.to_string(),
+            });
+        }
+        self.dsp_config.events.emit(RouterEvent::Started {
+            source: source_name.to_string(),
+            target: target_name.to_string(),
+        });
+    }
+
+    /// Retry routing after the output stream failed (e.g. the target was
+    /// opened exclusively by another app), with exponential backoff.
+    /// Returns true while still waiting for the target to become available.
+    pub fn poll_reconnect(&mut self) -> bool {
+        if !self.output_failed.load(Ordering::Relaxed) {
+            return false;
+        }
+        let now = Instant::now();
+        if self.next_reconnect.is_some_and(|t| now < t) {
+            return true;
+        }
+        let (Some(source), Some(target)) = (self.source_device_name.clone(), self.target_device_name.clone()) else {
+            return false;
+        };
+
+        match self.start_loopback(&source, &target) {
+            Ok(()) => {
+                info!("Target available again, routing resumed");
+                self.reconnect_backoff = RECONNECT_BACKOFF_INITIAL;
+                false
+            }
+            Err(e) => {
+                warn!("Target busy, waiting ({}), retry in {:?}", e, self.reconnect_backoff);
+                self.output_failed.store(true, Ordering::Relaxed);
+                self.next_reconnect = Some(now + self.reconnect_backoff);
+                self.reconnect_backoff = (self.reconnect_backoff * 2).min(RECONNECT_BACKOFF_MAX);
+                true
+            }
+        }
+    }
+
+    /// If the source is held in exclusive mode and fallback is enabled, switch
+    /// to the system default render device (when it differs from both source
+    /// and target). Returns the new source name on a successful switch.
+    pub fn poll_exclusive_source(&mut self) -> Option<String> {
+        if !self.exclusive_fallback || !self.source_in_exclusive_use() {
+            return None;
+        }
+        let default_name = self.host.default_output_device()?.name().ok()?;
+        let source = self.source_device_name.clone()?;
+        let target = self.target_device_name.clone()?;
+        if default_name == source || default_name == target {
+            return None;
+        }
+
+        warn!("Switching source to default device '{}' while '{}' is in exclusive use", default_name, source);
+        self.source_role = None;
+        match self.start_loopback(&default_name, &target) {
+            Ok(()) => Some(default_name),
+            Err(e) => {
+                error!("Failed to switch source: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Re-route to the new default device after the capture thread saw the
+    /// followed role switch devices. The output stays muted for
+    /// `device_change_mute_ms` after the restart. Returns the new source name.
+    pub fn poll_default_device_change(&mut self) -> Option<String> {
+        if !self.dsp_config.default_device_changed.swap(false, Ordering::Relaxed) || !self.is_running() {
+            return None;
+        }
+        let role = self.source_role?;
+        let source = default_render_device_name(role)?;
+        let target = self.target_device_name.clone()?;
+        let guard_ms = *self.dsp_config.device_change_mute_ms.read();
+        if guard_ms > 0.0 {
+            *self.dsp_config.device_change_guard.write() = Some(Instant::now() + Duration::from_secs_f32(guard_ms / 1000.0));
+        }
+        info!("Default {:?} device is now '{}', re-routing", role, source);
+        match self.start_loopback(&source, &target) {
+            Ok(()) => Some(source),
+            Err(e) => {
+                error!("Failed to re-route to the new default device: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Restart routing if the capture thread died or stopped heartbeating.
+    /// Returns true if a restart was attempted.
+    pub fn check_capture_health(&mut self) -> bool {
+        if !self.is_running() || self.output_failed.load(Ordering::Relaxed) {
+            return false;
+        }
+        if self.last_health_check.elapsed() < HEALTH_CHECK_INTERVAL {
+            return false;
+        }
+        self.last_health_check = Instant::now();
+
+        let Some(ref loopback) = self.loopback else {
+            return false;
+        };
+        let finished = loopback.is_finished();
+        if loopback.take_heartbeat() && !finished {
+            return false;
+        }
+        let (Some(source), Some(target)) = (self.source_device_name.clone(), self.target_device_name.clone()) else {
+            return false;
+        };
+
+        warn!("Capture thread {}, restarting routing", if finished { "exited" } else { "stalled" });
+        match self.start_loopback(&source, &target) {
+            Ok(()) => self.dsp_config.events.emit(RouterEvent::CaptureRestarted),
+            Err(e) => error!("Auto-restart failed: {}", e),
+        }
+        true
+    }
+
+    /// Disable routing. With keep_warm the capture client and output stream
+    /// are paused, with keep_stream_alive the output keeps playing silence;
+    /// `start_loopback` on the same devices resumes them. Otherwise (and in
+    /// direct mode) this is `stop`.
+    pub fn pause(&mut self) {
+        if self.loopback.as_ref().is_some_and(|l| l.is_paused()) {
+            return;
+        }
+        if !(self.keep_warm || self.stream_kept_alive()) || self.direct_active || !self.is_running() {
+            self.stop();
+            return;
+        }
+        // Fade to zero before the callback switches to plain silence or the
+        // stream is paused mid-signal
+        self.ramp_output_down();
+        let paused = match (&self.loopback, &self.output_stream) {
+            (Some(loopback), Some(_)) if self.stream_kept_alive() => {
+                loopback.pause();
+                self.output_idle.store(true, Ordering::Relaxed);
+                Ok(())
+            }
+            (Some(loopback), Some(stream)) => {
+                loopback.pause();
+                stream.pause().map_err(anyhow::Error::from)
+            }
+            _ => Err(anyhow::anyhow!("no active session")),
+        };
+        if let Err(e) = paused {
+            warn!("Could not pause routing ({}), stopping instead", e);
+            self.stop();
+            return;
+        }
+        // Silent now; a resume plays (and a later pause ramps) normally again
+        self.output_stopping.store(false, Ordering::Relaxed);
+        self.output_stopped.store(false, Ordering::Relaxed);
         self.running.store(false, Ordering::Relaxed);
+        self.next_reconnect = None;
+        self.started_at = None;
+        if self.stream_kept_alive() {
+            info!("Audio routing paused (output stream kept alive on silence)");
+        } else {
+            info!("Audio routing paused (capture kept warm)");
+        }
+        self.dsp_config.events.emit(RouterEvent::Stopped);
+    }
+
+    /// Fade the output to zero while capture still feeds it, so the stream
+    /// never ends on a DC step. Waits for the callback to finish the ramp, up
+    /// to `stop_ramp_ms` plus `STOP_RAMP_GRACE`.
+    fn ramp_output_down(&self) {
+        if self.output_stream.is_none() || self.stop_ramp_ms <= 0.0 {
+            return;
+        }
+        self.output_stopping.store(true, Ordering::Relaxed);
+        let deadline = Instant::now() + Duration::from_secs_f32(self.stop_ramp_ms / 1000.0) + STOP_RAMP_GRACE;
+        while !self.output_stopped.load(Ordering::Relaxed) && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    pub fn stop(&mut self) {
+        let was_running = self.running.swap(false, Ordering::Relaxed);
+        let output_failed = self.output_failed.swap(false, Ordering::Relaxed);
+        self.next_reconnect = None;
+        self.direct_active = false;
+        self.started_at = None;
+
+        // A failed stream runs no callback to fade
+        if !output_failed {
+            self.ramp_output_down();
+        }
         
         if let Some(mut loopback) = self.loopback.take() {
             loopback.stop();
         }
+        if let Some(mut outgoing) = self.fading_capture.take() {
+            outgoing.stop();
+        }
         if let Some(stream) = self.output_stream.take() {
             drop(stream);
         }
+        *self.crossfade_slot.lock() = None;
+        self.crossfade_done.store(false, Ordering::Relaxed);
+        self.stream_format = None;
+        self.output_idle.store(false, Ordering::Relaxed);
+        self.output_stopping.store(false, Ordering::Relaxed);
+        self.output_stopped.store(false, Ordering::Relaxed);
         
         info!("Audio routing stopped");
+        if was_running {
+            self.dsp_config.events.emit(RouterEvent::Stopped);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_map_from_mask() {
+        // 5.1: FL FR FC LFE BL BR
+        let map = ChannelMap::from_mask(0x3F, 6).unwrap();
+        assert_eq!(map.front(), (0, 1));
+        assert_eq!((map.index(ChannelSource::RL), map.index(ChannelSource::RR)), (4, 5));
+
+        // 7.1 with back and side pairs: the back pair is the rear
+        let map = ChannelMap::from_mask(0x63F, 8).unwrap();
+        assert_eq!((map.index(ChannelSource::RL), map.index(ChannelSource::RR)), (4, 5));
+
+        // 5.1 (side): FL FR FC LFE SL SR falls back to the side pair
+        let map = ChannelMap::from_mask(0x60F, 6).unwrap();
+        assert_eq!((map.index(ChannelSource::RL), map.index(ChannelSource::RR)), (4, 5));
+        assert!(map.present(ChannelSource::RL));
+
+        // The mask has to describe exactly `channels` channels
+        assert_eq!(ChannelMap::from_mask(0x3F, 8), None);
+        assert_eq!(ChannelMap::from_mask(0x3, 1), None);
+    }
+
+    #[test]
+    fn test_channel_map_mono_fallback() {
+        // FC-only mono has no front pair, so capture falls back to positional
+        assert_eq!(ChannelMap::from_mask(0x4, 1), None);
+        let map = ChannelMap::positional(1);
+        assert_eq!(map.front(), (0, 0));
+        for source in [ChannelSource::FL, ChannelSource::FR, ChannelSource::RL, ChannelSource::RR, ChannelSource::Index(3)] {
+            assert_eq!(map.index(source), 0, "{:?}", source);
+        }
+        assert!(!map.present(ChannelSource::RL));
     }
 }
@@ -1,14 +1,125 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tracing::{info, warn};
+
+/// Explicit config path from `--config`, takes precedence over the default lookup
+static CONFIG_PATH_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Override file from `--config-overlay`, layered on top of the base config
+static CONFIG_OVERLAY_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Read `SPLIT51_<name>` and parse it, warning about and ignoring malformed values
+fn env_override<T: std::str::FromStr>(name: &str) -> Option<T> {
+    let var = format!("SPLIT51_{}", name);
+    let raw = std::env::var(&var).ok()?;
+    match raw.trim().parse() {
+        Ok(value) => {
+            info!("Config override from environment: {}={}", var, raw);
+            Some(value)
+        }
+        Err(_) => {
+            warn!("Ignoring {}={:?}: not a valid value", var, raw);
+            None
+        }
+    }
+}
+
+/// Boolean environment value: true/false, 1/0, yes/no, on/off
+#[derive(Debug, Clone, Copy)]
+struct EnvBool(bool);
+
+impl std::str::FromStr for EnvBool {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Ok(EnvBool(true)),
+            "0" | "false" | "no" | "off" => Ok(EnvBool(false)),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Whether a config path should be read/written as JSON (by extension), otherwise TOML
+fn is_json_path(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.eq_ignore_ascii_case("json"))
+        .unwrap_or(false)
+}
+
+/// Read a TOML or JSON config file as an untyped tree for layering
+fn read_config_value(path: &Path) -> Result<toml::Value> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config from {:?}", path))?;
+    if is_json_path(path) {
+        let mut json: serde_json::Value = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse config file {:?}", path))?;
+        drop_json_nulls(&mut json);
+        toml::Value::try_from(json).context("Unsupported value in JSON config")
+    } else {
+        toml::from_str(&content).with_context(|| format!("Failed to parse config file {:?}", path))
+    }
+}
+
+/// Remove `null` object members, which TOML can't represent. JSON saves write
+/// unset `Option` fields as `null`; a missing key reads back as `None` too.
+fn drop_json_nulls(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            map.retain(|_, v| !v.is_null());
+            map.values_mut().for_each(drop_json_nulls);
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(drop_json_nulls),
+        _ => {}
+    }
+}
+
+/// Merge `overlay` into `base`: tables merge key by key, anything else replaces
+fn merge_config_values(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base), toml::Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_config_values(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// The parts of `current` that differ from `base` (the inverse of the merge).
+/// A field cleared back to unset can't be expressed and keeps the base value.
+fn diff_config_values(base: &toml::Value, current: &toml::Value) -> Option<toml::Value> {
+    match (base, current) {
+        (toml::Value::Table(base), toml::Value::Table(current)) => {
+            let table: toml::Table = current
+                .iter()
+                .filter_map(|(key, value)| match base.get(key) {
+                    Some(base_value) => diff_config_values(base_value, value).map(|d| (key.clone(), d)),
+                    None => Some((key.clone(), value.clone())),
+                })
+                .collect();
+            (!table.is_empty()).then_some(toml::Value::Table(table))
+        }
+        _ => (base != current).then(|| current.clone()),
+    }
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum ChannelSource {
     FL,  // Front Left (index 0) - for stereo clone
     FR,  // Front Right (index 1) - for stereo clone
-    RL,  // Rear Left (index 2)
-    RR,  // Rear Right (index 3)
+    RL,  // Rear Left (index 2, or its position in the source's channel mask)
+    RR,  // Rear Right (index 3, or its position in the source's channel mask)
+    /// Raw source channel index for non-standard layouts, e.g. `{ Index = 6 }`
+    Index(u16),
 }
 
 impl Default for ChannelSource {
@@ -17,6 +128,230 @@ impl Default for ChannelSource {
     }
 }
 
+/// Which outputs the L/R swap applies to
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SwapScope {
+    All,          // Routed audio and speaker test tones
+    RoutingOnly,  // Routed audio only
+    TestOnly,     // Speaker test tones only
+}
+
+impl Default for SwapScope {
+    fn default() -> Self {
+        SwapScope::All
+    }
+}
+
+impl SwapScope {
+    pub fn applies_to_routing(self) -> bool {
+        matches!(self, SwapScope::All | SwapScope::RoutingOnly)
+    }
+
+    pub fn applies_to_test(self) -> bool {
+        matches!(self, SwapScope::All | SwapScope::TestOnly)
+    }
+}
+
+/// What to do when a configured source channel doesn't exist in the captured
+/// stream (e.g. RL/RR requested but the source is stereo)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MissingChannelBehavior {
+    Fallback,  // Use the matching front channel (RL -> FL, RR -> FR)
+    Mute,      // Silence the affected output
+    Warn,      // Fall back, but log a warning
+    Passthrough,  // Ignore the routing and pass the source's front L/R straight through
+}
+
+impl Default for MissingChannelBehavior {
+    fn default() -> Self {
+        MissingChannelBehavior::Fallback
+    }
+}
+
+/// Frequency weighting for the level meter
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MeterWeighting {
+    Z,  // Flat (unweighted)
+    A,  // A-weighting, closer to perceived loudness / SPL meters
+}
+
+impl Default for MeterWeighting {
+    fn default() -> Self {
+        MeterWeighting::Z
+    }
+}
+
+/// How the pseudo-surround upmix is used. `AddRear` mixes the derived rear
+/// signal into whatever the routed channels carry; `FullSurround` (quad
+/// output only) keeps the fronts untouched and feeds the rear pair with the
+/// derived signal alone, for a complete 2 -> 4 upmix of stereo sources.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum UpmixMode {
+    AddRear,
+    FullSurround,
+}
+
+impl Default for UpmixMode {
+    fn default() -> Self {
+        UpmixMode::AddRear
+    }
+}
+
+/// Output limiter placement. `Bus` links both channels to the louder one so
+/// the stereo image stays put; `PerChannel` only pulls down the hot channel,
+/// which keeps the other at full level but can shift the image toward it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LimiterMode {
+    Bus,
+    PerChannel,
+}
+
+impl Default for LimiterMode {
+    fn default() -> Self {
+        LimiterMode::Bus
+    }
+}
+
+/// WASAPI device role for following the Windows default render device
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SourceRole {
+    Media,  // Default device (eMultimedia)
+    Comms,  // Default communications device (eCommunications)
+}
+
+/// Resampler quality/latency profile (only used when source and target rates differ)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ResamplerQuality {
+    Fast,      // Cubic polynomial interpolation
+    Balanced,  // Short sinc filter
+    High,      // Long sinc filter, 256x oversampling
+}
+
+impl Default for ResamplerQuality {
+    fn default() -> Self {
+        ResamplerQuality::High
+    }
+}
+
+impl ResamplerQuality {
+    /// Short description of the latency/CPU trade-off for logging
+    pub fn description(self) -> &'static str {
+        match self {
+            ResamplerQuality::Fast => "lowest CPU and latency, audible aliasing on bright content",
+            ResamplerQuality::Balanced => "moderate CPU, ~32 frames filter delay",
+            ResamplerQuality::High => "highest CPU, ~128 frames filter delay",
+        }
+    }
+}
+
+/// Night mode state: follow the schedule, or forced on/off until the next boundary
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum NightMode {
+    Auto,
+    On,
+    Off,
+}
+
+impl Default for NightMode {
+    fn default() -> Self {
+        NightMode::Auto
+    }
+}
+
+/// Low/mid/high band gains in dB (-12.0 to +12.0)
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EqGains {
+    pub low: f32,
+    pub mid: f32,
+    pub high: f32,
+}
+
+impl EqGains {
+    pub fn as_array(&self) -> [f32; 3] {
+        [self.low, self.mid, self.high]
+    }
+}
+
+/// Named source/target pairing, switchable from the tray "Outputs" menu
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutputSet {
+    pub name: String,
+    /// None keeps the current source
+    #[serde(default)]
+    pub source_device: Option<String>,
+    pub target_device: String,
+}
+
+impl OutputSet {
+    /// Whether the routing between `source` and `target` is this set
+    /// (names match like the config's device names, by substring)
+    pub fn matches(&self, source: &str, target: &str) -> bool {
+        target.contains(&self.target_device)
+            && self.source_device.as_ref().is_none_or(|s| source.contains(s))
+    }
+}
+
+/// Source endpoint and mix format seen on the last start. The next start
+/// opens the endpoint by ID and sizes the output from the cached rate
+/// instead of enumerating devices; the capture thread still reads the live
+/// format and replaces the cache when anything differs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceFormatCache {
+    /// Source name as configured (the cache only applies to this name)
+    pub device: String,
+    pub endpoint_id: String,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub bits_per_sample: u16,
+}
+
+impl std::fmt::Display for SourceFormatCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}ch / {} Hz / {}-bit", self.channels, self.sample_rate, self.bits_per_sample)
+    }
+}
+
+/// Settings applied while night mode is active
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NightProfile {
+    pub max_volume: f32,  // Master volume cap (0.0 - 2.0)
+    pub bass_db: f32,     // Added to EQ low band (-12.0 to 0.0 dB)
+}
+
+impl Default for NightProfile {
+    fn default() -> Self {
+        Self {
+            max_volume: 0.5,
+            bass_db: -6.0,
+        }
+    }
+}
+
+/// Parse "HH:MM" into minutes since midnight
+fn parse_hhmm(s: &str) -> Option<u32> {
+    let (h, m) = s.trim().split_once(':')?;
+    let h: u32 = h.parse().ok()?;
+    let m: u32 = m.parse().ok()?;
+    (h < 24 && m < 60).then_some(h * 60 + m)
+}
+
+/// Sample encoding hint for capture devices that misreport their format
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CaptureSampleType {
+    Float,
+    Int,
+}
+
+/// Advanced loopback format overrides (None = trust the device's mix format)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CaptureOverrides {
+    pub channels: Option<u16>,
+    pub sample_rate: Option<u32>,
+    pub sample_type: Option<CaptureSampleType>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChannelConfig {
     pub source: ChannelSource,  // Which source channel to use
@@ -35,37 +370,170 @@ impl Default for ChannelConfig {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AppConfig {
     pub source_device: Option<String>,
+    pub source_role: Option<SourceRole>,  // Capture the default device for this role instead of source_device
+    pub source_crossfade_ms: f32,  // Crossfade when switching source devices (0 = stop and restart)
+    pub source_format_cache: Option<SourceFormatCache>,  // Last seen source endpoint/format, maintained automatically
     pub target_device: Option<String>,
+    pub output_sets: Vec<OutputSet>,  // Named source/target pairings for the tray "Outputs" menu
     pub volume: f32,
     pub balance: f32,  // -1.0 (full left) to 1.0 (full right), 0.0 = center
     pub enabled: bool,
+    pub panic_muted: bool,  // Emergency mute of all output until explicitly unmuted
+    pub start_disabled: bool,  // Launch with routing off (enabled is kept as saved)
     pub swap_channels: bool,
+    pub swap_scope: SwapScope,  // Where swap_channels applies
     pub clone_stereo: bool,  // Use FL/FR instead of RL/RR
+    pub clone_stereo_restore: Option<[ChannelSource; 2]>,  // Left/right sources to restore when clone_stereo is turned off
     pub left_channel: ChannelConfig,   // Left speaker settings
     pub right_channel: ChannelConfig,  // Right speaker settings
+    pub left_trim_db: f32,   // Calibration trim for left output (-6.0 to +6.0 dB)
+    pub right_trim_db: f32,  // Calibration trim for right output (-6.0 to +6.0 dB)
+    pub missing_channel_behavior: MissingChannelBehavior,  // When the source lacks a configured channel
+    pub expected_source_channels: Option<u16>,  // Warn when the source delivers fewer channels (e.g. 6 for 5.1)
+    pub pause_on_channel_mismatch: bool,  // Silence routing while fewer than expected_source_channels arrive
     // DSP settings
+    pub input_gain_db: f32,  // Gain on the captured signal before routing/DSP (-12 to +24 dB)
     pub delay_ms: f32,       // Delay in milliseconds (0-200)
     pub eq_enabled: bool,
     pub eq_low: f32,         // -12.0 to +12.0 dB
     pub eq_mid: f32,         // -12.0 to +12.0 dB
     pub eq_high: f32,        // -12.0 to +12.0 dB
+    pub eq_shelf_slope: f32, // Low/high shelf slope S (1.0 = default, lower = gentler)
+    pub eq_low_freq: Option<f32>,   // Low shelf frequency in Hz (unset = 200)
+    pub eq_mid_freq: Option<f32>,   // Mid peak frequency in Hz (unset = 1000)
+    pub eq_high_freq: Option<f32>,  // High shelf frequency in Hz (unset = 4000)
+    pub ms_eq_enabled: bool, // Mid/side EQ (gains in config only)
+    pub ms_eq_mid: EqGains,
+    pub ms_eq_side: EqGains,
+    pub emphasis_enabled: bool,  // High-shelf pre-emphasis at the input, matching de-emphasis at the output
+    pub emphasis_freq: f32,      // Shelf frequency in Hz
+    pub emphasis_db: f32,        // Emphasis amount (0 to +12 dB)
+    pub declick: bool,  // Smooth over sample-level clicks (e.g. apps starting/stopping on the source)
     pub upmix_enabled: bool, // Pseudo-surround from stereo
-    pub upmix_strength: f32, // 0.0 to 1.0
+    pub upmix_mode: UpmixMode,  // AddRear, or FullSurround (needs output_channels = 4)
+    pub upmix_strength: f32, // 1.0 to 10.0
+    pub upmix_ambience_ms: f32,    // Spaciousness delay inside the upmixer (0-30ms)
+    pub upmix_rear_delay_ms: f32,  // Alignment delay for further rear speakers (0-50ms)
+    pub normalize_upmix: bool, // Auto-attenuate when front+rear sum exceeds unity
+    pub per_channel_limit: bool, // Soft-knee limit each routed channel before the upmix is added
+    pub upmix_dither: bool,  // TPDF dither on the upmix tail before the front+rear sum
+    pub upmix_dither_bits: u32,  // Bit depth the upmix dither targets (8-24)
+    pub upmix_dither_shaping: bool,  // First-order noise shaping for the upmix dither
+    pub upmix_center_enabled: bool,  // Steer correlated front content to C (needs output_channels = 6)
+    pub upmix_center_level: f32,  // Share of the extracted centre moved from the fronts (0.0-1.0)
     pub sync_master_volume: bool, // Sync with Windows master volume
+    pub volume_slew_db_per_sec: f32, // Max rate the synced volume follows Windows (0 = instant)
+    pub respect_system_mute: bool,  // Follow Windows mute even when volume sync is off
+    pub dry_wet: f32,  // DSP mix: 0.0 = dry (unprocessed), 1.0 = fully processed
+    pub meter_weighting: MeterWeighting,  // Level meter weighting (Z = flat, A = A-weighted)
+    pub meter_update_ms: f32,  // Level meter refresh interval, independent of rate and buffer size (10-1000)
+    pub compander_enabled: bool,  // "Leveler": even out loudness across content
+    pub compander_attack_ms: f32,
+    pub compander_release_ms: f32,
+    pub subsonic_filter: bool,  // Protective 12 dB/oct high-pass for small speakers
+    pub subsonic_cutoff_hz: f32,  // 20-80 Hz
+    pub agc_enabled: bool,  // Slow auto-gain toward agc_target_db (boost capped at +12 dB)
+    pub agc_target_db: f32,  // Target RMS level in dBFS (-40 to -6)
+    pub limiter_enabled: bool,  // Output limiter at the end of the DSP chain
+    pub limiter_mode: LimiterMode,  // Bus (stereo-linked) or PerChannel
+    pub limiter_ceiling_db: f32,  // Peak ceiling of the output limiter (-12 to 0 dBFS)
+    pub resampler_quality: ResamplerQuality,
+    pub match_source_rate: bool,  // Open the target at the source rate when it supports it (skips resampling)
+    pub prefill_ms: f32,  // Buffered audio before output starts (0-100ms)
+    pub soft_start_ms: f32,  // Fade-in when an output stream starts, against amp pops (0 = off)
+    pub stop_ramp_ms: f32,  // Fade-out to zero before the output stream is dropped, against thumps (0 = off)
+    pub preset_fade_ms: f32,  // Fade out/in around profile switches such as night mode (0-200ms, 0 = instant)
+    pub device_change_mute_ms: f32,  // Silence around a switch of the followed default device (0-5000ms, 0 = off)
+    pub output_channels: u16,  // 2 = stereo fold, 4 = discrete quad (front + routed rear), 6 = 5.1 (adds C/LFE)
+    pub output_buffer_frames: Option<u32>,  // Explicit output buffer size (unset = driver default)
+    pub preserve_dsp_state: bool,  // Keep filter/delay state across restarts (click-free switches)
+    pub direct_mode: bool,  // Experimental: bypass the ring buffer when rates match (may glitch)
+    pub keep_capture_warm: bool,  // Pause capture/output on disable instead of closing them (holds the devices)
+    pub keep_stream_alive: bool,  // On disable, pause capture but keep the output playing silence (no amp click)
+    pub capturable_output: bool,  // Target is a virtual cable other apps record from (implies keep_stream_alive)
+    pub exclusive_fallback: bool,  // Switch source to the default device while it's held in exclusive mode
+    pub startup_delay_ms: u32,  // Max wait for the target to appear at startup (0 = don't wait)
+    // Capture format overrides for devices that misreport their mix format
+    pub force_capture_channels: Option<u16>,
+    pub force_capture_rate: Option<u32>,
+    pub capture_sample_type: Option<CaptureSampleType>,
+    pub disable_output_clamp: bool,  // Skip hard clamp (for external limiting, risky)
+    pub linked_clamp: bool,  // Attenuate both channels equally on overload (keeps L/R balance)
+    pub level_safety: bool,  // Auto-reduce volume by 3 dB on sustained clipping
+    pub clip_hold_secs: f32,  // How long the tooltip shows CLIP after the last clipped sample
+    pub noise_channel: ChannelSource,  // Target channel for the continuous pink noise test
+    pub noise_level_db: f32,  // Pink noise RMS level in dBFS (-60 to -6)
+    // Night mode
+    pub night_mode: NightMode,
+    pub night_start: Option<String>,  // "HH:MM" local time
+    pub night_end: Option<String>,    // "HH:MM" local time
+    pub auto_enable_process: Option<String>,  // Route only while this process runs (e.g. "game.exe")
+    pub status_file: Option<String>,  // Write a JSON status snapshot here every second (relative = next to the config)
+    pub night_profile: NightProfile,
+    // Saved value of `enabled` while start_disabled or auto-enable overrides it
+    #[serde(skip)]
+    launch_enabled: Option<bool>,
+    // File values under the `SPLIT51_*` overrides, restored on save
+    #[serde(skip)]
+    env_overrides: Option<Box<EnvOverrides>>,
+}
+
+/// The config as read from file(s) and as it stood after the `SPLIT51_*`
+/// environment overrides, so saves never persist an override
+#[derive(Debug, Clone)]
+struct EnvOverrides {
+    file: AppConfig,
+    applied: AppConfig,
+}
+
+impl EnvOverrides {
+    /// Put the file value back into every overridden field that still holds
+    /// its override; fields changed since (e.g. from the tray) keep the change
+    fn restore_file_values(&self, config: &mut AppConfig) {
+        let (file, applied) = (&self.file, &self.applied);
+        if config.source_device == applied.source_device && config.source_role == applied.source_role {
+            config.source_device = file.source_device.clone();
+            config.source_role = file.source_role;
+        }
+        if config.target_device == applied.target_device {
+            config.target_device = file.target_device.clone();
+        }
+        if config.volume == applied.volume {
+            config.volume = file.volume;
+        }
+        if config.balance == applied.balance {
+            config.balance = file.balance;
+        }
+        if config.enabled == applied.enabled {
+            config.enabled = file.enabled;
+        }
+        if config.output_channels == applied.output_channels {
+            config.output_channels = file.output_channels;
+        }
+    }
 }
 
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
             source_device: None,
+            source_role: None,
+            source_crossfade_ms: 0.0,
+            source_format_cache: None,
             target_device: None,
+            output_sets: Vec::new(),
             volume: 1.0,
             balance: 0.0,
             enabled: true,
+            panic_muted: false,
+            start_disabled: false,
             swap_channels: false,
+            swap_scope: SwapScope::All,
             clone_stereo: false,
+            clone_stereo_restore: None,
             left_channel: ChannelConfig {
                 source: ChannelSource::RL,
                 volume: 1.0,
@@ -76,35 +544,289 @@ impl Default for AppConfig {
                 volume: 1.0,
                 muted: false,
             },
+            left_trim_db: 0.0,
+            right_trim_db: 0.0,
+            missing_channel_behavior: MissingChannelBehavior::Fallback,
+            expected_source_channels: None,
+            pause_on_channel_mismatch: false,
+            input_gain_db: 0.0,
             delay_ms: 0.0,
             eq_enabled: false,
             eq_low: 0.0,
             eq_mid: 0.0,
             eq_high: 0.0,
+            eq_shelf_slope: 1.0,
+            eq_low_freq: None,
+            eq_mid_freq: None,
+            eq_high_freq: None,
+            ms_eq_enabled: false,
+            ms_eq_mid: EqGains::default(),
+            ms_eq_side: EqGains::default(),
+            emphasis_enabled: false,
+            emphasis_freq: 3200.0,
+            emphasis_db: 6.0,
+            declick: false,
             upmix_enabled: false,
-            upmix_strength: 4.0,  // 4x for matching main volume
+            upmix_mode: UpmixMode::AddRear,
+            upmix_strength: 2.0,  // 4x overdrives many sources
+            upmix_ambience_ms: 10.0,
+            upmix_rear_delay_ms: 0.0,
+            normalize_upmix: true,
+            per_channel_limit: false,
+            upmix_dither: false,
+            upmix_dither_bits: 16,
+            upmix_dither_shaping: true,
+            upmix_center_enabled: false,
+            upmix_center_level: 1.0,
             sync_master_volume: true,  // Default: sync with Windows volume
+            volume_slew_db_per_sec: 60.0,
+            respect_system_mute: true,
+            dry_wet: 1.0,
+            meter_weighting: MeterWeighting::Z,
+            meter_update_ms: 40.0,
+            compander_enabled: false,
+            compander_attack_ms: 10.0,
+            compander_release_ms: 300.0,
+            subsonic_filter: false,
+            subsonic_cutoff_hz: 30.0,
+            agc_enabled: false,
+            agc_target_db: -20.0,
+            limiter_enabled: false,
+            limiter_mode: LimiterMode::Bus,
+            limiter_ceiling_db: 0.0,
+            resampler_quality: ResamplerQuality::High,
+            match_source_rate: true,
+            prefill_ms: 50.0,
+            soft_start_ms: 30.0,
+            stop_ramp_ms: 5.0,
+            preset_fade_ms: 20.0,
+            device_change_mute_ms: 300.0,
+            output_channels: 2,
+            output_buffer_frames: None,
+            preserve_dsp_state: true,
+            direct_mode: false,
+            keep_capture_warm: false,
+            keep_stream_alive: false,
+            capturable_output: false,
+            exclusive_fallback: false,
+            startup_delay_ms: 0,
+            force_capture_channels: None,
+            force_capture_rate: None,
+            capture_sample_type: None,
+            disable_output_clamp: false,
+            linked_clamp: false,
+            level_safety: false,
+            clip_hold_secs: 2.0,
+            noise_channel: ChannelSource::RL,
+            noise_level_db: -30.0,
+            night_mode: NightMode::Auto,
+            night_start: None,
+            night_end: None,
+            auto_enable_process: None,
+            status_file: None,
+            night_profile: NightProfile::default(),
+            launch_enabled: None,
+            env_overrides: None,
         }
     }
 }
 
 impl AppConfig {
+    /// Use an explicit config file (e.g. from `--config`). Only the first call has effect.
+    pub fn set_config_path(path: PathBuf) {
+        let _ = CONFIG_PATH_OVERRIDE.set(path);
+    }
+
+    /// Layer an override file (e.g. from `--config-overlay`) over the base
+    /// config. Only the first call has effect; saves then go to the overlay.
+    pub fn set_overlay_path(path: PathBuf) {
+        let _ = CONFIG_OVERLAY_PATH.set(path);
+    }
+
+    /// Resolve the config file: explicit path, else `config.toml` next to the
+    /// executable, else `config.json` if only that exists
     pub fn config_path() -> Result<PathBuf> {
+        if let Some(path) = CONFIG_PATH_OVERRIDE.get() {
+            return Ok(path.clone());
+        }
         let exe_path = std::env::current_exe().context("Failed to get executable path")?;
-        let config_path = exe_path
+        let exe_dir = exe_path
             .parent()
-            .context("Failed to get executable directory")?
-            .join("config.toml");
-        Ok(config_path)
+            .context("Failed to get executable directory")?;
+        // A config written by the %APPDATA% fallback is newer than the one next to the exe
+        if let Some(fallback) = Self::fallback_config_path().filter(|f| f.exists()) {
+            return Ok(fallback);
+        }
+        let toml_path = exe_dir.join("config.toml");
+        let json_path = exe_dir.join("config.json");
+        if !toml_path.exists() && json_path.exists() {
+            Ok(json_path)
+        } else {
+            Ok(toml_path)
+        }
+    }
+
+    /// Fallback location used when the exe directory is not writable
+    fn fallback_config_path() -> Option<PathBuf> {
+        let appdata = std::env::var_os("APPDATA")?;
+        Some(PathBuf::from(appdata).join("split51").join("config.toml"))
+    }
+
+    pub fn capture_overrides(&self) -> CaptureOverrides {
+        CaptureOverrides {
+            channels: self.force_capture_channels,
+            sample_rate: self.force_capture_rate,
+            sample_type: self.capture_sample_type,
+        }
+    }
+
+    /// Where to write the JSON status snapshot; relative paths are resolved
+    /// next to the config file
+    pub fn status_file_path(&self) -> Option<PathBuf> {
+        let path = PathBuf::from(self.status_file.as_deref().filter(|p| !p.is_empty())?);
+        if path.is_relative()
+            && let Some(dir) = Self::config_path().ok().as_deref().and_then(Path::parent)
+        {
+            return Some(dir.join(path));
+        }
+        Some(path)
     }
 
+    /// Whether `minutes` (since local midnight) falls in the night schedule window.
+    /// Windows may wrap past midnight (e.g. 22:00 - 07:00).
+    pub fn is_night_window(&self, minutes: u32) -> bool {
+        let (Some(start), Some(end)) = (
+            self.night_start.as_deref().and_then(parse_hhmm),
+            self.night_end.as_deref().and_then(parse_hhmm),
+        ) else {
+            return false;
+        };
+        if start <= end {
+            minutes >= start && minutes < end
+        } else {
+            minutes >= start || minutes < end
+        }
+    }
+
+    /// Master volume and EQ (enabled, [low, mid, high]) to run the router with:
+    /// the configured values, under the night profile's volume cap and bass cut
+    /// while night mode is active. The cut is clamped to the EQ's ±12 dB range.
+    pub fn effective_levels(&self, night_active: bool) -> (f32, bool, [f32; 3]) {
+        if night_active {
+            let profile = &self.night_profile;
+            let low = (self.eq_low + profile.bass_db).clamp(-12.0, 12.0);
+            (self.volume.min(profile.max_volume), true, [low, self.eq_mid, self.eq_high])
+        } else {
+            (self.volume, self.eq_enabled, [self.eq_low, self.eq_mid, self.eq_high])
+        }
+    }
+
+    /// Apply `start_disabled`: turn routing off for this session only.
+    /// Saves keep writing the persisted `enabled` until `set_enabled` is called.
+    pub fn apply_start_disabled(&mut self) {
+        if self.start_disabled && self.launch_enabled.is_none() {
+            self.set_session_enabled(false);
+        }
+    }
+
+    /// Change the routing state for this session only (e.g. `auto_enable_process`).
+    /// Saves keep writing the persisted `enabled` until `set_enabled` is called.
+    pub fn set_session_enabled(&mut self, enabled: bool) {
+        if self.launch_enabled.is_none() {
+            self.launch_enabled = Some(self.enabled);
+        }
+        self.enabled = enabled;
+    }
+
+    /// Process name routing follows, if `auto_enable_process` is set and non-empty
+    pub fn auto_enable_process_name(&self) -> Option<&str> {
+        self.auto_enable_process.as_deref().filter(|name| !name.is_empty())
+    }
+
+    /// Change the routing state from the UI (persisted on the next save)
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.launch_enabled = None;
+    }
+
+    /// Load the config file(s), then apply `SPLIT51_*` environment overrides.
+    /// The overrides last for this run only: saves write the file values back.
     pub fn load() -> Result<Self> {
+        let mut config = Self::load_layered()?;
+        let file = config.clone();
+        if config.apply_env_overrides() {
+            let applied = config.clone();
+            config.env_overrides = Some(Box::new(EnvOverrides { file, applied }));
+        }
+        Ok(config)
+    }
+
+    /// Override fields from the environment for headless deployment:
+    /// SPLIT51_SOURCE_DEVICE, SPLIT51_TARGET_DEVICE (device names as in `--list`),
+    /// SPLIT51_VOLUME, SPLIT51_BALANCE, SPLIT51_ENABLED, SPLIT51_OUTPUT_CHANNELS.
+    /// Devices are picked by name only, like in the config file; there are no
+    /// endpoint ID variables. Returns whether any override was applied.
+    fn apply_env_overrides(&mut self) -> bool {
+        let mut applied = false;
+        if let Some(name) = env_override::<String>("SOURCE_DEVICE") {
+            self.source_device = Some(name);
+            self.source_role = None;
+            applied = true;
+        }
+        if let Some(name) = env_override::<String>("TARGET_DEVICE") {
+            self.target_device = Some(name);
+            applied = true;
+        }
+        if let Some(volume) = env_override::<f32>("VOLUME") {
+            self.volume = volume.clamp(0.0, 2.0);
+            applied = true;
+        }
+        if let Some(balance) = env_override::<f32>("BALANCE") {
+            self.balance = balance.clamp(-1.0, 1.0);
+            applied = true;
+        }
+        if let Some(EnvBool(enabled)) = env_override("ENABLED") {
+            self.enabled = enabled;
+            applied = true;
+        }
+        match env_override::<u16>("OUTPUT_CHANNELS") {
+            Some(channels @ (2 | 4 | 6)) => {
+                self.output_channels = channels;
+                applied = true;
+            }
+            Some(channels) => warn!("Ignoring SPLIT51_OUTPUT_CHANNELS={}: must be 2, 4 or 6", channels),
+            None => {}
+        }
+        applied
+    }
+
+    /// Load the base config, with the overlay's fields on top if one is set
+    fn load_layered() -> Result<Self> {
+        let Some(overlay_path) = CONFIG_OVERLAY_PATH.get() else {
+            return Self::load_base();
+        };
+        let path = Self::config_path()?;
+        let mut value = if path.exists() {
+            read_config_value(&path)?
+        } else {
+            toml::Value::Table(toml::Table::new())
+        };
+        if overlay_path.exists() {
+            merge_config_values(&mut value, read_config_value(overlay_path)?);
+        }
+        value.try_into().context("Failed to parse layered config")
+    }
+
+    fn load_base() -> Result<Self> {
         let path = Self::config_path()?;
         if path.exists() {
             let content = fs::read_to_string(&path)
                 .with_context(|| format!("Failed to read config from {:?}", path))?;
-            let config: AppConfig =
-                toml::from_str(&content).context("Failed to parse config file")?;
+            let config: AppConfig = if is_json_path(&path) {
+                serde_json::from_str(&content).context("Failed to parse config file")?
+            } else {
+                toml::from_str(&content).context("Failed to parse config file")?
+            };
             Ok(config)
         } else {
             Ok(Self::default())
@@ -112,10 +834,146 @@ impl AppConfig {
     }
 
     pub fn save(&self) -> Result<()> {
+        if let Some(enabled) = self.launch_enabled {
+            let mut persisted = self.clone();
+            persisted.enabled = enabled;
+            persisted.launch_enabled = None;
+            return persisted.save();
+        }
+        if let Some(env) = &self.env_overrides {
+            let mut persisted = self.clone();
+            persisted.env_overrides = None;
+            env.restore_file_values(&mut persisted);
+            return persisted.save();
+        }
+        if let Some(overlay_path) = CONFIG_OVERLAY_PATH.get() {
+            return self.save_overlay(overlay_path);
+        }
         let path = Self::config_path()?;
-        let content = toml::to_string_pretty(self).context("Failed to serialize config")?;
-        fs::write(&path, content)
-            .with_context(|| format!("Failed to write config to {:?}", path))?;
-        Ok(())
+        let content = if is_json_path(&path) {
+            serde_json::to_string_pretty(self).context("Failed to serialize config")?
+        } else {
+            toml::to_string_pretty(self).context("Failed to serialize config")?
+        };
+        let err = match fs::write(&path, &content) {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+
+        // Retry under %APPDATA% unless an explicit path was given
+        let fallback = CONFIG_PATH_OVERRIDE
+            .get()
+            .is_none()
+            .then(Self::fallback_config_path)
+            .flatten()
+            .filter(|f| *f != path);
+        if let Some(fallback) = fallback {
+            let content = toml::to_string_pretty(self).context("Failed to serialize config")?;
+            let written = fallback
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|()| fs::write(&fallback, content));
+            if written.is_ok() {
+                warn!("Could not write config to {:?} ({}), saved to {:?} instead", path, err, fallback);
+                return Ok(());
+            }
+        }
+        Err(err).with_context(|| format!("Failed to write config to {:?}", path))
+    }
+
+    /// Write only the fields that differ from the base config, leaving the
+    /// shared base file untouched
+    fn save_overlay(&self, overlay_path: &Path) -> Result<()> {
+        let base = toml::Value::try_from(Self::load_base()?).context("Failed to serialize config")?;
+        let current = toml::Value::try_from(self).context("Failed to serialize config")?;
+        let diff = diff_config_values(&base, &current)
+            .unwrap_or_else(|| toml::Value::Table(toml::Table::new()));
+        let content = if is_json_path(overlay_path) {
+            serde_json::to_string_pretty(&diff).context("Failed to serialize config")?
+        } else {
+            toml::to_string_pretty(&diff).context("Failed to serialize config")?
+        };
+        fs::write(overlay_path, content)
+            .with_context(|| format!("Failed to write config overlay to {:?}", overlay_path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_night_cap_survives_volume_change() {
+        let mut config = AppConfig {
+            volume: 0.4,
+            eq_low: -10.0,
+            night_profile: NightProfile { max_volume: 0.5, bass_db: -6.0 },
+            ..AppConfig::default()
+        };
+
+        // Raising the volume during night mode stays under the cap
+        config.volume = 1.5;
+        let (volume, eq_enabled, [low, mid, high]) = config.effective_levels(true);
+        assert_eq!(volume, 0.5);
+        assert!(eq_enabled);
+        assert_eq!((low, mid, high), (-12.0, config.eq_mid, config.eq_high));
+
+        // And the configured values come back once it ends
+        assert_eq!(config.effective_levels(false), (1.5, config.eq_enabled, [-10.0, config.eq_mid, config.eq_high]));
+    }
+
+    #[test]
+    fn test_json_config_with_unset_fields_round_trips() {
+        let config = AppConfig {
+            target_device: Some("Speakers".into()),
+            source_device: None,
+            volume: 0.7,
+            ..AppConfig::default()
+        };
+        let path = std::env::temp_dir().join(format!("split51-test-{}.json", std::process::id()));
+        fs::write(&path, serde_json::to_string_pretty(&config).unwrap()).unwrap();
+        let value = read_config_value(&path);
+        let _ = fs::remove_file(&path);
+
+        let loaded: AppConfig = value.expect("JSON with nulls loads").try_into().unwrap();
+        assert_eq!(loaded.source_device, None);
+        assert_eq!(loaded.target_device.as_deref(), Some("Speakers"));
+        assert_eq!(loaded.volume, 0.7);
+    }
+
+    #[test]
+    fn test_session_enabled_keeps_saved_state() {
+        let mut config = AppConfig { enabled: false, ..AppConfig::default() };
+        config.set_session_enabled(true);
+        config.set_session_enabled(false);
+        config.set_session_enabled(true);
+        assert!(config.enabled);
+        assert_eq!(config.launch_enabled, Some(false));
+
+        // A manual change is the new saved state
+        config.set_enabled(true);
+        assert_eq!(config.launch_enabled, None);
+
+        let unset = AppConfig { auto_enable_process: Some(String::new()), ..AppConfig::default() };
+        assert_eq!(unset.auto_enable_process_name(), None);
+    }
+
+    #[test]
+    fn test_env_overrides_are_not_persisted() {
+        let file = AppConfig { volume: 0.8, target_device: Some("Speakers".into()), ..AppConfig::default() };
+        let applied = AppConfig { volume: 0.3, target_device: Some("Headphones".into()), ..file.clone() };
+        let env = EnvOverrides { file, applied: applied.clone() };
+
+        // Untouched overrides go back to the file values
+        let mut persisted = applied.clone();
+        env.restore_file_values(&mut persisted);
+        assert_eq!(persisted.volume, 0.8);
+        assert_eq!(persisted.target_device.as_deref(), Some("Speakers"));
+
+        // A change made during the session is kept
+        let mut persisted = AppConfig { volume: 0.5, ..applied };
+        env.restore_file_values(&mut persisted);
+        assert_eq!(persisted.volume, 0.5);
+        assert_eq!(persisted.target_device.as_deref(), Some("Speakers"));
     }
 }
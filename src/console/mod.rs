@@ -0,0 +1,136 @@
+//! Text front-end for `--console`: a numbered menu on stdin/stdout that
+//! issues the same `TrayCommand`s as the tray, for headless machines
+
+use std::io::{BufRead, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use crate::tray::TrayCommand;
+use crate::App;
+
+/// Line reader that keeps the app's periodic housekeeping running while
+/// waiting for input
+struct Console {
+    lines: Receiver<String>,
+    poll_interval: Duration,
+}
+
+impl Console {
+    fn new() -> Self {
+        // stdin blocks, so read it on its own thread
+        let (tx, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lock().lines().map_while(|l| l.ok()) {
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { lines, poll_interval: Duration::ZERO }
+    }
+
+    /// Print `text` and wait for a line, or None when stdin is closed
+    fn prompt(&mut self, app: &mut App, text: &str) -> Option<String> {
+        print!("{}", text);
+        let _ = std::io::stdout().flush();
+        loop {
+            match self.lines.recv_timeout(self.poll_interval) {
+                Ok(line) => return Some(line.trim().to_string()),
+                Err(RecvTimeoutError::Timeout) => self.poll_interval = app.poll(),
+                Err(RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+    }
+
+    /// Ask for a number within `range`; None (with a message) when invalid
+    fn prompt_number(&mut self, app: &mut App, text: &str, range: std::ops::RangeInclusive<f32>) -> Option<f32> {
+        let line = self.prompt(app, &format!("{} [{} to {}]: ", text, range.start(), range.end()))?;
+        match line.parse::<f32>() {
+            Ok(value) if range.contains(&value) => Some(value),
+            _ => {
+                println!("Invalid value: {:?}", line);
+                None
+            }
+        }
+    }
+
+    /// Numbered device list; returns the chosen name
+    fn pick_device(&mut self, app: &mut App, devices: &[String], current: &str) -> Option<String> {
+        for (i, name) in devices.iter().enumerate() {
+            let marker = if name == current { "*" } else { " " };
+            println!("  {}{:>2}) {}", marker, i + 1, name);
+        }
+        let line = self.prompt(app, "Device number: ")?;
+        match line.parse::<usize>().ok().and_then(|n| n.checked_sub(1)).and_then(|i| devices.get(i)) {
+            Some(name) => Some(name.clone()),
+            None => {
+                println!("Invalid device: {:?}", line);
+                None
+            }
+        }
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value { "on" } else { "off" }
+}
+
+fn print_menu(app: &App) {
+    let config = &app.config;
+    println!();
+    println!("Source: {}", app.source_name);
+    println!("Target: {}", app.target_name);
+    println!(
+        "Routing: {}{}  Volume: {}%  Swap: {}  EQ: {} ({:+} / {:+} / {:+} dB)",
+        on_off(config.enabled),
+        if config.panic_muted { " (PANIC MUTED)" } else { "" },
+        (config.volume * 100.0).round() as i32,
+        on_off(config.swap_channels),
+        on_off(config.eq_enabled),
+        config.eq_low, config.eq_mid, config.eq_high,
+    );
+    println!("  1) Toggle routing      2) Source device     3) Target device");
+    println!("  4) Volume              5) Toggle swap L/R   6) Toggle EQ");
+    println!("  7) EQ low              8) EQ mid            9) EQ high");
+    println!("  m) Panic mute          q) Quit              (Enter refreshes)");
+}
+
+/// Run the console until 'q' or end of input. `devices` are the render
+/// endpoints offered for source and target selection.
+pub fn run(app: &mut App, devices: &[String]) {
+    let mut console = Console::new();
+    loop {
+        print_menu(app);
+        let Some(choice) = console.prompt(app, "> ") else {
+            app.handle_command(TrayCommand::Quit);
+            return;
+        };
+        let cmd = match choice.as_str() {
+            "1" => Some(TrayCommand::ToggleEnabled),
+            "2" => {
+                let current = app.source_name.clone();
+                console.pick_device(app, devices, &current).map(TrayCommand::SelectSourceDevice)
+            }
+            "3" => {
+                let current = app.target_name.clone();
+                console.pick_device(app, devices, &current).map(TrayCommand::SelectTargetDevice)
+            }
+            "4" => console.prompt_number(app, "Volume %", 0.0..=200.0).map(|pct| TrayCommand::SetVolume(pct / 100.0)),
+            "5" => Some(TrayCommand::ToggleSwapChannels),
+            "6" => Some(TrayCommand::ToggleEq),
+            "7" => console.prompt_number(app, "EQ low dB", -12.0..=12.0).map(TrayCommand::SetEqLow),
+            "8" => console.prompt_number(app, "EQ mid dB", -12.0..=12.0).map(TrayCommand::SetEqMid),
+            "9" => console.prompt_number(app, "EQ high dB", -12.0..=12.0).map(TrayCommand::SetEqHigh),
+            "m" | "M" => Some(TrayCommand::TogglePanicMute),
+            "q" | "Q" => Some(TrayCommand::Quit),
+            "" => None,
+            other => {
+                println!("Unknown choice: {:?}", other);
+                None
+            }
+        };
+        if cmd.is_some_and(|cmd| app.handle_command(cmd)) {
+            return;
+        }
+    }
+}
@@ -2,8 +2,59 @@
 //! Provides delay, EQ, upmix, and level metering
 
 use std::f32::consts::PI;
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
+use tracing::warn;
+
+/// Highest filter frequency as a fraction of the sample rate. Keeps `w0`
+/// clear of pi, where the biquad coefficients degenerate.
+const MAX_FILTER_FREQ_RATIO: f32 = 0.45;
+
+/// Clamp a filter frequency below Nyquist (with margin) for the given sample rate
+fn clamp_filter_freq(freq: f32, sample_rate: f32) -> f32 {
+    let max = sample_rate * MAX_FILTER_FREQ_RATIO;
+    if freq > max {
+        warn!("Filter frequency {} Hz is above the limit for {} Hz, clamping to {} Hz", freq, sample_rate, max);
+        max
+    } else {
+        freq.max(1.0)
+    }
+}
+
+/// RBJ shelf alpha for slope `s`; s = 1 gives the sqrt(2) (Butterworth-like) term
+fn shelf_alpha(sin_w0: f32, a: f32, s: f32) -> f32 {
+    let s = s.max(0.01);
+    sin_w0 / 2.0 * ((a + 1.0 / a) * (1.0 / s - 1.0) + 2.0).max(0.0).sqrt()
+}
+
+/// Speaker names for an output frame of `channels` channels (stereo, quad or
+/// 5.1), in channel order; empty for other layouts
+pub fn output_channel_names(channels: usize) -> &'static [&'static str] {
+    match channels {
+        2 => &["L", "R"],
+        4 => &["FL", "FR", "RL", "RR"],
+        6 => &["FL", "FR", "C", "LFE", "RL", "RR"],
+        _ => &[],
+    }
+}
+
+/// Convert a gain in dB to a linear multiplier
+pub fn db_to_linear(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
+
+/// Move gain `current` toward `target` by at most `max_step_db`, stepping in
+/// dB so the ramp sounds even. Gains below -60 dB count as silence; a
+/// non-positive step jumps straight to the target.
+pub fn slew_gain(current: f32, target: f32, max_step_db: f32) -> f32 {
+    const FLOOR_DB: f32 = -60.0;
+    let to_db = |gain: f32| if gain > 0.0 { (20.0 * gain.log10()).max(FLOOR_DB) } else { FLOOR_DB };
+    let (from, to) = (to_db(current), to_db(target));
+    if max_step_db <= 0.0 || (to - from).abs() <= max_step_db {
+        return target;
+    }
+    db_to_linear(from + max_step_db.copysign(to - from))
+}
 
 /// Delay buffer for latency compensation
 pub struct DelayBuffer {
@@ -38,6 +89,71 @@ impl DelayBuffer {
     }
 }
 
+/// Reassembles interleaved frames across buffers, carrying any trailing
+/// partial frame over to the next buffer instead of dropping it
+pub struct FrameAligner {
+    channels: usize,
+    pending: Vec<f32>,
+}
+
+impl FrameAligner {
+    pub fn new(channels: usize) -> Self {
+        Self {
+            channels: channels.max(1),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Append samples and return only whole frames
+    pub fn push(&mut self, samples: &[f32]) -> Vec<f32> {
+        let mut out = std::mem::take(&mut self.pending);
+        out.extend_from_slice(samples);
+        let whole = out.len() - out.len() % self.channels;
+        self.pending = out.split_off(whole);
+        out
+    }
+}
+
+/// Linearly ramped gain to avoid clicks on sudden changes (e.g. mute toggles)
+pub struct SmoothedValue {
+    current: f32,
+    target: f32,
+    step: f32,
+}
+
+impl SmoothedValue {
+    /// `ramp_samples` is the number of samples for a full 0.0 <-> 1.0 transition
+    pub fn new(initial: f32, ramp_samples: usize) -> Self {
+        Self {
+            current: initial,
+            target: initial,
+            step: 1.0 / ramp_samples.max(1) as f32,
+        }
+    }
+
+    pub fn set_target(&mut self, target: f32) {
+        self.target = target;
+    }
+
+    /// Change the ramp length; a ramp in progress continues at the new rate
+    pub fn set_ramp_samples(&mut self, ramp_samples: usize) {
+        self.step = 1.0 / ramp_samples.max(1) as f32;
+    }
+
+    pub fn value(&self) -> f32 {
+        self.current
+    }
+
+    pub fn next(&mut self) -> f32 {
+        if self.current < self.target {
+            self.current = (self.current + self.step).min(self.target);
+        } else if self.current > self.target {
+            self.current = (self.current - self.step).max(self.target);
+        }
+        self.current
+    }
+}
+
 /// Biquad filter for EQ and filtering
 #[derive(Clone)]
 pub struct Biquad {
@@ -59,11 +175,17 @@ impl Biquad {
 
     /// Low-shelf filter
     pub fn low_shelf(freq: f32, gain_db: f32, sample_rate: f32) -> Self {
+        Self::low_shelf_s(freq, gain_db, 1.0, sample_rate)
+    }
+
+    /// Low-shelf filter with RBJ shelf slope `s` (1.0 = steepest without overshoot)
+    pub fn low_shelf_s(freq: f32, gain_db: f32, s: f32, sample_rate: f32) -> Self {
+        let freq = clamp_filter_freq(freq, sample_rate);
         let a = 10.0_f32.powf(gain_db / 40.0);
         let w0 = 2.0 * PI * freq / sample_rate;
         let cos_w0 = w0.cos();
         let sin_w0 = w0.sin();
-        let alpha = sin_w0 / 2.0 * (2.0_f32).sqrt();
+        let alpha = shelf_alpha(sin_w0, a, s);
 
         let a0 = (a + 1.0) + (a - 1.0) * cos_w0 + 2.0 * a.sqrt() * alpha;
         let a1 = -2.0 * ((a - 1.0) + (a + 1.0) * cos_w0);
@@ -81,11 +203,17 @@ impl Biquad {
 
     /// High-shelf filter
     pub fn high_shelf(freq: f32, gain_db: f32, sample_rate: f32) -> Self {
+        Self::high_shelf_s(freq, gain_db, 1.0, sample_rate)
+    }
+
+    /// High-shelf filter with RBJ shelf slope `s` (see `low_shelf_s`)
+    pub fn high_shelf_s(freq: f32, gain_db: f32, s: f32, sample_rate: f32) -> Self {
+        let freq = clamp_filter_freq(freq, sample_rate);
         let a = 10.0_f32.powf(gain_db / 40.0);
         let w0 = 2.0 * PI * freq / sample_rate;
         let cos_w0 = w0.cos();
         let sin_w0 = w0.sin();
-        let alpha = sin_w0 / 2.0 * (2.0_f32).sqrt();
+        let alpha = shelf_alpha(sin_w0, a, s);
 
         let a0 = (a + 1.0) - (a - 1.0) * cos_w0 + 2.0 * a.sqrt() * alpha;
         let a1 = 2.0 * ((a - 1.0) - (a + 1.0) * cos_w0);
@@ -103,6 +231,7 @@ impl Biquad {
 
     /// Peaking EQ filter
     pub fn peaking(freq: f32, gain_db: f32, q: f32, sample_rate: f32) -> Self {
+        let freq = clamp_filter_freq(freq, sample_rate);
         let a = 10.0_f32.powf(gain_db / 40.0);
         let w0 = 2.0 * PI * freq / sample_rate;
         let cos_w0 = w0.cos();
@@ -125,6 +254,7 @@ impl Biquad {
 
     /// High-pass filter for upmix
     pub fn highpass(freq: f32, q: f32, sample_rate: f32) -> Self {
+        let freq = clamp_filter_freq(freq, sample_rate);
         let w0 = 2.0 * PI * freq / sample_rate;
         let cos_w0 = w0.cos();
         let sin_w0 = w0.sin();
@@ -144,6 +274,39 @@ impl Biquad {
         }
     }
 
+    /// Low-pass filter
+    pub fn lowpass(freq: f32, q: f32, sample_rate: f32) -> Self {
+        let freq = clamp_filter_freq(freq, sample_rate);
+        let w0 = 2.0 * PI * freq / sample_rate;
+        let cos_w0 = w0.cos();
+        let sin_w0 = w0.sin();
+        let alpha = sin_w0 / (2.0 * q);
+
+        let a0 = 1.0 + alpha;
+        let b0 = (1.0 - cos_w0) / 2.0;
+        let b1 = 1.0 - cos_w0;
+        let b2 = (1.0 - cos_w0) / 2.0;
+        let a1 = -2.0 * cos_w0;
+        let a2 = 1.0 - alpha;
+
+        Self {
+            b0: b0 / a0, b1: b1 / a0, b2: b2 / a0,
+            a1: a1 / a0, a2: a2 / a0,
+            x1: 0.0, x2: 0.0, y1: 0.0, y2: 0.0,
+        }
+    }
+
+    /// Magnitude response at `freq`
+    pub fn magnitude_at(&self, freq: f32, sample_rate: f32) -> f32 {
+        let w = 2.0 * PI * freq / sample_rate;
+        let (c1, s1, c2, s2) = (w.cos(), w.sin(), (2.0 * w).cos(), (2.0 * w).sin());
+        let num_re = self.b0 + self.b1 * c1 + self.b2 * c2;
+        let num_im = -(self.b1 * s1 + self.b2 * s2);
+        let den_re = 1.0 + self.a1 * c1 + self.a2 * c2;
+        let den_im = -(self.a1 * s1 + self.a2 * s2);
+        (num_re * num_re + num_im * num_im).sqrt() / (den_re * den_re + den_im * den_im).sqrt()
+    }
+
     pub fn process(&mut self, input: f32) -> f32 {
         let output = self.b0 * input + self.b1 * self.x1 + self.b2 * self.x2
                    - self.a1 * self.y1 - self.a2 * self.y2;
@@ -170,22 +333,55 @@ pub struct ThreeBandEq {
     mid_peak: Biquad,
     high_shelf: Biquad,
     sample_rate: f32,
+    /// Shelf slope (S) and the current gains, kept to rebuild on slope changes
+    slope: f32,
+    gains: (f32, f32, f32),
+    /// Low shelf, mid peak and high shelf frequencies in Hz
+    freqs: (f32, f32, f32),
 }
 
 impl ThreeBandEq {
+    pub const DEFAULT_FREQS: (f32, f32, f32) = (200.0, 1000.0, 4000.0);
+
     pub fn new(sample_rate: f32) -> Self {
+        let (low, mid, high) = Self::DEFAULT_FREQS;
         Self {
-            low_shelf: Biquad::low_shelf(200.0, 0.0, sample_rate),
-            mid_peak: Biquad::peaking(1000.0, 0.0, 1.0, sample_rate),
-            high_shelf: Biquad::high_shelf(4000.0, 0.0, sample_rate),
+            low_shelf: Biquad::low_shelf(low, 0.0, sample_rate),
+            mid_peak: Biquad::peaking(mid, 0.0, 1.0, sample_rate),
+            high_shelf: Biquad::high_shelf(high, 0.0, sample_rate),
             sample_rate,
+            slope: 1.0,
+            gains: (0.0, 0.0, 0.0),
+            freqs: Self::DEFAULT_FREQS,
         }
     }
 
     pub fn set_gains(&mut self, low_db: f32, mid_db: f32, high_db: f32) {
-        self.low_shelf = Biquad::low_shelf(200.0, low_db, self.sample_rate);
-        self.mid_peak = Biquad::peaking(1000.0, mid_db, 1.0, self.sample_rate);
-        self.high_shelf = Biquad::high_shelf(4000.0, high_db, self.sample_rate);
+        let (low, mid, high) = self.freqs;
+        self.low_shelf = Biquad::low_shelf_s(low, low_db, self.slope, self.sample_rate);
+        self.mid_peak = Biquad::peaking(mid, mid_db, 1.0, self.sample_rate);
+        self.high_shelf = Biquad::high_shelf_s(high, high_db, self.slope, self.sample_rate);
+        self.gains = (low_db, mid_db, high_db);
+    }
+
+    /// Set the band frequencies, kept below Nyquist; rebuilds only when they change
+    pub fn set_freqs(&mut self, low: f32, mid: f32, high: f32) {
+        let max = self.sample_rate * 0.45;
+        let freqs = (low.clamp(10.0, max), mid.clamp(10.0, max), high.clamp(10.0, max));
+        if freqs != self.freqs {
+            self.freqs = freqs;
+            let (low, mid, high) = self.gains;
+            self.set_gains(low, mid, high);
+        }
+    }
+
+    /// Set the shelf slope (S); rebuilds the shelves only when it changes
+    pub fn set_shelf_slope(&mut self, slope: f32) {
+        if slope != self.slope {
+            self.slope = slope;
+            let (low, mid, high) = self.gains;
+            self.set_gains(low, mid, high);
+        }
     }
 
     pub fn process(&mut self, sample: f32) -> f32 {
@@ -195,40 +391,143 @@ impl ThreeBandEq {
     }
 }
 
+/// Complementary high-shelf pair: `pre` boosts the highs at the chain input
+/// and `de` cuts them by the same amount at the output, so the net response
+/// is flat while the stages in between see the emphasized signal
+pub struct Emphasis {
+    pre_l: Biquad,
+    pre_r: Biquad,
+    de_l: Biquad,
+    de_r: Biquad,
+    sample_rate: f32,
+    /// Current (frequency, dB), kept to rebuild only on changes
+    setting: (f32, f32),
+}
+
+impl Emphasis {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            pre_l: Biquad::new(),
+            pre_r: Biquad::new(),
+            de_l: Biquad::new(),
+            de_r: Biquad::new(),
+            sample_rate,
+            setting: (0.0, 0.0),
+        }
+    }
+
+    pub fn set(&mut self, freq: f32, gain_db: f32) {
+        if (freq, gain_db) != self.setting {
+            self.pre_l = Biquad::high_shelf(freq, gain_db, self.sample_rate);
+            self.pre_r = Biquad::high_shelf(freq, gain_db, self.sample_rate);
+            self.de_l = Biquad::high_shelf(freq, -gain_db, self.sample_rate);
+            self.de_r = Biquad::high_shelf(freq, -gain_db, self.sample_rate);
+            self.setting = (freq, gain_db);
+        }
+    }
+
+    pub fn pre(&mut self, left: f32, right: f32) -> (f32, f32) {
+        (self.pre_l.process(left), self.pre_r.process(right))
+    }
+
+    pub fn de(&mut self, left: f32, right: f32) -> (f32, f32) {
+        (self.de_l.process(left), self.de_r.process(right))
+    }
+}
+
 /// Pseudo-surround upmixer: creates rear channel content from stereo
 pub struct Upmixer {
     hp_left: Biquad,
     hp_right: Biquad,
+    /// Ambience delay (spaciousness effect)
     delay_left: DelayBuffer,
     delay_right: DelayBuffer,
+    /// Rear alignment delay (physical speaker distance)
+    rear_delay_left: DelayBuffer,
+    rear_delay_right: DelayBuffer,
     strength: f32,
+    sample_rate: f32,
+    ambience_ms: f32,
+    rear_delay_ms: f32,
+    /// Centre extraction: high-passed mid, steered by mid/side power envelopes
+    center_hp: Biquad,
+    mid_power: f32,
+    side_power: f32,
+    steer_coeff: f32,
 }
 
 impl Upmixer {
+    pub const MAX_AMBIENCE_MS: f32 = 30.0;
+    pub const MAX_REAR_DELAY_MS: f32 = 50.0;
+
     pub fn new(sample_rate: u32) -> Self {
         let sr = sample_rate as f32;
-        // 10ms delay for spaciousness
-        let delay_samples = (sr * 0.010) as usize;
-        
-        let mut delay_left = DelayBuffer::new(delay_samples * 2);
-        let mut delay_right = DelayBuffer::new(delay_samples * 2);
-        delay_left.set_delay_samples(delay_samples);
-        delay_right.set_delay_samples(delay_samples);
-        
-        Self {
+        let ms_to_samples = |ms: f32| (sr * ms / 1000.0) as usize;
+
+        let mut upmixer = Self {
             // Lower cutoff (150Hz) to preserve more bass
             hp_left: Biquad::highpass(150.0, 0.7, sr),
             hp_right: Biquad::highpass(150.0, 0.7, sr),
-            delay_left,
-            delay_right,
-            strength: 4.0,  // 4x strength for matching main volume
-        }
+            delay_left: DelayBuffer::new(ms_to_samples(Self::MAX_AMBIENCE_MS)),
+            delay_right: DelayBuffer::new(ms_to_samples(Self::MAX_AMBIENCE_MS)),
+            rear_delay_left: DelayBuffer::new(ms_to_samples(Self::MAX_REAR_DELAY_MS)),
+            rear_delay_right: DelayBuffer::new(ms_to_samples(Self::MAX_REAR_DELAY_MS)),
+            strength: 2.0,
+            sample_rate: sr,
+            ambience_ms: -1.0,
+            rear_delay_ms: -1.0,
+            // Keeps bass out of the centre speaker against muddiness
+            center_hp: Biquad::highpass(150.0, 0.7, sr),
+            mid_power: 0.0,
+            side_power: 0.0,
+            // ~50 ms steering envelope
+            steer_coeff: 1.0 - (-1.0 / (0.05 * sr)).exp(),
+        };
+        // 10ms delay for spaciousness
+        upmixer.set_ambience_ms(10.0);
+        upmixer.set_rear_delay_ms(0.0);
+        upmixer
     }
 
     pub fn set_strength(&mut self, strength: f32) {
         self.strength = strength.clamp(0.0, 10.0);  // Allow higher values
     }
 
+    /// Set the spaciousness delay (0 to 30 ms)
+    pub fn set_ambience_ms(&mut self, ms: f32) {
+        let ms = ms.clamp(0.0, Self::MAX_AMBIENCE_MS);
+        if ms != self.ambience_ms {
+            self.ambience_ms = ms;
+            let samples = (self.sample_rate * ms / 1000.0) as usize;
+            self.delay_left.set_delay_samples(samples);
+            self.delay_right.set_delay_samples(samples);
+        }
+    }
+
+    /// Set the rear alignment delay applied to the upmix output (0 to 50 ms)
+    pub fn set_rear_delay_ms(&mut self, ms: f32) {
+        let ms = ms.clamp(0.0, Self::MAX_REAR_DELAY_MS);
+        if ms != self.rear_delay_ms {
+            self.rear_delay_ms = ms;
+            let samples = (self.sample_rate * ms / 1000.0) as usize;
+            self.rear_delay_left.set_delay_samples(samples);
+            self.rear_delay_right.set_delay_samples(samples);
+        }
+    }
+
+    /// Correlated (centre-panned) part of a stereo pair for a centre speaker:
+    /// the high-passed mid signal, scaled by how far the mid power exceeds
+    /// the side power, so dialog is steered while wide content stays put
+    pub fn center(&mut self, left: f32, right: f32) -> f32 {
+        let mid = (left + right) * 0.5;
+        let side = (left - right) * 0.5;
+        self.mid_power += self.steer_coeff * (mid * mid - self.mid_power);
+        self.side_power += self.steer_coeff * (side * side - self.side_power);
+        let total = self.mid_power + self.side_power;
+        let steer = if total > 1e-12 { ((self.mid_power - self.side_power) / total).max(0.0) } else { 0.0 };
+        self.center_hp.process(mid) * steer
+    }
+
     /// Process stereo input and return rear channel output
     /// Takes FL/FR, returns RL/RR to be mixed with output
     pub fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
@@ -244,151 +543,800 @@ impl Upmixer {
         let rear_l = (delayed_l * 0.9 + delayed_r * 0.1) * self.strength;
         let rear_r = (delayed_r * 0.9 + delayed_l * 0.1) * self.strength;
         
-        (rear_l, rear_r)
+        // Align with physically further rear speakers
+        (self.rear_delay_left.process(rear_l), self.rear_delay_right.process(rear_r))
+    }
+}
+
+/// Masks sample-level discontinuities (clicks from apps starting/stopping on
+/// the source). A sample is flagged when it departs from the linear
+/// prediction of the previous two by far more than the recent signal
+/// activity explains; the jump is then spread over a short ramp.
+pub struct Declicker {
+    prev: [f32; 2],
+    last_out: f32,
+    /// Smoothed absolute prediction error of the input
+    activity: f32,
+    attack: f32,
+    release: f32,
+    /// Remaining correction added to the input, fading out over `ramp` samples
+    offset: f32,
+    offset_step: f32,
+    ramp: usize,
+}
+
+impl Declicker {
+    /// Prediction errors below this are never treated as clicks
+    const FLOOR: f32 = 0.3;
+    /// ...nor those within this multiple of the recent activity
+    const RATIO: f32 = 10.0;
+    const RAMP_MS: f32 = 1.0;
+
+    pub fn new(sample_rate: u32) -> Self {
+        let sr = sample_rate as f32;
+        Self {
+            prev: [0.0; 2],
+            last_out: 0.0,
+            activity: 0.0,
+            attack: 1.0 - (-1.0 / (sr * 0.001)).exp(),
+            release: 1.0 - (-1.0 / (sr * 0.02)).exp(),
+            offset: 0.0,
+            offset_step: 0.0,
+            ramp: ((sr * Self::RAMP_MS / 1000.0) as usize).max(1),
+        }
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let predicted = 2.0 * self.prev[0] - self.prev[1];
+        let error = (sample - predicted).abs();
+        let threshold = Self::FLOOR.max(Self::RATIO * self.activity);
+        if error > threshold {
+            // Continue from the last output and glide onto the new signal
+            self.offset = self.last_out - sample;
+            self.offset_step = self.offset / self.ramp as f32;
+        }
+        // Clicks don't count as activity, or they would mask the next one
+        let coeff = if error > self.activity { self.attack } else { self.release };
+        self.activity += coeff * (error.min(threshold) - self.activity);
+        self.prev = [sample, self.prev[0]];
+
+        let out = sample + self.offset;
+        if self.offset != 0.0 {
+            self.offset -= self.offset_step;
+            if self.offset * self.offset_step <= 0.0 {
+                self.offset = 0.0;
+            }
+        }
+        self.last_out = out;
+        out
+    }
+}
+
+/// Fast-attack, slow-release gain reduction keeping a stereo sum within unity
+pub struct HeadroomLimiter {
+    gain: f32,
+    release: f32,
+    /// Peak level the output is held under (linear, 1.0 = 0 dBFS)
+    ceiling: f32,
+}
+
+impl HeadroomLimiter {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            gain: 1.0,
+            // ~200ms recovery time constant
+            release: 1.0 / (sample_rate as f32 * 0.2),
+            ceiling: 1.0,
+        }
+    }
+
+    /// Set the ceiling in dBFS (0 dBFS or below)
+    pub fn set_ceiling_db(&mut self, db: f32) {
+        self.ceiling = db_to_linear(db.min(0.0));
+    }
+
+    pub fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let peak = left.abs().max(right.abs());
+        if peak * self.gain > self.ceiling {
+            self.gain = self.ceiling / peak;
+        } else {
+            self.gain += self.release * (1.0 - self.gain);
+        }
+        (left * self.gain, right * self.gain)
+    }
+
+    /// Limit a single channel on its own
+    pub fn process_mono(&mut self, sample: f32) -> f32 {
+        self.process(sample, sample).0
+    }
+}
+
+/// Loudness leveler: downward compression above a threshold, gentle upward
+/// gain below a floor, driven by a linked stereo peak envelope
+pub struct Compander {
+    envelope: f32,
+    attack: f32,
+    release: f32,
+    sample_rate: u32,
+}
+
+impl Compander {
+    /// Compression starts above this level, 3:1
+    const THRESHOLD_DB: f32 = -18.0;
+    const RATIO: f32 = 3.0;
+    /// Quiet passages below the floor are lifted, 1.5:1 up to MAX_BOOST_DB
+    const FLOOR_DB: f32 = -45.0;
+    const UPWARD_RATIO: f32 = 1.5;
+    const MAX_BOOST_DB: f32 = 9.0;
+    /// Below this the signal is treated as silence and never lifted; the boost
+    /// tapers off 1 dB per dB on the way down so there is no step at the gate
+    const GATE_DB: f32 = -70.0;
+
+    pub fn new(sample_rate: u32) -> Self {
+        let mut compander = Self {
+            envelope: 0.0,
+            attack: 0.0,
+            release: 0.0,
+            sample_rate,
+        };
+        compander.set_times(10.0, 300.0);
+        compander
+    }
+
+    /// Set envelope attack/release time constants in ms
+    pub fn set_times(&mut self, attack_ms: f32, release_ms: f32) {
+        let coeff = |ms: f32| (-1000.0 / (ms.max(0.1) * self.sample_rate as f32)).exp();
+        self.attack = coeff(attack_ms);
+        self.release = coeff(release_ms);
+    }
+
+    /// Static gain curve in dB for an envelope level in dB
+    fn gain_db(level_db: f32) -> f32 {
+        if level_db > Self::THRESHOLD_DB {
+            -(level_db - Self::THRESHOLD_DB) * (1.0 - 1.0 / Self::RATIO)
+        } else if level_db < Self::FLOOR_DB && level_db > Self::GATE_DB {
+            ((Self::FLOOR_DB - level_db) * (1.0 - 1.0 / Self::UPWARD_RATIO))
+                .min(Self::MAX_BOOST_DB)
+                .min(level_db - Self::GATE_DB)
+        } else {
+            0.0
+        }
+    }
+
+    pub fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let peak = left.abs().max(right.abs());
+        let coeff = if peak > self.envelope { self.attack } else { self.release };
+        self.envelope = coeff * self.envelope + (1.0 - coeff) * peak;
+
+        let level_db = 20.0 * self.envelope.max(1e-10).log10();
+        let gain = db_to_linear(Self::gain_db(level_db));
+        (left * gain, right * gain)
+    }
+}
+
+/// Uniform white noise in [-1.0, 1.0] from a xorshift32 state (never zero)
+fn xorshift_white(state: &mut u32) -> f32 {
+    *state ^= *state << 13;
+    *state ^= *state >> 17;
+    *state ^= *state << 5;
+    *state as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
+
+/// Requantize to a lower bit depth with TPDF (triangular, ±1 LSB) dither,
+/// optionally with first-order error feedback that tilts the noise toward
+/// high frequencies where it is least audible
+pub struct TpdfDither {
+    state: u32,
+    lsb: f32,
+    pub noise_shaping: bool,
+    /// Quantization error of the previous sample, fed back when shaping
+    error: f32,
+}
+
+impl TpdfDither {
+    pub const DEFAULT_BITS: u32 = 16;
+
+    pub fn new(seed: u32) -> Self {
+        Self { state: seed.max(1), lsb: Self::lsb(Self::DEFAULT_BITS), noise_shaping: true, error: 0.0 }
+    }
+
+    fn lsb(bits: u32) -> f32 {
+        2.0f32.powi(1 - bits.clamp(8, 24) as i32)
+    }
+
+    /// Target bit depth (8-24)
+    pub fn set_bits(&mut self, bits: u32) {
+        self.lsb = Self::lsb(bits);
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let shaped = if self.noise_shaping { sample - self.error } else { sample };
+        // The difference of two uniform values is triangular over ±1
+        let dither = (xorshift_white(&mut self.state) - xorshift_white(&mut self.state)) * 0.5 * self.lsb;
+        let quantized = ((shaped + dither) / self.lsb).round() * self.lsb;
+        self.error = quantized - shaped;
+        quantized
+    }
+}
+
+/// Pink (-3 dB/octave) noise: xorshift white noise through Paul Kellet's
+/// filter, normalized to roughly unit RMS
+pub struct PinkNoise {
+    state: u32,
+    b: [f32; 7],
+}
+
+impl PinkNoise {
+    /// Kellet's filter output has an RMS of about 1.75 for uniform white input
+    const NORMALIZE: f32 = 0.57;
+
+    pub fn new(seed: u32) -> Self {
+        Self { state: seed.max(1), b: [0.0; 7] }
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let white = xorshift_white(&mut self.state);
+        let b = &mut self.b;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.153_852;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b.iter().sum::<f32>() + white * 0.5362;
+        b[6] = white * 0.115926;
+        pink * Self::NORMALIZE
     }
 }
 
 /// Level meter for monitoring audio levels
+/// IEC 61672 A-weighting approximated by a biquad cascade, normalized to 0 dB at 1 kHz
+pub struct AWeighting {
+    stages: [Biquad; 3],
+    gain: f32,
+}
+
+impl AWeighting {
+    pub fn new(sample_rate: u32) -> Self {
+        let sr = sample_rate as f32;
+        let stages = [
+            // Double pole at 20.6 Hz
+            Biquad::highpass(20.6, 0.5, sr),
+            // Single poles at 107.7 Hz and 737.9 Hz combined
+            Biquad::highpass((107.7_f32 * 737.9).sqrt(), (107.7_f32 * 737.9).sqrt() / (107.7 + 737.9), sr),
+            // Double pole at 12194 Hz
+            Biquad::lowpass(12194.0, 0.5, sr),
+        ];
+        let response: f32 = stages.iter().map(|s| s.magnitude_at(1000.0, sr)).product();
+        Self { stages, gain: 1.0 / response }
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        self.stages.iter_mut().fold(sample * self.gain, |x, stage| stage.process(x))
+    }
+}
+
 pub struct LevelMeter {
-    left_rms: f32,
-    right_rms: f32,
-    left_peak: f32,
-    right_peak: f32,
+    /// Smoothed mean square per channel
+    rms: Vec<f32>,
+    peak: Vec<f32>,
     attack: f32,
     release: f32,
+    /// Optional A-weighting of the RMS path (peaks stay unweighted)
+    weighting: Option<Vec<AWeighting>>,
 }
 
 impl LevelMeter {
+    /// Stereo meter
     pub fn new() -> Self {
+        Self::with_channels(2)
+    }
+
+    pub fn with_channels(channels: usize) -> Self {
         Self {
-            left_rms: 0.0,
-            right_rms: 0.0,
-            left_peak: 0.0,
-            right_peak: 0.0,
+            rms: vec![0.0; channels],
+            peak: vec![0.0; channels],
             attack: 0.01,   // Fast attack
             release: 0.001, // Slow release
+            weighting: None,
         }
     }
 
-    pub fn process(&mut self, left: f32, right: f32) {
-        // RMS with smoothing
-        let left_sq = left * left;
-        let right_sq = right * right;
-        
-        let coeff = if left_sq > self.left_rms { self.attack } else { self.release };
-        self.left_rms += coeff * (left_sq - self.left_rms);
-        
-        let coeff = if right_sq > self.right_rms { self.attack } else { self.release };
-        self.right_rms += coeff * (right_sq - self.right_rms);
-        
-        // Peak hold
-        let left_abs = left.abs();
-        let right_abs = right.abs();
-        
-        if left_abs > self.left_peak {
-            self.left_peak = left_abs;
-        } else {
-            self.left_peak *= 0.9995; // Peak decay
+    pub fn channels(&self) -> usize {
+        self.rms.len()
+    }
+
+    /// Enable or disable A-weighting of the RMS reading (no-op if unchanged)
+    pub fn set_a_weighting(&mut self, enabled: bool, sample_rate: u32) {
+        if enabled != self.weighting.is_some() {
+            self.weighting = enabled.then(|| (0..self.channels()).map(|_| AWeighting::new(sample_rate)).collect());
         }
-        
-        if right_abs > self.right_peak {
-            self.right_peak = right_abs;
-        } else {
-            self.right_peak *= 0.9995;
+    }
+
+    pub fn process(&mut self, left: f32, right: f32) {
+        self.process_frame(&[left, right]);
+    }
+
+    /// Meter one interleaved frame; extra samples beyond the meter's channels are ignored
+    pub fn process_frame(&mut self, frame: &[f32]) {
+        for (ch, &sample) in frame.iter().enumerate().take(self.rms.len()) {
+            // RMS with smoothing, weighted if enabled
+            let weighted = match self.weighting {
+                Some(ref mut w) => w[ch].process(sample),
+                None => sample,
+            };
+            let sq = weighted * weighted;
+            let coeff = if sq > self.rms[ch] { self.attack } else { self.release };
+            self.rms[ch] += coeff * (sq - self.rms[ch]);
+
+            // Peak hold
+            let abs = sample.abs();
+            if abs > self.peak[ch] {
+                self.peak[ch] = abs;
+            } else {
+                self.peak[ch] *= 0.9995; // Peak decay
+            }
         }
     }
 
+    /// RMS level of the first two channels in dB
     pub fn get_rms_db(&self) -> (f32, f32) {
-        let left_db = 20.0 * self.left_rms.sqrt().max(1e-10).log10();
-        let right_db = 20.0 * self.right_rms.sqrt().max(1e-10).log10();
-        (left_db.max(-60.0), right_db.max(-60.0))
+        let db = self.rms_db_all();
+        (db[0], db.get(1).copied().unwrap_or(db[0]))
     }
 
+    /// Peak level of the first two channels in dB
     pub fn get_peak_db(&self) -> (f32, f32) {
-        let left_db = 20.0 * self.left_peak.max(1e-10).log10();
-        let right_db = 20.0 * self.right_peak.max(1e-10).log10();
-        (left_db.max(-60.0), right_db.max(-60.0))
+        let db: Vec<f32> = self.peak.iter().map(|p| (20.0 * p.max(1e-10).log10()).max(-60.0)).collect();
+        (db[0], db.get(1).copied().unwrap_or(db[0]))
+    }
+
+    /// RMS level of every channel in dB
+    pub fn rms_db_all(&self) -> Vec<f32> {
+        self.rms.iter().map(|ms| (20.0 * ms.sqrt().max(1e-10).log10()).max(-60.0)).collect()
+    }
+}
+
+/// Slow automatic gain toward a target RMS level, measured by a `LevelMeter`
+/// on its input. Moves a few dB per second, so it evens out quiet and loud
+/// sources without acting like a compressor.
+pub struct AutoGain {
+    meter: LevelMeter,
+    target_db: f32,
+    gain_db: f32,
+    gain: f32,
+    /// Largest gain change per update block
+    step_db: f32,
+    counter: u32,
+}
+
+impl AutoGain {
+    const MAX_BOOST_DB: f32 = 12.0;
+    const MAX_CUT_DB: f32 = 12.0;
+    /// Levels below this are treated as silence/noise floor and hold the gain
+    const GATE_DB: f32 = -50.0;
+    const SLEW_DB_PER_SEC: f32 = 2.0;
+    const BLOCK: u32 = 64;
+
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            meter: LevelMeter::new(),
+            target_db: -20.0,
+            gain_db: 0.0,
+            gain: 1.0,
+            step_db: Self::SLEW_DB_PER_SEC * Self::BLOCK as f32 / sample_rate as f32,
+            counter: 0,
+        }
+    }
+
+    pub fn set_target_db(&mut self, db: f32) {
+        self.target_db = db;
+    }
+
+    pub fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        self.meter.process(left, right);
+        self.counter += 1;
+        if self.counter >= Self::BLOCK {
+            self.counter = 0;
+            let (left_db, right_db) = self.meter.get_rms_db();
+            let level = left_db.max(right_db);
+            if level > Self::GATE_DB {
+                let wanted = (self.target_db - level).clamp(-Self::MAX_CUT_DB, Self::MAX_BOOST_DB);
+                self.gain_db += (wanted - self.gain_db).clamp(-self.step_db, self.step_db);
+                self.gain = db_to_linear(self.gain_db);
+            }
+        }
+        (left * self.gain, right * self.gain)
+    }
+}
+
+/// Detects sustained clipping (runaway levels) on the output
+pub struct ClipWatchdog {
+    block_len: usize,
+    block_pos: usize,
+    block_clipped: bool,
+    clipped_blocks: usize,
+    trigger_blocks: usize,
+    triggered: bool,
+}
+
+impl ClipWatchdog {
+    /// Samples at or above this magnitude count as clipped
+    pub const CLIP_THRESHOLD: f32 = 0.999;
+
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            block_len: (sample_rate as usize / 100).max(1), // 10ms blocks
+            block_pos: 0,
+            block_clipped: false,
+            clipped_blocks: 0,
+            trigger_blocks: 30, // 300ms of consecutive clipping
+            triggered: false,
+        }
+    }
+
+    pub fn process(&mut self, left: f32, right: f32) {
+        if left.abs() >= Self::CLIP_THRESHOLD || right.abs() >= Self::CLIP_THRESHOLD {
+            self.block_clipped = true;
+        }
+
+        self.block_pos += 1;
+        if self.block_pos >= self.block_len {
+            self.block_pos = 0;
+            if self.block_clipped {
+                self.clipped_blocks += 1;
+                if self.clipped_blocks >= self.trigger_blocks {
+                    self.triggered = true;
+                    self.clipped_blocks = 0;
+                }
+            } else {
+                self.clipped_blocks = 0;
+            }
+            self.block_clipped = false;
+        }
+    }
+
+    /// Returns true once per detected runaway period
+    pub fn take_triggered(&mut self) -> bool {
+        std::mem::take(&mut self.triggered)
     }
 }
 
 /// Shared level values for display (thread-safe)
 pub struct SharedLevels {
     // Store as integer (dB * 10) for atomic access
-    left_db: AtomicU32,
-    right_db: AtomicU32,
+    levels: [AtomicU32; SharedLevels::MAX_CHANNELS],
+    /// Number of channels in the last update
+    channels: AtomicUsize,
+    /// Clipped output frames since creation, for clip indicators
+    clips: AtomicU64,
 }
 
 impl SharedLevels {
+    pub const MAX_CHANNELS: usize = 8;
+
     pub fn new() -> Arc<Self> {
         Arc::new(Self {
-            left_db: AtomicU32::new(0),
-            right_db: AtomicU32::new(0),
+            levels: std::array::from_fn(|_| AtomicU32::new(0)),
+            channels: AtomicUsize::new(2),
+            clips: AtomicU64::new(0),
         })
     }
 
+    pub fn add_clip(&self) {
+        self.clips.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Running count of clipped frames; compare with an earlier reading
+    pub fn clip_count(&self) -> u64 {
+        self.clips.load(Ordering::Relaxed)
+    }
+
     pub fn update(&self, left_db: f32, right_db: f32) {
-        // Convert to positive integer (add 60 to make -60..0 -> 0..60)
-        let left = ((left_db + 60.0) * 10.0).clamp(0.0, 600.0) as u32;
-        let right = ((right_db + 60.0) * 10.0).clamp(0.0, 600.0) as u32;
-        self.left_db.store(left, Ordering::Relaxed);
-        self.right_db.store(right, Ordering::Relaxed);
+        self.update_all(&[left_db, right_db]);
+    }
+
+    /// Publish one level per output channel (up to `MAX_CHANNELS`)
+    pub fn update_all(&self, levels_db: &[f32]) {
+        for (slot, db) in self.levels.iter().zip(levels_db) {
+            // Convert to positive integer (add 60 to make -60..0 -> 0..60)
+            slot.store(((db + 60.0) * 10.0).clamp(0.0, 600.0) as u32, Ordering::Relaxed);
+        }
+        self.channels.store(levels_db.len().min(Self::MAX_CHANNELS), Ordering::Relaxed);
+    }
+
+    fn load(&self, ch: usize) -> f32 {
+        self.levels[ch].load(Ordering::Relaxed) as f32 / 10.0 - 60.0
     }
 
     pub fn get(&self) -> (f32, f32) {
-        let left = self.left_db.load(Ordering::Relaxed) as f32 / 10.0 - 60.0;
-        let right = self.right_db.load(Ordering::Relaxed) as f32 / 10.0 - 60.0;
-        (left, right)
+        (self.load(0), self.load(1))
+    }
+
+    /// Levels of all channels from the last update
+    pub fn get_all(&self) -> Vec<f32> {
+        (0..self.channels.load(Ordering::Relaxed)).map(|ch| self.load(ch)).collect()
+    }
+}
+
+/// Per-frame gains of the routed pair, indexed by configured channel
+/// (left/right settings) except `trim`, which belongs to the physical outputs
+#[derive(Debug, Clone, Copy)]
+pub struct PairGains {
+    pub volume: (f32, f32),
+    /// Current value of the ramped mute gains (0 = muted)
+    pub mute: (f32, f32),
+    /// Missing-source gate (0 when the source channel is absent and muted by policy)
+    pub present: (f32, f32),
+    /// Output the upmix alone instead of adding it (full-surround rear pair)
+    pub upmix_only: bool,
+    /// Soft-knee limit each channel after its volume, before the upmix sum
+    pub channel_limit: bool,
+    pub swap: bool,
+    /// -1.0 = full left, 0.0 = center, 1.0 = full right
+    pub balance: f32,
+    pub trim: (f32, f32),
+    pub master: f32,
+}
+
+/// Balance multipliers for the left and right outputs
+pub fn balance_multipliers(balance: f32) -> (f32, f32) {
+    let left = if balance > 0.0 { 1.0 - balance } else { 1.0 };
+    let right = if balance < 0.0 { 1.0 + balance } else { 1.0 };
+    (left, right)
+}
+
+/// Gentle per-channel overload protection: unity below the knee (-3 dBFS),
+/// then a tanh curve that approaches full scale without reaching it
+pub fn soft_knee_limit(sample: f32) -> f32 {
+    const KNEE: f32 = 0.7;
+    let magnitude = sample.abs();
+    if magnitude <= KNEE {
+        return sample;
+    }
+    let limited = KNEE + (1.0 - KNEE) * ((magnitude - KNEE) / (1.0 - KNEE)).tanh();
+    limited.copysign(sample)
+}
+
+/// Mix one frame of the routed pair. The signal flow is fixed:
+///
+/// 1. source select: `source` holds the configured source samples, with the
+///    input gain already applied to the whole captured frame
+/// 2. per channel: volume, mute and the missing-source gate, then the
+///    optional soft-knee channel limit
+/// 3. upmix sum: the upmix is added under the channel's mute (its level comes
+///    from the upmix strength), or replaces the source under volume and mute
+///    in full-surround mode, so a muted channel is always silent
+/// 4. swap: each channel's settings travel with its signal
+/// 5. balance and calibration trim, per physical output
+/// 6. master volume
+///
+/// Nothing here clamps: samples above full scale pass through unchanged.
+/// Upmix normalization follows in the caller, then the rest of the DSP chain
+/// (EQ, limiter, ...), and the output clamp is the single final stage.
+pub fn route_pair(source: (f32, f32), upmix: (f32, f32), gains: &PairGains) -> (f32, f32) {
+    let limit = |x: f32| if gains.channel_limit { soft_knee_limit(x) } else { x };
+    let channel = |sample: f32, upmix: f32, volume: f32, mute: f32, present: f32| {
+        if gains.upmix_only {
+            limit(upmix * volume * mute)
+        } else {
+            limit(sample * volume * mute * present) + upmix * mute
+        }
+    };
+    let mut left = channel(source.0, upmix.0, gains.volume.0, gains.mute.0, gains.present.0);
+    let mut right = channel(source.1, upmix.1, gains.volume.1, gains.mute.1, gains.present.1);
+
+    if gains.swap {
+        std::mem::swap(&mut left, &mut right);
     }
+
+    let (left_mult, right_mult) = balance_multipliers(gains.balance);
+    (
+        left * left_mult * gains.trim.0 * gains.master,
+        right * right_mult * gains.trim.1 * gains.master,
+    )
 }
 
 /// DSP chain combining all effects
 pub struct DspChain {
     pub delay_l: DelayBuffer,
     pub delay_r: DelayBuffer,
+    /// Delay-compensated copy of the unprocessed input for the dry/wet mix
+    dry_delay_l: DelayBuffer,
+    dry_delay_r: DelayBuffer,
     pub eq_l: ThreeBandEq,
     pub eq_r: ThreeBandEq,
+    pub eq_ms_mid: ThreeBandEq,
+    pub eq_ms_side: ThreeBandEq,
+    pub emphasis: Emphasis,
+    pub declick_l: Declicker,
+    pub declick_r: Declicker,
     pub upmixer: Upmixer,
+    pub upmix_headroom: HeadroomLimiter,
+    /// Dither on the rear (upmix) signal ahead of the front+rear sum
+    pub upmix_dither_l: TpdfDither,
+    pub upmix_dither_r: TpdfDither,
+    pub compander: Compander,
+    pub agc: AutoGain,
+    /// Protective 2nd-order high-pass against subsonic content
+    subsonic_l: Biquad,
+    subsonic_r: Biquad,
+    /// Output limiter; `limiter_r` is only used in per-channel mode
+    pub limiter_l: HeadroomLimiter,
+    pub limiter_r: HeadroomLimiter,
     pub meter: LevelMeter,
+    /// Meter over the full output frame, used once more than two channels are output
+    output_meter: Option<LevelMeter>,
+    pub clip_watchdog: ClipWatchdog,
     pub shared_levels: Arc<SharedLevels>,
     pub delay_ms: f32,
     pub eq_enabled: bool,
+    pub ms_eq_enabled: bool,
+    pub emphasis_enabled: bool,
+    /// Mask sample-level discontinuities at the input
+    pub declick_enabled: bool,
     pub upmix_enabled: bool,
+    /// Dither the upmix tail so quiet decays don't reveal quantization
+    pub upmix_dither: bool,
+    /// Steer the correlated front content to a centre speaker (5.1 output)
+    pub upmix_center_enabled: bool,
+    /// Share of the extracted centre moved from the fronts, 0.0 to 1.0
+    pub upmix_center_level: f32,
+    pub compander_enabled: bool,
+    pub agc_enabled: bool,
+    pub subsonic_enabled: bool,
+    subsonic_cutoff: f32,
+    pub limiter_enabled: bool,
+    /// Limit each channel independently instead of linking both to the louder one
+    pub limiter_per_channel: bool,
+    /// Soft-knee limit each routed channel ahead of the upmix sum (see `route_pair`)
+    pub per_channel_limit: bool,
+    pub normalize_upmix: bool,
+    pub output_clamp: bool,
+    /// Scale both channels by the hotter one's overshoot instead of clamping each
+    pub linked_clamp: bool,
+    /// Processed signal share in the output, 0.0 (dry) to 1.0 (wet)
+    pub dry_wet: f32,
+    /// Emergency override: the routed frame is silenced after all processing
+    pub panic_muted: bool,
+    /// A/B override: every stage (upmix included) is skipped, metering still runs
+    pub bypass: bool,
+    /// Output gain dipped to silence while a preset switch swaps settings
+    pub preset_fade: SmoothedValue,
+    /// Diagnostics: invert the left output, collapse to mono
+    pub phase_flip_left: bool,
+    pub mono: bool,
     sample_rate: u32,
     update_counter: u32,
+    /// Frames between `SharedLevels` publishes, from `set_meter_update_ms`
+    update_interval: u32,
     // Cache for EQ settings to avoid unnecessary recalculations
     eq_low_cache: f32,
     eq_mid_cache: f32,
     eq_high_cache: f32,
+    ms_eq_cache: ([f32; 3], [f32; 3]),
 }
 
 impl DspChain {
+    pub const SUBSONIC_DEFAULT_HZ: f32 = 30.0;
+
     pub fn new(sample_rate: u32, shared_levels: Arc<SharedLevels>) -> Self {
         let max_delay = (sample_rate as f32 * 0.2) as usize; // 200ms max
         
         Self {
             delay_l: DelayBuffer::new(max_delay),
             delay_r: DelayBuffer::new(max_delay),
+            dry_delay_l: DelayBuffer::new(max_delay),
+            dry_delay_r: DelayBuffer::new(max_delay),
             eq_l: ThreeBandEq::new(sample_rate as f32),
             eq_r: ThreeBandEq::new(sample_rate as f32),
+            eq_ms_mid: ThreeBandEq::new(sample_rate as f32),
+            eq_ms_side: ThreeBandEq::new(sample_rate as f32),
+            emphasis: Emphasis::new(sample_rate as f32),
+            declick_l: Declicker::new(sample_rate),
+            declick_r: Declicker::new(sample_rate),
             upmixer: Upmixer::new(sample_rate),
+            upmix_headroom: HeadroomLimiter::new(sample_rate),
+            upmix_dither_l: TpdfDither::new(0x5EED_1EF7),
+            upmix_dither_r: TpdfDither::new(0x5EED_4167),
+            compander: Compander::new(sample_rate),
+            agc: AutoGain::new(sample_rate),
+            subsonic_l: Biquad::highpass(Self::SUBSONIC_DEFAULT_HZ, 0.707, sample_rate as f32),
+            subsonic_r: Biquad::highpass(Self::SUBSONIC_DEFAULT_HZ, 0.707, sample_rate as f32),
+            limiter_l: HeadroomLimiter::new(sample_rate),
+            limiter_r: HeadroomLimiter::new(sample_rate),
             meter: LevelMeter::new(),
+            output_meter: None,
+            clip_watchdog: ClipWatchdog::new(sample_rate),
             shared_levels,
             delay_ms: 0.0,
             eq_enabled: false,
+            ms_eq_enabled: false,
+            emphasis_enabled: false,
+            declick_enabled: false,
             upmix_enabled: false,
+            upmix_dither: false,
+            upmix_center_enabled: false,
+            upmix_center_level: 1.0,
+            compander_enabled: false,
+            agc_enabled: false,
+            subsonic_enabled: false,
+            subsonic_cutoff: Self::SUBSONIC_DEFAULT_HZ,
+            limiter_enabled: false,
+            limiter_per_channel: false,
+            per_channel_limit: false,
+            normalize_upmix: true,
+            output_clamp: true,
+            linked_clamp: false,
+            dry_wet: 1.0,
+            panic_muted: false,
+            bypass: false,
+            preset_fade: SmoothedValue::new(1.0, 1),
+            phase_flip_left: false,
+            mono: false,
             sample_rate,
             update_counter: 0,
+            update_interval: Self::meter_interval(sample_rate, Self::DEFAULT_METER_UPDATE_MS),
             eq_low_cache: 0.0,
             eq_mid_cache: 0.0,
             eq_high_cache: 0.0,
+            ms_eq_cache: ([0.0; 3], [0.0; 3]),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Publish the meter every this many ms, whatever the rate or buffer size
+    pub const DEFAULT_METER_UPDATE_MS: f32 = 40.0;
+
+    fn meter_interval(sample_rate: u32, ms: f32) -> u32 {
+        ((sample_rate as f32 * ms / 1000.0) as u32).max(1)
+    }
+
+    /// Set how often the levels are published to `SharedLevels`
+    pub fn set_meter_update_ms(&mut self, ms: f32) {
+        self.update_interval = Self::meter_interval(self.sample_rate, ms);
+    }
+
+    /// A-weight the level meter (metering only, audio is untouched)
+    pub fn set_meter_a_weighting(&mut self, enabled: bool) {
+        self.meter.set_a_weighting(enabled, self.sample_rate);
+        if let Some(ref mut meter) = self.output_meter {
+            meter.set_a_weighting(enabled, self.sample_rate);
+        }
+    }
+
+    /// Meter a complete output frame. Stereo output is already metered by
+    /// `process`; wider frames get a meter per channel, which then takes
+    /// over publishing to `shared_levels`.
+    pub fn meter_output(&mut self, frame: &[f32]) {
+        if frame.len() <= 2 {
+            return;
+        }
+        let a_weighted = self.meter.weighting.is_some();
+        let sample_rate = self.sample_rate;
+        let meter = self.output_meter.get_or_insert_with(|| LevelMeter::with_channels(frame.len()));
+        if meter.channels() != frame.len() {
+            *meter = LevelMeter::with_channels(frame.len());
+        }
+        meter.set_a_weighting(a_weighted, sample_rate);
+        meter.process_frame(frame);
+        // `process` just ran for this frame and resets the counter on its publish tick
+        if self.update_counter == 0 {
+            self.shared_levels.update_all(&meter.rms_db_all());
         }
     }
 
+
     pub fn set_delay_ms(&mut self, ms: f32) {
         self.delay_ms = ms;
         let samples = (self.sample_rate as f32 * ms / 1000.0) as usize;
         self.delay_l.set_delay_samples(samples);
         self.delay_r.set_delay_samples(samples);
+        self.dry_delay_l.set_delay_samples(samples);
+        self.dry_delay_r.set_delay_samples(samples);
     }
 
     pub fn set_eq(&mut self, low_db: f32, mid_db: f32, high_db: f32) {
@@ -405,8 +1353,65 @@ impl DspChain {
         }
     }
 
+    /// Set the output limiter ceiling in dBFS
+    pub fn set_limiter_ceiling_db(&mut self, db: f32) {
+        self.limiter_l.set_ceiling_db(db);
+        self.limiter_r.set_ceiling_db(db);
+    }
+
+    /// Set the subsonic filter cutoff (20 to 80 Hz, Butterworth Q)
+    pub fn set_subsonic_cutoff(&mut self, hz: f32) {
+        let hz = hz.clamp(20.0, 80.0);
+        if hz != self.subsonic_cutoff {
+            let rate = self.sample_rate as f32;
+            self.subsonic_l = Biquad::highpass(hz, 0.707, rate);
+            self.subsonic_r = Biquad::highpass(hz, 0.707, rate);
+            self.subsonic_cutoff = hz;
+        }
+    }
+
+    /// Set the shelf slope (S) of all EQ bands
+    pub fn set_eq_shelf_slope(&mut self, slope: f32) {
+        for eq in [&mut self.eq_l, &mut self.eq_r, &mut self.eq_ms_mid, &mut self.eq_ms_side] {
+            eq.set_shelf_slope(slope);
+        }
+    }
+
+    /// Set the (low, mid, high) band frequencies of every EQ in the chain
+    pub fn set_eq_freqs(&mut self, (low, mid, high): (f32, f32, f32)) {
+        for eq in [&mut self.eq_l, &mut self.eq_r, &mut self.eq_ms_mid, &mut self.eq_ms_side] {
+            eq.set_freqs(low, mid, high);
+        }
+    }
+
+    /// Set mid/side EQ gains as [low, mid, high] dB for the mid and side signals
+    pub fn set_ms_eq(&mut self, mid: [f32; 3], side: [f32; 3]) {
+        if (mid, side) != self.ms_eq_cache {
+            self.eq_ms_mid.set_gains(mid[0], mid[1], mid[2]);
+            self.eq_ms_side.set_gains(side[0], side[1], side[2]);
+            self.ms_eq_cache = (mid, side);
+        }
+    }
+
     /// Process a stereo frame (L, R) and return processed (L, R)
     pub fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        if self.bypass {
+            self.update_meters(left, right);
+            return (left, right);
+        }
+
+        // Glitch masking on the raw input, ahead of everything that could smear it
+        let (left, right) = if self.declick_enabled {
+            (self.declick_l.process(left), self.declick_r.process(right))
+        } else {
+            (left, right)
+        };
+        // Pre-emphasis ahead of both the wet and dry paths, undone at the end
+        let (left, right) = if self.emphasis_enabled {
+            self.emphasis.pre(left, right)
+        } else {
+            (left, right)
+        };
         let mut l = left;
         let mut r = right;
 
@@ -416,38 +1421,285 @@ impl DspChain {
             r = self.eq_r.process(r);
         }
 
+        // Apply mid/side EQ if enabled
+        if self.ms_eq_enabled {
+            let mid = self.eq_ms_mid.process((l + r) * 0.5);
+            let side = self.eq_ms_side.process((l - r) * 0.5);
+            l = mid + side;
+            r = mid - side;
+        }
+
+        // Slow makeup gain toward the AGC target
+        if self.agc_enabled {
+            (l, r) = self.agc.process(l, r);
+        }
+
         // Apply delay
         l = self.delay_l.process(l);
         r = self.delay_r.process(r);
 
-        // Update level meter
+        // Blend with the equally delayed dry input
+        let dry_l = self.dry_delay_l.process(left);
+        let dry_r = self.dry_delay_r.process(right);
+        if self.dry_wet < 1.0 {
+            l = self.dry_wet * l + (1.0 - self.dry_wet) * dry_l;
+            r = self.dry_wet * r + (1.0 - self.dry_wet) * dry_r;
+        }
+
+        // Speaker protection covers the dry share too, ahead of the dynamics
+        if self.subsonic_enabled {
+            l = self.subsonic_l.process(l);
+            r = self.subsonic_r.process(r);
+        }
+
+        // Level loudness ahead of the output limiter/clamp
+        if self.compander_enabled {
+            (l, r) = self.compander.process(l, r);
+        }
+        if self.limiter_enabled {
+            if self.limiter_per_channel {
+                l = self.limiter_l.process_mono(l);
+                r = self.limiter_r.process_mono(r);
+            } else {
+                (l, r) = self.limiter_l.process(l, r);
+            }
+        }
+        if self.emphasis_enabled {
+            (l, r) = self.emphasis.de(l, r);
+        }
+
+        // Diagnostic overrides
+        if self.mono {
+            let m = (l + r) * 0.5;
+            l = m;
+            r = m;
+        }
+        if self.phase_flip_left {
+            l = -l;
+        }
+
+        self.update_meters(l, r);
+        (l, r)
+    }
+
+    /// Update level meter and clip watchdog with an output frame
+    fn update_meters(&mut self, l: f32, r: f32) {
         self.meter.process(l, r);
+        self.clip_watchdog.process(l, r);
+        if l.abs() >= ClipWatchdog::CLIP_THRESHOLD || r.abs() >= ClipWatchdog::CLIP_THRESHOLD {
+            self.shared_levels.add_clip();
+        }
         
-        // Update shared levels periodically (every 256 samples)
+        // Update shared levels periodically (every `update_interval` frames)
         self.update_counter += 1;
-        if self.update_counter >= 256 {
+        if self.update_counter >= self.update_interval {
             self.update_counter = 0;
-            let (left_db, right_db) = self.meter.get_rms_db();
-            self.shared_levels.update(left_db, right_db);
+            if self.output_meter.is_none() {
+                let (left_db, right_db) = self.meter.get_rms_db();
+                self.shared_levels.update(left_db, right_db);
+            }
         }
-
-        (l, r)
     }
 
     /// Get upmixed rear channels from front stereo
     pub fn get_upmix(&mut self, front_l: f32, front_r: f32) -> (f32, f32) {
-        if self.upmix_enabled {
+        if self.bypass {
+            (0.0, 0.0)
+        } else if self.upmix_enabled && self.upmix_dither {
+            let (l, r) = self.upmixer.process(front_l, front_r);
+            (self.upmix_dither_l.process(l), self.upmix_dither_r.process(r))
+        } else if self.upmix_enabled {
             self.upmixer.process(front_l, front_r)
         } else {
             (0.0, 0.0)
         }
     }
+
+    /// Centre channel for the source fronts; the caller subtracts it from
+    /// both fronts so the steered content moves rather than doubles
+    pub fn get_center(&mut self, front_l: f32, front_r: f32) -> f32 {
+        if self.upmix_center_enabled && !self.bypass {
+            self.upmixer.center(front_l, front_r) * self.upmix_center_level
+        } else {
+            0.0
+        }
+    }
+
+    /// Hard-clamp to [-1.0, 1.0] unless disabled for external limiting.
+    /// Linked mode attenuates both channels equally so the balance holds.
+    /// This is the only clamp, applied once per output frame after `process`
+    /// (and the limiter in it), so overs earlier in the chain keep their shape.
+    pub fn clamp_output(&self, left: f32, right: f32) -> (f32, f32) {
+        if self.output_clamp && self.linked_clamp {
+            let peak = left.abs().max(right.abs());
+            if peak > 1.0 { (left / peak, right / peak) } else { (left, right) }
+        } else if self.output_clamp {
+            (left.clamp(-1.0, 1.0), right.clamp(-1.0, 1.0))
+        } else {
+            (left, right)
+        }
+    }
+
+    /// Attenuate the summed front+rear output when it would exceed unity
+    pub fn normalize_upmix_sum(&mut self, left: f32, right: f32) -> (f32, f32) {
+        if self.upmix_enabled && self.normalize_upmix && !self.bypass {
+            self.upmix_headroom.process(left, right)
+        } else {
+            (left, right)
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_pink_noise_level_and_tilt() {
+        let mut noise = PinkNoise::new(1);
+        let samples: Vec<f32> = (0..96000).map(|_| noise.next_sample()).collect();
+        let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+        assert!((0.8..1.25).contains(&rms), "rms {}", rms);
+
+        // Equal energy per octave: more below 200 Hz than above 5 kHz,
+        // where white noise would have nearly all of it above
+        let energy = |mut filter: Biquad| samples.iter().map(|&s| filter.process(s).powi(2)).sum::<f32>();
+        let low = energy(Biquad::lowpass(200.0, 0.707, 48000.0));
+        let high = energy(Biquad::highpass(5000.0, 0.707, 48000.0));
+        assert!(low > high, "low {} high {}", low, high);
+    }
+
+    fn unity_gains() -> PairGains {
+        PairGains {
+            volume: (1.0, 1.0),
+            mute: (1.0, 1.0),
+            present: (1.0, 1.0),
+            upmix_only: false,
+            channel_limit: false,
+            swap: false,
+            balance: 0.0,
+            trim: (1.0, 1.0),
+            master: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_route_pair_order() {
+        let source = (0.5, 0.5);
+        let upmix = (0.2, 0.2);
+
+        // Muting left then panning right keeps the left output silent,
+        // including the upmix share
+        let gains = PairGains { mute: (0.0, 1.0), balance: 0.5, ..unity_gains() };
+        let (l, r) = route_pair(source, upmix, &gains);
+        assert_eq!(l, 0.0);
+        assert!((r - 0.7).abs() < 1e-6);
+
+        // Mute happens before swap: the silence moves to the right output,
+        // while balance still applies to the physical outputs
+        let gains = PairGains { mute: (0.0, 1.0), swap: true, balance: 0.5, ..unity_gains() };
+        let (l, r) = route_pair(source, upmix, &gains);
+        assert!((l - 0.35).abs() < 1e-6);
+        assert_eq!(r, 0.0);
+
+        // Volume and trim are on different sides of the swap
+        let gains = PairGains { volume: (0.5, 1.0), trim: (1.0, 0.25), swap: true, ..unity_gains() };
+        let (l, r) = route_pair((1.0, 1.0), (0.0, 0.0), &gains);
+        assert!((l - 1.0).abs() < 1e-6);
+        assert!((r - 0.125).abs() < 1e-6);
+
+        // Full surround: the upmix alone, under volume and mute
+        let gains = PairGains { upmix_only: true, volume: (0.5, 1.0), mute: (1.0, 0.0), master: 0.5, ..unity_gains() };
+        let (l, r) = route_pair(source, upmix, &gains);
+        assert!((l - 0.05).abs() < 1e-6);
+        assert_eq!(r, 0.0);
+    }
+
+    #[test]
+    fn test_channel_limit_spares_other_channel() {
+        // Left driven 4x hot, right at a normal level
+        let gains = PairGains { volume: (4.0, 1.0), channel_limit: true, ..unity_gains() };
+        let (l, r) = route_pair((0.5, 0.5), (0.0, 0.0), &gains);
+        assert!(l < 1.0 && l > 0.9, "left {}", l);
+        assert_eq!(r, 0.5);
+
+        // Below the knee the limit is transparent, and it keeps the sign
+        assert_eq!(soft_knee_limit(0.6), 0.6);
+        assert_eq!(soft_knee_limit(-3.0), -soft_knee_limit(3.0));
+
+        // Off: the hot channel passes through unlimited
+        let gains = PairGains { channel_limit: false, ..gains };
+        assert_eq!(route_pair((0.5, 0.5), (0.0, 0.0), &gains).0, 2.0);
+    }
+
+    #[test]
+    fn test_tpdf_dither() {
+        let mut dither = TpdfDither::new(1);
+        dither.set_bits(8);
+        let lsb = 1.0 / 128.0;
+
+        // Output lands on the 8-bit grid and stays within a few LSB of the input
+        for i in 0..1000 {
+            let x = (i as f32 * 0.01).sin() * 0.01;
+            let y = dither.process(x);
+            assert!(((y / lsb).round() * lsb - y).abs() < 1e-6, "off grid {}", y);
+            assert!((y - x).abs() <= 3.0 * lsb, "error {} at {}", y - x, x);
+        }
+
+        // A constant below one LSB is preserved on average instead of truncated
+        dither.noise_shaping = false;
+        let mean = (0..20000).map(|_| dither.process(0.25 * lsb)).sum::<f32>() / 20000.0;
+        assert!((mean - 0.25 * lsb).abs() < 0.05 * lsb, "mean {}", mean / lsb);
+    }
+
+    #[test]
+    fn test_declicker() {
+        let rate = 48000;
+
+        // A steady tone passes untouched
+        let mut declick = Declicker::new(rate);
+        for i in 0..4800 {
+            let x = (i as f32 * 2.0 * std::f32::consts::PI * 2000.0 / rate as f32).sin() * 0.9;
+            assert_eq!(declick.process(x), x, "sample {}", i);
+        }
+
+        // A step out of silence is spread over the ramp, then tracks the input
+        let mut declick = Declicker::new(rate);
+        let out: Vec<f32> = (0..200).map(|i| declick.process(if i < 100 { 0.0 } else { 0.8 })).collect();
+        let max_jump = out.windows(2).map(|w| (w[1] - w[0]).abs()).fold(0.0, f32::max);
+        assert!(max_jump < 0.1, "max jump {}", max_jump);
+        assert_eq!(out[199], 0.8);
+
+        // An isolated spike is removed
+        let mut declick = Declicker::new(rate);
+        let out: Vec<f32> = (0..100).map(|i| declick.process(if i == 50 { 0.9 } else { 0.0 })).collect();
+        assert!(out.iter().all(|s| s.abs() < 1e-6), "{:?}", &out[48..54]);
+    }
+
+    #[test]
+    fn test_subsonic_filter() {
+        let rate = 48000;
+        let peak = |freq: f32| {
+            let mut chain = DspChain::new(rate, SharedLevels::new());
+            chain.subsonic_enabled = true;
+            chain.set_subsonic_cutoff(40.0);
+            // Skip two seconds of settling, then take the peak over one more
+            (0..rate as usize * 3)
+                .map(|i| {
+                    let x = (i as f32 * 2.0 * std::f32::consts::PI * freq / rate as f32).sin() * 0.5;
+                    chain.process(x, x).0
+                })
+                .skip(rate as usize * 2)
+                .fold(0.0, |max: f32, s| max.max(s.abs()))
+        };
+
+        // 12 dB/octave: two octaves below the cutoff is down by ~24 dB
+        assert!(peak(10.0) < 0.5 * 0.1, "10 Hz peak {}", peak(10.0));
+        // Above the cutoff the level is barely touched
+        assert!(peak(100.0) > 0.5 * 0.95, "100 Hz peak {}", peak(100.0));
+        assert!((peak(1000.0) - 0.5).abs() < 0.01, "1 kHz peak {}", peak(1000.0));
+    }
+
     #[test]
     fn test_delay_buffer() {
         let mut delay = DelayBuffer::new(100);
@@ -461,6 +1713,424 @@ mod tests {
         assert_eq!(delay.process(1.0), 1.0);
     }
 
+    #[test]
+    fn test_shelf_slope_response() {
+        let rate = 48000.0;
+        let db = |f: &Biquad, freq: f32| 20.0 * f.magnitude_at(freq, rate).log10();
+
+        // S = 1 reproduces the fixed-slope shelf
+        let default = Biquad::low_shelf(200.0, 12.0, rate);
+        let s1 = Biquad::low_shelf_s(200.0, 12.0, 1.0, rate);
+        for (a, b) in [(default.b0, s1.b0), (default.b1, s1.b1), (default.a1, s1.a1), (default.a2, s1.a2)] {
+            assert!((a - b).abs() < 1e-6);
+        }
+
+        // Both slopes reach full gain and cross half gain at the corner,
+        // but the gentler one spreads further past it
+        let gentle = Biquad::low_shelf_s(200.0, 12.0, 0.5, rate);
+        for f in [&s1, &gentle] {
+            assert!((db(f, 10.0) - 12.0).abs() < 0.5);
+            assert!((db(f, 200.0) - 6.0).abs() < 0.5);
+        }
+        assert!(db(&gentle, 800.0) > db(&s1, 800.0) + 0.5);
+
+        let gentle_high = Biquad::high_shelf_s(4000.0, 12.0, 0.5, rate);
+        let steep_high = Biquad::high_shelf_s(4000.0, 12.0, 1.0, rate);
+        assert!(db(&gentle_high, 1000.0) > db(&steep_high, 1000.0) + 0.5);
+    }
+
+    #[test]
+    fn test_eq_custom_freqs() {
+        let rate = 48000.0;
+        let mut eq = ThreeBandEq::new(rate);
+        eq.set_gains(0.0, 12.0, 0.0);
+        let db = |eq: &ThreeBandEq, freq: f32| 20.0 * eq.mid_peak.magnitude_at(freq, rate).log10();
+        assert!((db(&eq, 1000.0) - 12.0).abs() < 0.1);
+
+        // Moving the band keeps the gain and follows the new center
+        eq.set_freqs(150.0, 2500.0, 4000.0);
+        assert!((db(&eq, 2500.0) - 12.0).abs() < 0.1);
+        assert!(db(&eq, 1000.0) < 9.0);
+
+        // Frequencies past Nyquist are pulled back below it
+        eq.set_freqs(200.0, 1000.0, 30000.0);
+        assert_eq!(eq.freqs.2, rate * 0.45);
+    }
+
+    #[test]
+    fn test_emphasis_pair_is_flat() {
+        let rate = 48000.0;
+        let mut emphasis = Emphasis::new(rate);
+        emphasis.set(3200.0, 10.0);
+
+        // The pre stage boosts the highs...
+        assert!(20.0 * emphasis.pre_l.magnitude_at(15000.0, rate).log10() > 9.0);
+
+        // ...and the pair passes an impulse through unchanged
+        for i in 0..256 {
+            let x = if i == 0 { 1.0 } else { 0.0 };
+            let (l, r) = emphasis.pre(x, x);
+            let (l, r) = emphasis.de(l, r);
+            assert!((l - x).abs() < 1e-4 && (r - x).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_biquad_near_nyquist_is_finite() {
+        let filters = [
+            Biquad::high_shelf(30000.0, 6.0, 44100.0),
+            Biquad::peaking(22050.0, -6.0, 1.0, 44100.0),
+            Biquad::low_shelf(22000.0, 12.0, 44100.0),
+            Biquad::highpass(50000.0, 0.7, 44100.0),
+        ];
+        for mut f in filters {
+            for c in [f.b0, f.b1, f.b2, f.a1, f.a2] {
+                assert!(c.is_finite());
+            }
+            for i in 0..1000 {
+                assert!(f.process(if i % 2 == 0 { 1.0 } else { -1.0 }).is_finite());
+            }
+        }
+    }
+
+    #[test]
+    fn test_upmixer_rear_delay() {
+        fn peak_index(upmixer: &mut Upmixer) -> usize {
+            (0..4800)
+                .map(|i| upmixer.process(if i == 0 { 1.0 } else { 0.0 }, 0.0).0.abs())
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .unwrap()
+                .0
+        }
+
+        let mut base = Upmixer::new(48000);
+        let mut delayed = Upmixer::new(48000);
+        delayed.set_rear_delay_ms(5.0);
+        assert_eq!(peak_index(&mut delayed) - peak_index(&mut base), 240);
+
+        // Ambience and rear delays add up
+        let mut both = Upmixer::new(48000);
+        both.set_ambience_ms(0.0);
+        both.set_rear_delay_ms(10.0);
+        assert_eq!(peak_index(&mut both), peak_index(&mut base));
+    }
+
+    #[test]
+    fn test_dry_wet_full_dry_bypasses_eq() {
+        let mut chain = DspChain::new(48000, SharedLevels::new());
+        chain.eq_enabled = true;
+        chain.set_eq(12.0, -12.0, 12.0);
+        chain.set_delay_ms(1.0);
+        chain.dry_wet = 0.0;
+
+        // Fully dry output is the input delayed by 48 samples
+        let input: Vec<f32> = (0..200).map(|i| (i as f32 * 0.1).sin()).collect();
+        let output: Vec<f32> = input.iter().map(|&x| chain.process(x, -x).0).collect();
+        for i in 48..200 {
+            assert!((output[i] - input[i - 48]).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_a_weighting_response() {
+        fn gain_db(freq: f32) -> f32 {
+            let mut weighting = AWeighting::new(48000);
+            let mut peak: f32 = 0.0;
+            for i in 0..48000 {
+                let y = weighting.process((2.0 * PI * freq * i as f32 / 48000.0).sin());
+                if i >= 24000 {
+                    peak = peak.max(y.abs());
+                }
+            }
+            20.0 * peak.log10()
+        }
+        assert!(gain_db(1000.0).abs() < 0.5);
+        // Standard A-weighting: -19.1 dB at 100 Hz, +1.2 dB at 2.5 kHz
+        assert!((gain_db(100.0) + 19.1).abs() < 1.0);
+        assert!((gain_db(2500.0) - 1.3).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_db_to_linear() {
+        assert!((db_to_linear(0.0) - 1.0).abs() < 1e-6);
+        assert!((db_to_linear(6.0) - 1.9953).abs() < 1e-3);
+        assert!((db_to_linear(-6.0) - 0.5012).abs() < 1e-3);
+        assert!((db_to_linear(-0.5) * db_to_linear(0.5) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_slew_gain() {
+        // A jump from full to 10% (-20 dB) takes four 6 dB steps
+        let mut gain = 1.0;
+        let mut steps = 0;
+        while gain != 0.1 {
+            let next = slew_gain(gain, 0.1, 6.0);
+            assert!(next < gain && 20.0 * (gain / next).log10() <= 6.0 + 1e-3);
+            gain = next;
+            steps += 1;
+        }
+        assert_eq!(steps, 4);
+
+        // Silence is reached from the -60 dB floor, and a zero step is instant
+        assert_eq!(slew_gain(0.0015, 0.0, 6.0), 0.0);
+        assert!(slew_gain(0.0, 1.0, 6.0) < 0.01);
+        assert_eq!(slew_gain(0.0, 1.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn test_frame_aligner_misaligned_chunks() {
+        // 6-channel frames where each sample encodes (frame * 10 + channel)
+        let channels = 6;
+        let input: Vec<f32> = (0..100)
+            .flat_map(|f| (0..channels).map(move |c| (f * 10 + c) as f32))
+            .collect();
+
+        let mut aligner = FrameAligner::new(channels);
+        let mut output = Vec::new();
+        for chunk in input.chunks(7) {
+            let frames = aligner.push(chunk);
+            assert_eq!(frames.len() % channels, 0);
+            output.extend(frames);
+        }
+
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn test_smoothed_value_ramp() {
+        let mut gain = SmoothedValue::new(1.0, 720); // 15ms at 48kHz
+        gain.set_target(0.0);
+
+        let mut prev = 1.0;
+        for _ in 0..720 {
+            let g = gain.next();
+            assert!(g <= prev);
+            prev = g;
+        }
+        assert_eq!(prev, 0.0);
+        assert_eq!(gain.next(), 0.0);
+    }
+
+    #[test]
+    fn test_clip_watchdog() {
+        let mut watchdog = ClipWatchdog::new(48000);
+        // Short burst (100ms) of full scale should not trigger
+        for _ in 0..4800 {
+            watchdog.process(1.0, 1.0);
+        }
+        for _ in 0..480 {
+            watchdog.process(0.1, 0.1);
+        }
+        assert!(!watchdog.take_triggered());
+
+        // Sustained full scale (500ms) triggers once
+        for _ in 0..24000 {
+            watchdog.process(1.0, -1.0);
+        }
+        assert!(watchdog.take_triggered());
+        assert!(!watchdog.take_triggered());
+    }
+
+    #[test]
+    fn test_clip_counter() {
+        let shared = SharedLevels::new();
+        let mut dsp = DspChain::new(48000, shared.clone());
+        for _ in 0..100 {
+            dsp.process(0.5, -0.5);
+        }
+        assert_eq!(shared.clip_count(), 0);
+        for _ in 0..10 {
+            dsp.process(1.2, 0.5);
+        }
+        assert_eq!(shared.clip_count(), 10);
+    }
+
+    #[test]
+    fn test_upmix_center_steering() {
+        let rate = 48000;
+        let tone = |i: usize| (i as f32 * 2.0 * std::f32::consts::PI * 1000.0 / rate as f32).sin() * 0.5;
+        let settle = rate as usize / 2;
+
+        // Centre-panned content is extracted almost entirely
+        let mut upmixer = Upmixer::new(rate);
+        let center: Vec<f32> = (0..settle + 480).map(|i| upmixer.center(tone(i), tone(i))).collect();
+        let peak = center[settle..].iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!(peak > 0.45 && peak < 0.55, "centred peak {}", peak);
+
+        // Hard-panned and out-of-phase content stays out of the centre
+        for right_gain in [0.0, -1.0] {
+            let mut upmixer = Upmixer::new(rate);
+            let center: Vec<f32> = (0..settle + 480).map(|i| upmixer.center(tone(i), tone(i) * right_gain)).collect();
+            let peak = center[settle..].iter().fold(0.0f32, |m, s| m.max(s.abs()));
+            assert!(peak < 0.01, "peak {} with right gain {}", peak, right_gain);
+        }
+
+        // Off by default and scaled by the level when on
+        let mut dsp = DspChain::new(rate, SharedLevels::new());
+        assert_eq!(dsp.get_center(0.5, 0.5), 0.0);
+        dsp.upmix_center_enabled = true;
+        dsp.upmix_center_level = 0.0;
+        assert_eq!(dsp.get_center(0.5, 0.5), 0.0);
+    }
+
+    #[test]
+    fn test_meter_update_interval_is_time_based() {
+        // Frames until the first publish, at two rates with the same interval
+        let frames_to_publish = |rate: u32| {
+            let shared = SharedLevels::new();
+            let mut dsp = DspChain::new(rate, shared.clone());
+            dsp.set_meter_update_ms(20.0);
+            let initial = shared.get_all();
+            (1..=rate).find(|_| {
+                dsp.process(0.5, 0.5);
+                shared.get_all() != initial
+            })
+        };
+        assert_eq!(frames_to_publish(48000), Some(960));
+        assert_eq!(frames_to_publish(96000), Some(1920));
+    }
+
+    #[test]
+    fn test_dsp_bypass() {
+        let shared = SharedLevels::new();
+        let mut dsp = DspChain::new(48000, shared.clone());
+        dsp.eq_enabled = true;
+        dsp.eq_l.set_gains(12.0, 12.0, 12.0);
+        dsp.upmix_enabled = true;
+        dsp.limiter_enabled = true;
+        dsp.bypass = true;
+
+        // Input comes back untouched and the upmix is silent
+        assert_eq!(dsp.process(1.5, -0.25), (1.5, -0.25));
+        assert_eq!(dsp.get_upmix(0.5, 0.5), (0.0, 0.0));
+        // Metering keeps running, and the effect flags are left as they were
+        assert_eq!(shared.clip_count(), 1);
+        assert!(dsp.eq_enabled && dsp.upmix_enabled && dsp.limiter_enabled);
+    }
+
+    #[test]
+    fn test_headroom_limiter() {
+        let mut limiter = HeadroomLimiter::new(48000);
+        let (l, r) = limiter.process(2.5, -1.5);
+        assert!(l.abs() <= 1.0 && r.abs() <= 1.0);
+
+        // Recovers toward unity gain once the signal is back under 1.0
+        for _ in 0..48000 {
+            limiter.process(0.5, 0.5);
+        }
+        let (l, _) = limiter.process(0.5, 0.5);
+        assert!(l > 0.49);
+
+        // A lowered ceiling holds peaks under it
+        let mut limiter = HeadroomLimiter::new(48000);
+        limiter.set_ceiling_db(-6.0);
+        let (l, r) = limiter.process(0.9, -0.3);
+        assert!((l - db_to_linear(-6.0)).abs() < 1e-6);
+        assert!((r + db_to_linear(-6.0) / 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_compander_stepped_level() {
+        let rate = 48000;
+        let mut compander = Compander::new(rate);
+        let settle = |c: &mut Compander, amp: f32| {
+            let mut out = 0.0f32;
+            // Two seconds, measured over the last half once the envelope has settled
+            for i in 0..rate * 2 {
+                let x = amp * (i as f32 * 0.05).sin();
+                out = out.max(c.process(x, x).0.abs());
+                if i < rate {
+                    out = 0.0;
+                }
+            }
+            out
+        };
+
+        // Loud step is pulled down, quiet step is lifted, mid level is untouched
+        let loud = settle(&mut compander, 0.9);
+        assert!(loud < 0.5, "loud peak {}", loud);
+        let mid = settle(&mut compander, 0.05);
+        assert!((mid - 0.05).abs() < 0.005, "mid peak {}", mid);
+        let quiet = settle(&mut compander, 0.002);
+        assert!(quiet > 0.002 * db_to_linear(2.0) && quiet < 0.002 * db_to_linear(9.5), "quiet peak {}", quiet);
+
+        // Silence is not lifted
+        let silent = settle(&mut compander, 0.0001);
+        assert!(silent < 0.00011, "silent peak {}", silent);
+    }
+
+    #[test]
+    fn test_compander_curve_has_no_steps() {
+        // Sweep the static curve in 0.1 dB steps: no jump steeper than 1 dB/dB,
+        // in particular none at the gate
+        let mut prev = Compander::gain_db(-90.0);
+        for i in 1..=900 {
+            let gain = Compander::gain_db(-90.0 + i as f32 * 0.1);
+            assert!((gain - prev).abs() <= 0.1 + 1e-4, "step of {} dB at {} dB", gain - prev, -90.0 + i as f32 * 0.1);
+            prev = gain;
+        }
+        assert_eq!(Compander::gain_db(Compander::GATE_DB), 0.0);
+    }
+
+    #[test]
+    fn test_limiter_bus_vs_per_channel() {
+        let mut dsp = DspChain::new(48000, SharedLevels::new());
+        dsp.limiter_enabled = true;
+
+        // Bus mode: a hot left channel pulls the right down with it
+        let (l, r) = dsp.process(2.0, 0.5);
+        assert!(l <= 1.0);
+        assert!((r - 0.25).abs() < 1e-6);
+
+        // Per-channel mode: the right channel is left alone
+        let mut dsp = DspChain::new(48000, SharedLevels::new());
+        dsp.limiter_enabled = true;
+        dsp.limiter_per_channel = true;
+        let (l, r) = dsp.process(2.0, 0.5);
+        assert!(l <= 1.0);
+        assert!((r - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_auto_gain_reaches_target() {
+        let rate = 48000;
+        let mut agc = AutoGain::new(rate);
+        agc.set_target_db(-20.0);
+
+        // A sine at 0.045 peak is about -30 dBFS RMS; give it 10 s to settle
+        let mut meter = LevelMeter::new();
+        for i in 0..rate * 10 {
+            let x = 0.045 * (i as f32 * 0.05).sin();
+            let (l, r) = agc.process(x, x);
+            meter.process(l, r);
+        }
+        let (level, _) = meter.get_rms_db();
+        assert!((level + 20.0).abs() < 1.5, "level {}", level);
+
+        // Noise-floor input is gated, never boosted
+        let mut agc = AutoGain::new(rate);
+        for i in 0..rate * 5 {
+            let x = 0.001 * (i as f32 * 0.05).sin();
+            let (l, _) = agc.process(x, x);
+            assert!(l.abs() <= 0.001);
+        }
+    }
+
+    #[test]
+    fn test_ms_eq_flat_is_transparent() {
+        let mut dsp = DspChain::new(48000, SharedLevels::new());
+        dsp.ms_eq_enabled = true;
+        dsp.set_ms_eq([0.0; 3], [0.0; 3]);
+        for i in 0..100 {
+            let l = (i as f32 * 0.1).sin() * 0.5;
+            let r = (i as f32 * 0.07).cos() * 0.3;
+            let (out_l, out_r) = dsp.process(l, r);
+            assert!((out_l - l).abs() < 1e-4);
+            assert!((out_r - r).abs() < 1e-4);
+        }
+    }
+
     #[test]
     fn test_level_meter() {
         let mut meter = LevelMeter::new();
@@ -472,4 +2142,65 @@ mod tests {
         assert!(l > -10.0 && l < -4.0);
         assert!(r > -10.0 && r < -4.0);
     }
+
+    #[test]
+    fn test_overs_reach_the_final_stage_unclipped() {
+        let gains = PairGains {
+            volume: (2.0, 2.0),
+            mute: (1.0, 1.0),
+            present: (1.0, 1.0),
+            upmix_only: false,
+            channel_limit: false,
+            swap: false,
+            balance: 0.0,
+            trim: (1.0, 1.0),
+            master: 1.0,
+        };
+        // A transient at 0.8/0.4 doubled by the channel volume exceeds unity mid-chain
+        let (l, r) = route_pair((0.8, 0.4), (0.0, 0.0), &gains);
+        assert_eq!((l, r), (1.6, 0.8));
+
+        // The bus limiter brings it down with the L/R ratio intact, which an
+        // early clamp to (1.0, 0.8) would have lost
+        let mut dsp = DspChain::new(48000, SharedLevels::new());
+        dsp.limiter_enabled = true;
+        let (out_l, out_r) = dsp.process(l, r);
+        let (out_l, out_r) = dsp.clamp_output(out_l, out_r);
+        assert!(out_l <= 1.0, "left {}", out_l);
+        assert!((out_l / out_r - 2.0).abs() < 1e-4, "ratio {}", out_l / out_r);
+
+        // Without the limiter the final clamp is what catches it
+        let mut dsp = DspChain::new(48000, SharedLevels::new());
+        let (out_l, out_r) = dsp.process(l, r);
+        assert_eq!(dsp.clamp_output(out_l, out_r), (1.0, 0.8));
+    }
+
+    #[test]
+    fn test_linked_clamp_keeps_balance() {
+        let mut dsp = DspChain::new(48000, SharedLevels::new());
+        assert_eq!(dsp.clamp_output(1.6, 0.8), (1.0, 0.8));
+
+        dsp.linked_clamp = true;
+        let (l, r) = dsp.clamp_output(1.6, -0.8);
+        assert!((l - 1.0).abs() < 1e-6 && (r + 0.5).abs() < 1e-6);
+        assert_eq!(dsp.clamp_output(0.5, 0.25), (0.5, 0.25));
+    }
+
+    #[test]
+    fn test_multichannel_levels() {
+        let shared = SharedLevels::new();
+        let mut dsp = DspChain::new(48000, shared.clone());
+        // Quad frames: silent fronts, routed pair at 0.5 in the rear
+        for _ in 0..2048 {
+            let mut frame = [0.0, 0.0, 0.5, 0.5];
+            let (l, r) = dsp.process(frame[2], frame[3]);
+            frame[2] = l;
+            frame[3] = r;
+            dsp.meter_output(&frame);
+        }
+        let levels = shared.get_all();
+        assert_eq!(levels.len(), 4);
+        assert!(levels[0] <= -59.0 && levels[1] <= -59.0);
+        assert!(levels[2] > -10.0 && levels[3] > -10.0);
+    }
 }
@@ -3,13 +3,17 @@
 
 mod audio;
 mod config;
+mod console;
 mod dsp;
 mod tray;
 
 use anyhow::Result;
 use audio::AudioRouter;
-use config::AppConfig;
+use config::{AppConfig, NightMode};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 use muda::MenuEvent;
+use serde::Serialize;
 use tracing::{info, error, warn};
 use winit::application::ApplicationHandler;
 use winit::event::WindowEvent;
@@ -55,6 +59,162 @@ fn set_startup_enabled(enabled: bool) -> Result<()> {
     Ok(())
 }
 
+/// Replace the clipboard contents with `text` (CF_UNICODETEXT)
+fn copy_to_clipboard(text: &str) -> Result<()> {
+    use windows::Win32::Foundation::{GlobalFree, HANDLE, HWND};
+    use windows::Win32::System::DataExchange::{CloseClipboard, EmptyClipboard, OpenClipboard, SetClipboardData};
+    use windows::Win32::System::Memory::{GlobalAlloc, GlobalLock, GlobalUnlock, GMEM_MOVEABLE};
+    use windows::Win32::System::Ole::CF_UNICODETEXT;
+
+    let wide: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
+    unsafe {
+        OpenClipboard(HWND::default())?;
+        let result = (|| -> Result<()> {
+            EmptyClipboard()?;
+            let mem = GlobalAlloc(GMEM_MOVEABLE, wide.len() * 2)?;
+            let ptr = GlobalLock(mem) as *mut u16;
+            if ptr.is_null() {
+                let _ = GlobalFree(mem);
+                anyhow::bail!("GlobalLock failed");
+            }
+            std::ptr::copy_nonoverlapping(wide.as_ptr(), ptr, wide.len());
+            let _ = GlobalUnlock(mem);
+            // On success the clipboard owns the memory
+            if let Err(e) = SetClipboardData(CF_UNICODETEXT.0 as u32, HANDLE(mem.0)) {
+                let _ = GlobalFree(mem);
+                return Err(e.into());
+            }
+            Ok(())
+        })();
+        let _ = CloseClipboard();
+        result
+    }
+}
+
+/// Identifier of the global panic mute hotkey (Ctrl+Alt+M)
+const PANIC_HOTKEY_ID: i32 = 1;
+/// Set by the message hook when the panic hotkey fires, consumed in `about_to_wait`
+static PANIC_HOTKEY_PRESSED: AtomicBool = AtomicBool::new(false);
+
+/// Register Ctrl+Alt+M for the calling thread; WM_HOTKEY arrives in its message queue
+fn register_panic_hotkey() {
+    use windows::Win32::Foundation::HWND;
+    use windows::Win32::UI::Input::KeyboardAndMouse::{RegisterHotKey, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT};
+    unsafe {
+        if let Err(e) = RegisterHotKey(HWND::default(), PANIC_HOTKEY_ID, MOD_CONTROL | MOD_ALT | MOD_NOREPEAT, 'M' as u32) {
+            warn!("Could not register the panic mute hotkey Ctrl+Alt+M (in use by another app?): {}", e);
+        }
+    }
+}
+
+/// Set by the power window when the system resumes from sleep, consumed in `poll`
+static RESUMED_FROM_SLEEP: AtomicBool = AtomicBool::new(false);
+/// Wait after resume before restarting, while devices re-enumerate
+const RESUME_SETTLE: Duration = Duration::from_secs(2);
+
+/// Hidden top-level window on the event loop thread: WM_POWERBROADCAST is
+/// only sent to top-level windows, so the message hook never sees it
+fn create_power_window() {
+    use windows::core::w;
+    use windows::Win32::Foundation::{HINSTANCE, HWND, LPARAM, LRESULT, WPARAM};
+    use windows::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DefWindowProcW, RegisterClassW, HMENU, PBT_APMRESUMEAUTOMATIC, WINDOW_EX_STYLE,
+        WM_POWERBROADCAST, WNDCLASSW, WS_OVERLAPPED,
+    };
+
+    unsafe extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        if msg == WM_POWERBROADCAST && wparam.0 == PBT_APMRESUMEAUTOMATIC as usize {
+            RESUMED_FROM_SLEEP.store(true, Ordering::Relaxed);
+        }
+        unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) }
+    }
+
+    unsafe {
+        let class = WNDCLASSW {
+            lpfnWndProc: Some(wndproc),
+            lpszClassName: w!("split51_power"),
+            ..Default::default()
+        };
+        if RegisterClassW(&class) == 0 {
+            warn!("Could not register the power notification window class, no restart on resume");
+            return;
+        }
+        if let Err(e) = CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            w!("split51_power"),
+            w!("split51"),
+            WS_OVERLAPPED,
+            0, 0, 0, 0,
+            HWND::default(),
+            HMENU::default(),
+            HINSTANCE::default(),
+            None,
+        ) {
+            warn!("Could not create the power notification window, no restart on resume: {}", e);
+        }
+    }
+}
+
+/// How often `auto_enable_process` is looked for
+const AUTO_ENABLE_POLL: Duration = Duration::from_secs(3);
+/// How long the process must stay started or exited before routing follows
+const AUTO_ENABLE_DEBOUNCE: Duration = Duration::from_secs(5);
+
+/// Whether a process with this executable name is running (case-insensitive,
+/// ".exe" optional)
+fn process_running(exe_name: &str) -> bool {
+    use windows::Win32::Foundation::CloseHandle;
+    use windows::Win32::System::Diagnostics::ToolHelp::{
+        CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS,
+    };
+
+    let wanted = exe_name.to_lowercase();
+    let wanted = wanted.strip_suffix(".exe").unwrap_or(&wanted);
+    unsafe {
+        let Ok(snapshot) = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) else {
+            return false;
+        };
+        let mut entry = PROCESSENTRY32W { dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32, ..Default::default() };
+        let mut found = false;
+        let mut next = Process32FirstW(snapshot, &mut entry);
+        while next.is_ok() {
+            let len = entry.szExeFile.iter().position(|&c| c == 0).unwrap_or(entry.szExeFile.len());
+            let name = String::from_utf16_lossy(&entry.szExeFile[..len]).to_lowercase();
+            if name.strip_suffix(".exe").unwrap_or(&name) == wanted {
+                found = true;
+                break;
+            }
+            next = Process32NextW(snapshot, &mut entry);
+        }
+        let _ = CloseHandle(snapshot);
+        found
+    }
+}
+
+/// How often `status_file` is rewritten
+const STATUS_FILE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Snapshot written to `status_file` for dashboards
+#[derive(Serialize)]
+struct StatusSnapshot<'a> {
+    version: &'static str,
+    enabled: bool,
+    running: bool,
+    panic_muted: bool,
+    source: &'a str,
+    target: &'a str,
+    capturing: Option<&'a str>,
+    source_format: Option<&'a str>,
+    /// Output RMS level per channel in dBFS
+    levels_db: Vec<f32>,
+    clipped_frames: u64,
+    latency_ms: Option<f32>,
+    resampler_ratio: Option<f64>,
+    source_drift_ppm: Option<f64>,
+    uptime_secs: Option<u64>,
+    dropouts: Option<u64>,
+}
+
 fn format_balance(bal: f32) -> String {
     if bal < -0.01 {
         format!("{}% Left", (bal.abs() * 100.0) as i32)
@@ -71,6 +231,371 @@ struct App {
     source_name: String,
     target_name: String,
     tray_manager: Option<tray::TrayManager>,
+    night_active: bool,
+    last_night_window: Option<bool>,
+    capture_device: Option<String>,
+    source_format: Option<String>,
+    latency_ms: Option<i32>,
+    resampler: Option<String>,
+    // Router events (level safety volume reductions)
+    events: std::sync::mpsc::Receiver<audio::RouterEvent>,
+    // Clip count at the last poll, and until when the tooltip shows CLIP
+    clip_count: u64,
+    clip_until: Option<Instant>,
+    // Diagnostic overrides, never saved to config
+    diag_phase_flip: bool,
+    diag_mono: bool,
+    dsp_bypass: bool,
+    // Running continuous pink noise test; dropping it stops the stream
+    noise_loop: Option<audio::NoiseLoop>,
+    // Pending restart after resume from sleep
+    resume_restart_at: Option<Instant>,
+    // auto_enable_process: next lookup, last seen state and since when, and
+    // the debounced state routing last followed
+    next_process_check: Instant,
+    auto_process_seen: Option<(bool, Instant)>,
+    auto_process_state: Option<bool>,
+    // status_file: next write, and whether the last one failed (warned once)
+    next_status_write: Instant,
+    status_write_failed: bool,
+}
+
+impl App {
+    /// Save the config, logging failures and flagging the first one in the tray
+    fn save_config(&mut self) {
+        if let Err(e) = self.config.save() {
+            error!("Failed to save config: {:#}", e);
+            if let Some(ref mut tray_manager) = self.tray_manager {
+                tray_manager.notify_save_failed();
+            }
+        }
+    }
+
+    /// Route both speakers from the given sources and mark them in the tray
+    fn set_channel_sources(&mut self, left: config::ChannelSource, right: config::ChannelSource) {
+        self.config.left_channel.source = left;
+        self.config.right_channel.source = right;
+        self.router.set_left_source(left);
+        self.router.set_right_source(right);
+        if let Some(ref mut tray_manager) = self.tray_manager {
+            tray_manager.set_channel_sources(left, right);
+        }
+    }
+
+    /// A manual source pick ends clone stereo; the picked routing is kept
+    fn leave_clone_stereo(&mut self) {
+        if self.config.clone_stereo {
+            self.config.clone_stereo = false;
+            self.config.clone_stereo_restore = None;
+            if let Some(ref mut tray_manager) = self.tray_manager {
+                tray_manager.set_clone_stereo(false);
+            }
+            info!("Clone stereo: false (source picked manually)");
+        }
+    }
+
+    /// Start or pause routing to match `config.enabled` and mark it in the tray
+    fn apply_enabled(&mut self) {
+        if self.config.enabled {
+            if let Err(e) = self.router.start_loopback(&self.source_name, &self.target_name) {
+                error!("Failed to start: {}", e);
+            } else {
+                info!("Routing enabled");
+            }
+        } else {
+            self.router.pause();
+            info!("Routing disabled");
+        }
+        if let Some(ref mut tray_manager) = self.tray_manager {
+            tray_manager.set_enabled(self.config.enabled);
+        }
+    }
+
+    /// Flip the emergency mute. It is saved, so it survives restarts.
+    fn toggle_panic_mute(&mut self) {
+        self.config.panic_muted = !self.config.panic_muted;
+        self.router.set_panic_muted(self.config.panic_muted);
+        if self.config.panic_muted {
+            // Pink noise plays on its own stream, so stop it too
+            if self.noise_loop.take().is_some()
+                && let Some(ref mut tray_manager) = self.tray_manager
+            {
+                tray_manager.set_noise_running(false);
+            }
+            warn!("PANIC MUTE on: all output silenced");
+        } else {
+            info!("Panic mute off");
+        }
+        if let Some(ref mut tray_manager) = self.tray_manager {
+            tray_manager.set_panic_muted(self.config.panic_muted);
+        }
+        self.save_config();
+    }
+
+    /// Re-evaluate the night schedule and apply/revert the night profile on changes
+    fn update_night_mode(&mut self) {
+        use chrono::Timelike;
+        let now = chrono::Local::now();
+        let in_window = self.config.is_night_window(now.hour() * 60 + now.minute());
+
+        // A manual On/Off override lasts until the next schedule boundary
+        let crossed_boundary = self.last_night_window.is_some_and(|w| w != in_window);
+        if crossed_boundary && self.config.night_mode != NightMode::Auto {
+            info!("Night schedule boundary reached, returning night mode to Auto");
+            self.config.night_mode = NightMode::Auto;
+            if let Some(ref mut tray_manager) = self.tray_manager {
+                tray_manager.set_night_mode(NightMode::Auto);
+            }
+            self.save_config();
+        }
+        self.last_night_window = Some(in_window);
+
+        let active = match self.config.night_mode {
+            NightMode::Auto => in_window,
+            NightMode::On => true,
+            NightMode::Off => false,
+        };
+        if active != self.night_active {
+            self.night_active = active;
+            self.apply_night_profile();
+        }
+    }
+
+    /// Follow `auto_enable_process`: enable routing when it starts and disable
+    /// it when it exits. Only debounced changes of the process state act, so a
+    /// manual toggle in between is left alone. Auto changes are not saved.
+    fn update_auto_enable(&mut self) {
+        let Some(name) = self.config.auto_enable_process_name().map(str::to_string) else {
+            return;
+        };
+        let now = Instant::now();
+        if now < self.next_process_check {
+            return;
+        }
+        self.next_process_check = now + AUTO_ENABLE_POLL;
+
+        let running = process_running(&name);
+        let since = match self.auto_process_seen {
+            Some((seen, since)) if seen == running => since,
+            _ => now,
+        };
+        self.auto_process_seen = Some((running, since));
+        if now.duration_since(since) < AUTO_ENABLE_DEBOUNCE || self.auto_process_state == Some(running) {
+            return;
+        }
+        self.auto_process_state = Some(running);
+        if running != self.config.enabled {
+            info!(
+                "Auto-enable: '{}' {}, {} routing",
+                name,
+                if running { "is running" } else { "is not running" },
+                if running { "enabling" } else { "disabling" }
+            );
+            self.config.set_session_enabled(running);
+            self.apply_enabled();
+        }
+    }
+
+    /// Rewrite `status_file` once per `STATUS_FILE_INTERVAL`. The snapshot
+    /// goes to a temp file first and is renamed over the old one, so readers
+    /// never see a partial file.
+    fn write_status_file(&mut self) {
+        let Some(path) = self.config.status_file_path() else {
+            return;
+        };
+        let now = Instant::now();
+        if now < self.next_status_write {
+            return;
+        }
+        self.next_status_write = now + STATUS_FILE_INTERVAL;
+
+        let running = self.router.is_running();
+        let shared_levels = self.router.get_shared_levels();
+        let resampler = self.router.resampler_status();
+        let uptime = self.router.uptime_status();
+        let status = StatusSnapshot {
+            version: env!("CARGO_PKG_VERSION"),
+            enabled: self.config.enabled,
+            running,
+            panic_muted: self.config.panic_muted,
+            source: &self.source_name,
+            target: &self.target_name,
+            capturing: self.capture_device.as_deref(),
+            source_format: self.source_format.as_deref(),
+            levels_db: if running { shared_levels.get_all() } else { Vec::new() },
+            clipped_frames: shared_levels.clip_count(),
+            latency_ms: self.router.latency_estimate_ms(),
+            resampler_ratio: resampler.and_then(|r| r.ratio),
+            source_drift_ppm: resampler.and_then(|r| r.source_drift_ppm),
+            uptime_secs: uptime.map(|(uptime, _)| uptime.as_secs()),
+            dropouts: uptime.map(|(_, dropouts)| dropouts),
+        };
+        let result = serde_json::to_string_pretty(&status).map_err(anyhow::Error::from).and_then(|json| {
+            let tmp = path.with_extension("tmp");
+            std::fs::write(&tmp, json)?;
+            std::fs::rename(&tmp, &path)?;
+            Ok(())
+        });
+        match result {
+            Ok(()) => self.status_write_failed = false,
+            Err(e) if !self.status_write_failed => {
+                warn!("Failed to write status file {}: {}", path.display(), e);
+                self.status_write_failed = true;
+            }
+            Err(_) => {}
+        }
+    }
+
+    /// Plain-text report for bug reports: version, devices, status and config
+    fn diagnostics_text(&self) -> String {
+        use std::fmt::Write;
+        let mut out = String::new();
+        let _ = writeln!(out, "split51 {}", env!("CARGO_PKG_VERSION"));
+        let _ = writeln!(out, "Source: {}", self.source_name);
+        let _ = writeln!(out, "Target: {}", self.target_name);
+        let _ = writeln!(out, "Capturing: {}", self.capture_device.as_deref().unwrap_or("-"));
+        let _ = writeln!(out, "Source format: {}", self.source_format.as_deref().unwrap_or("(not detected)"));
+        match self.latency_ms {
+            Some(ms) => { let _ = writeln!(out, "Latency: ~{} ms", ms); }
+            None => { let _ = writeln!(out, "Latency: -"); }
+        }
+        let _ = writeln!(out, "Resampler: {}", self.resampler.as_deref().unwrap_or("-"));
+        match self.router.uptime_status() {
+            Some((uptime, dropouts)) => {
+                let _ = writeln!(out, "Uptime: {}s, {} dropout(s)", uptime.as_secs(), dropouts);
+            }
+            None => { let _ = writeln!(out, "Routing: stopped"); }
+        }
+        let _ = writeln!(out, "Night mode active: {}", self.night_active);
+
+        let _ = writeln!(out, "\nOutput devices:");
+        match self.router.list_output_devices() {
+            Ok(devices) => {
+                let ids = audio::render_endpoint_ids();
+                for d in devices {
+                    let id = ids.iter().find(|(name, _)| *name == d.name).map_or("?", |(_, id)| id.as_str());
+                    let _ = writeln!(out, "  {} ({} ch, {} Hz) [{}]", d.name, d.channels, d.sample_rate, id);
+                }
+            }
+            Err(e) => { let _ = writeln!(out, "  (failed to enumerate: {})", e); }
+        }
+
+        let _ = writeln!(out, "\nConfig:");
+        match toml::to_string_pretty(&self.config) {
+            Ok(config) => out.push_str(&config),
+            Err(e) => { let _ = writeln!(out, "(failed to serialize: {})", e); }
+        }
+        out
+    }
+
+    /// Switch source and target to a configured output set and restart routing
+    fn select_output_set(&mut self, name: &str) {
+        let Some(set) = self.config.output_sets.iter().find(|s| s.name == name).cloned() else {
+            error!("Unknown output set: {}", name);
+            return;
+        };
+        let devices = match self.router.list_output_devices() {
+            Ok(devices) => devices,
+            Err(e) => {
+                error!("Failed to enumerate devices: {}", e);
+                return;
+            }
+        };
+        let find = |wanted: &str| devices.iter().find(|d| d.name.contains(wanted)).map(|d| d.name.clone());
+        let Some(target) = find(&set.target_device) else {
+            error!("Output set {}: target device not found: {}", name, set.target_device);
+            return;
+        };
+        let source = match &set.source_device {
+            Some(wanted) => match find(wanted) {
+                Some(source) => Some(source),
+                None => {
+                    error!("Output set {}: source device not found: {}", name, wanted);
+                    return;
+                }
+            },
+            None => None,
+        };
+
+        if let Some(ref source) = source {
+            self.source_name = source.clone();
+            self.config.source_device = Some(source.clone());
+            self.config.source_role = None;
+            self.router.set_source_role(None);
+        }
+        self.target_name = target.clone();
+        self.config.target_device = Some(target.clone());
+        self.router.stop();
+        if self.config.enabled {
+            if let Err(e) = self.router.start_loopback(&self.source_name, &self.target_name) {
+                error!("Failed to start: {}", e);
+            } else {
+                info!("Output set {}: {} -> {}", name, self.source_name, self.target_name);
+            }
+        }
+        if let Some(ref mut tray_manager) = self.tray_manager {
+            if source.is_some() {
+                tray_manager.set_source_role(None);
+                tray_manager.set_current_source(Some(&self.source_name));
+            }
+            tray_manager.set_current_target(Some(&self.target_name));
+            tray_manager.set_current_output_set(Some(name));
+        }
+        self.save_config();
+    }
+
+    /// After sleep the devices re-enumerate and open streams are usually dead:
+    /// refresh the device menus and restart routing from scratch
+    fn restart_after_resume(&mut self) {
+        info!("Resumed from sleep, refreshing devices and restarting routing");
+        let devices: Vec<String> = match self.router.list_output_devices() {
+            Ok(devices) => devices.into_iter().map(|d| d.name).collect(),
+            Err(e) => {
+                error!("Failed to enumerate devices after resume: {}", e);
+                Vec::new()
+            }
+        };
+        if let Some(device) = self.config.source_role.and_then(audio::default_render_device_name) {
+            self.source_name = device;
+        }
+        if let Some(ref mut tray_manager) = self.tray_manager {
+            let source = self.config.source_role.is_none().then_some(self.source_name.as_str());
+            if let Err(e) = tray_manager.set_devices(&devices, source, Some(&self.target_name)) {
+                warn!("Failed to refresh the device menus: {}", e);
+            }
+        }
+
+        // A warm-paused session is as stale as a running one
+        self.router.stop();
+        if self.config.enabled {
+            match self.router.start_loopback(&self.source_name, &self.target_name) {
+                Ok(()) => info!("Routing restarted after resume: {} -> {}", self.source_name, self.target_name),
+                Err(e) => error!("Failed to restart routing after resume: {}", e),
+            }
+        }
+    }
+
+    /// Push the configured volume and EQ to the router, with the night
+    /// profile on top while night mode is active. Every volume/EQ change goes
+    /// through here so a change during night mode keeps the cap and bass cut.
+    fn apply_levels(&self) {
+        let (volume, eq_enabled, [low, mid, high]) = self.config.effective_levels(self.night_active);
+        self.router.set_volume(volume);
+        self.router.set_eq_enabled(eq_enabled);
+        self.router.set_eq(low, mid, high);
+    }
+
+    /// Apply the night profile on top of the config, or restore the config values
+    fn apply_night_profile(&self) {
+        self.router.begin_preset_change();
+        self.apply_levels();
+        if self.night_active {
+            let profile = &self.config.night_profile;
+            info!("Night mode on (volume cap {}%, bass {:+} dB)", (profile.max_volume * 100.0) as i32, profile.bass_db);
+        } else {
+            info!("Night mode off");
+        }
+    }
 }
 
 impl ApplicationHandler for App {
@@ -81,245 +606,727 @@ impl ApplicationHandler for App {
     fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
         // Process menu events
         if let Ok(event) = MenuEvent::receiver().try_recv() {
-            if let Some(ref mut tray_manager) = self.tray_manager {
-                if let Some(cmd) = tray_manager.handle_menu_event(&event) {
-                    match cmd {
-                        tray::TrayCommand::ToggleEnabled => {
-                            self.config.enabled = !self.config.enabled;
-                            if self.config.enabled {
-                                if let Err(e) = self.router.start_loopback(&self.source_name, &self.target_name) {
-                                    error!("Failed to start: {}", e);
-                                } else {
-                                    info!("Routing enabled");
-                                }
-                            } else {
-                                self.router.stop();
-                                info!("Routing disabled");
-                            }
-                            tray_manager.set_enabled(self.config.enabled);
-                            let _ = self.config.save();
-                        }
-                        tray::TrayCommand::ToggleSwapChannels => {
-                            self.config.swap_channels = !self.config.swap_channels;
-                            self.router.set_swap_channels(self.config.swap_channels);
-                            tray_manager.set_swap(self.config.swap_channels);
-                            info!("Swap channels: {}", self.config.swap_channels);
-                            let _ = self.config.save();
-                        }
-                        tray::TrayCommand::ToggleCloneStereo => {
-                            self.config.clone_stereo = !self.config.clone_stereo;
-                            // Update channel sources based on clone_stereo mode
-                            if self.config.clone_stereo {
-                                self.config.left_channel.source = config::ChannelSource::FL;
-                                self.config.right_channel.source = config::ChannelSource::FR;
-                            } else {
-                                self.config.left_channel.source = config::ChannelSource::RL;
-                                self.config.right_channel.source = config::ChannelSource::RR;
-                            }
-                            self.router.set_left_source(self.config.left_channel.source);
-                            self.router.set_right_source(self.config.right_channel.source);
-                            tray_manager.set_clone_stereo(self.config.clone_stereo);
-                            info!("Clone stereo: {}", self.config.clone_stereo);
-                            let _ = self.config.save();
-                        }
-                        tray::TrayCommand::ToggleStartup => {
-                            let current = is_startup_enabled();
-                            let new_state = !current;
-                            // Run registry operation and update UI based on result
-                            match set_startup_enabled(new_state) {
-                                Ok(_) => {
-                                    tray_manager.set_startup(new_state);
-                                    info!("Startup: {}", new_state);
-                                }
-                                Err(e) => {
-                                    error!("Failed to toggle startup: {}", e);
-                                    // Keep UI in sync with actual state
-                                    tray_manager.set_startup(current);
-                                }
-                            }
-                        }
-                        tray::TrayCommand::SetVolume(vol) => {
-                            self.config.volume = vol;
-                            self.router.set_volume(vol);
-                            info!("Volume set to {}%", (vol * 100.0) as i32);
-                            let _ = self.config.save();
-                        }
-                        tray::TrayCommand::SetBalance(bal) => {
-                            self.config.balance = bal;
-                            self.router.set_balance(bal);
-                            info!("Balance set to {}", format_balance(bal));
-                            let _ = self.config.save();
-                        }
-                        tray::TrayCommand::SetLeftSource(source) => {
-                            self.config.left_channel.source = source;
-                            self.router.set_left_source(source);
-                            info!("Left source: {:?}", source);
-                            let _ = self.config.save();
-                        }
-                        tray::TrayCommand::SetRightSource(source) => {
-                            self.config.right_channel.source = source;
-                            self.router.set_right_source(source);
-                            info!("Right source: {:?}", source);
-                            let _ = self.config.save();
-                        }
-                        tray::TrayCommand::ToggleLeftMute => {
-                            self.config.left_channel.muted = !self.config.left_channel.muted;
-                            self.router.set_left_muted(self.config.left_channel.muted);
-                            tray_manager.set_left_mute(self.config.left_channel.muted);
-                            info!("Left mute: {}", self.config.left_channel.muted);
-                            let _ = self.config.save();
-                        }
-                        tray::TrayCommand::ToggleRightMute => {
-                            self.config.right_channel.muted = !self.config.right_channel.muted;
-                            self.router.set_right_muted(self.config.right_channel.muted);
-                            tray_manager.set_right_mute(self.config.right_channel.muted);
-                            info!("Right mute: {}", self.config.right_channel.muted);
-                            let _ = self.config.save();
-                        }
-                        tray::TrayCommand::SetLeftVolume(vol) => {
-                            self.config.left_channel.volume = vol;
-                            self.router.set_left_volume(vol);
-                            info!("Left volume: {}%", (vol * 100.0) as i32);
-                            let _ = self.config.save();
-                        }
-                        tray::TrayCommand::SetRightVolume(vol) => {
-                            self.config.right_channel.volume = vol;
-                            self.router.set_right_volume(vol);
-                            info!("Right volume: {}%", (vol * 100.0) as i32);
-                            let _ = self.config.save();
-                        }
-                        tray::TrayCommand::SelectSourceDevice(device) => {
-                            self.source_name = device.clone();
-                            self.config.source_device = Some(device.clone());
-                            self.router.stop();
-                            if self.config.enabled {
-                                if let Err(e) = self.router.start_loopback(&self.source_name, &self.target_name) {
-                                    error!("Failed to start: {}", e);
-                                } else {
-                                    info!("Source changed to: {}", device);
-                                }
-                            }
-                            tray_manager.set_current_source(Some(&device));
-                            let _ = self.config.save();
-                        }
-                        tray::TrayCommand::SelectTargetDevice(device) => {
-                            self.target_name = device.clone();
-                            self.config.target_device = Some(device.clone());
-                            self.router.stop();
-                            if self.config.enabled {
-                                if let Err(e) = self.router.start_loopback(&self.source_name, &self.target_name) {
-                                    error!("Failed to start: {}", e);
-                                } else {
-                                    info!("Target changed to: {}", device);
-                                }
-                            }
-                            tray_manager.set_current_target(Some(&device));
-                            let _ = self.config.save();
-                        }
-                        tray::TrayCommand::TestMainLeft => {
-                            let source = self.source_name.clone();
-                            let router = self.router.clone_for_test();
-                            std::thread::spawn(move || {
-                                if let Err(e) = router.play_test_tone_main(true, &source) {
-                                    error!("Test tone error: {}", e);
-                                }
-                            });
-                        }
-                        tray::TrayCommand::TestMainRight => {
-                            let source = self.source_name.clone();
-                            let router = self.router.clone_for_test();
-                            std::thread::spawn(move || {
-                                if let Err(e) = router.play_test_tone_main(false, &source) {
-                                    error!("Test tone error: {}", e);
-                                }
-                            });
-                        }
-                        tray::TrayCommand::TestSubLeft => {
-                            let router = self.router.clone_for_test();
-                            std::thread::spawn(move || {
-                                if let Err(e) = router.play_test_tone_sub(true) {
-                                    error!("Test tone error: {}", e);
-                                }
-                            });
-                        }
-                        tray::TrayCommand::TestSubRight => {
-                            let router = self.router.clone_for_test();
-                            std::thread::spawn(move || {
-                                if let Err(e) = router.play_test_tone_sub(false) {
-                                    error!("Test tone error: {}", e);
-                                }
-                            });
-                        }
-                        tray::TrayCommand::SetDelayMs(ms) => {
-                            self.config.delay_ms = ms;
-                            self.router.set_delay_ms(ms);
-                            tray_manager.set_delay_ms(ms);
-                            info!("Delay set to {} ms", ms);
-                            let _ = self.config.save();
-                        }
-                        tray::TrayCommand::ToggleEq => {
-                            self.config.eq_enabled = !self.config.eq_enabled;
-                            self.router.set_eq_enabled(self.config.eq_enabled);
-                            if self.config.eq_enabled {
-                                self.router.set_eq(self.config.eq_low, self.config.eq_mid, self.config.eq_high);
-                            }
-                            tray_manager.set_eq_enabled(self.config.eq_enabled);
-                            info!("EQ: {}", self.config.eq_enabled);
-                            let _ = self.config.save();
-                        }
-                        tray::TrayCommand::SetEqLow(db) => {
-                            self.config.eq_low = db;
-                            self.router.set_eq(self.config.eq_low, self.config.eq_mid, self.config.eq_high);
-                            tray_manager.set_eq_low(db);
-                            info!("EQ Low: {} dB", db);
-                            let _ = self.config.save();
-                        }
-                        tray::TrayCommand::SetEqMid(db) => {
-                            self.config.eq_mid = db;
-                            self.router.set_eq(self.config.eq_low, self.config.eq_mid, self.config.eq_high);
-                            tray_manager.set_eq_mid(db);
-                            info!("EQ Mid: {} dB", db);
-                            let _ = self.config.save();
-                        }
-                        tray::TrayCommand::SetEqHigh(db) => {
-                            self.config.eq_high = db;
-                            self.router.set_eq(self.config.eq_low, self.config.eq_mid, self.config.eq_high);
-                            tray_manager.set_eq_high(db);
-                            info!("EQ High: {} dB", db);
-                            let _ = self.config.save();
-                        }
-                        tray::TrayCommand::ToggleUpmix => {
-                            self.config.upmix_enabled = !self.config.upmix_enabled;
-                            self.router.set_upmix_enabled(self.config.upmix_enabled);
-                            tray_manager.set_upmix_enabled(self.config.upmix_enabled);
-                            info!("Upmix: {}", self.config.upmix_enabled);
-                            let _ = self.config.save();
+            let cmd = self.tray_manager.as_mut().and_then(|t| t.handle_menu_event(&event));
+            if cmd.is_some_and(|cmd| self.handle_command(cmd)) {
+                event_loop.exit();
+            }
+        }
+
+        if PANIC_HOTKEY_PRESSED.swap(false, Ordering::Relaxed) {
+            self.toggle_panic_mute();
+        }
+
+        let poll_interval = self.poll();
+        event_loop.set_control_flow(ControlFlow::WaitUntil(Instant::now() + poll_interval));
+    }
+}
+
+impl App {
+    /// Apply a menu command (from the tray or the console). Returns true on quit.
+    fn handle_command(&mut self, cmd: tray::TrayCommand) -> bool {
+        match cmd {
+            tray::TrayCommand::TogglePanicMute => self.toggle_panic_mute(),
+            tray::TrayCommand::ToggleEnabled => {
+                self.config.set_enabled(!self.config.enabled);
+                self.apply_enabled();
+                self.save_config();
+            }
+            tray::TrayCommand::ToggleSwapChannels => {
+                self.config.swap_channels = !self.config.swap_channels;
+                self.router.set_swap_channels(self.config.swap_channels);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_swap(self.config.swap_channels);
+                }
+                info!("Swap channels: {}", self.config.swap_channels);
+                self.save_config();
+            }
+            tray::TrayCommand::ToggleCloneStereo => {
+                self.config.clone_stereo = !self.config.clone_stereo;
+                // Clone the fronts, remembering the selection to go back to
+                let (left, right) = if self.config.clone_stereo {
+                    self.config.clone_stereo_restore = Some([self.config.left_channel.source, self.config.right_channel.source]);
+                    (config::ChannelSource::FL, config::ChannelSource::FR)
+                } else {
+                    match self.config.clone_stereo_restore.take() {
+                        Some([left, right]) => (left, right),
+                        None => (config::ChannelSource::RL, config::ChannelSource::RR),
+                    }
+                };
+                self.set_channel_sources(left, right);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_clone_stereo(self.config.clone_stereo);
+                }
+                info!("Clone stereo: {}", self.config.clone_stereo);
+                self.save_config();
+            }
+            tray::TrayCommand::ToggleStartup => {
+                let current = is_startup_enabled();
+                let new_state = !current;
+                // Run registry operation and update UI based on result
+                match set_startup_enabled(new_state) {
+                    Ok(_) => {
+                        if let Some(ref mut tray_manager) = self.tray_manager {
+                            tray_manager.set_startup(new_state);
                         }
-                        tray::TrayCommand::SetUpmixStrength(strength) => {
-                            self.config.upmix_strength = strength;
-                            self.router.set_upmix_strength(strength);
-                            tray_manager.set_upmix_strength(strength);
-                            info!("Upmix strength: {}x", strength);
-                            let _ = self.config.save();
+                        info!("Startup: {}", new_state);
+                    }
+                    Err(e) => {
+                        error!("Failed to toggle startup: {}", e);
+                        // Keep UI in sync with actual state
+                        if let Some(ref mut tray_manager) = self.tray_manager {
+                            tray_manager.set_startup(current);
                         }
-                        tray::TrayCommand::ToggleSyncMasterVolume => {
-                            self.config.sync_master_volume = !self.config.sync_master_volume;
-                            self.router.set_sync_master_volume(self.config.sync_master_volume);
-                            tray_manager.set_sync_master_volume(self.config.sync_master_volume);
-                            info!("Sync master volume: {}", self.config.sync_master_volume);
-                            let _ = self.config.save();
+                    }
+                }
+            }
+            tray::TrayCommand::SetVolume(vol) => {
+                self.config.volume = vol;
+                self.apply_levels();
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_volume(vol);
+                }
+                info!("Volume set to {}%", (vol * 100.0) as i32);
+                self.save_config();
+            }
+            tray::TrayCommand::SetBalance(bal) => {
+                self.config.balance = bal;
+                self.router.set_balance(bal);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_balance(bal);
+                }
+                info!("Balance set to {}", format_balance(bal));
+                self.save_config();
+            }
+            tray::TrayCommand::SetLeftSource(source) => {
+                self.leave_clone_stereo();
+                self.set_channel_sources(source, self.config.right_channel.source);
+                info!("Left source: {:?}", source);
+                self.save_config();
+            }
+            tray::TrayCommand::SetRightSource(source) => {
+                self.leave_clone_stereo();
+                self.set_channel_sources(self.config.left_channel.source, source);
+                info!("Right source: {:?}", source);
+                self.save_config();
+            }
+            tray::TrayCommand::ToggleLeftMute => {
+                self.config.left_channel.muted = !self.config.left_channel.muted;
+                self.router.set_left_muted(self.config.left_channel.muted);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_left_mute(self.config.left_channel.muted);
+                }
+                info!("Left mute: {}", self.config.left_channel.muted);
+                self.save_config();
+            }
+            tray::TrayCommand::ToggleRightMute => {
+                self.config.right_channel.muted = !self.config.right_channel.muted;
+                self.router.set_right_muted(self.config.right_channel.muted);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_right_mute(self.config.right_channel.muted);
+                }
+                info!("Right mute: {}", self.config.right_channel.muted);
+                self.save_config();
+            }
+            tray::TrayCommand::SetLeftVolume(vol) => {
+                self.config.left_channel.volume = vol;
+                self.router.set_left_volume(vol);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_left_volume(vol);
+                }
+                info!("Left volume: {}%", (vol * 100.0) as i32);
+                self.save_config();
+            }
+            tray::TrayCommand::SetRightVolume(vol) => {
+                self.config.right_channel.volume = vol;
+                self.router.set_right_volume(vol);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_right_volume(vol);
+                }
+                info!("Right volume: {}%", (vol * 100.0) as i32);
+                self.save_config();
+            }
+            tray::TrayCommand::SetLeftTrim(db) => {
+                self.config.left_trim_db = db;
+                self.router.set_left_trim_db(db);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_left_trim(db);
+                }
+                info!("Left trim: {:+.1} dB", db);
+                self.save_config();
+            }
+            tray::TrayCommand::SetRightTrim(db) => {
+                self.config.right_trim_db = db;
+                self.router.set_right_trim_db(db);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_right_trim(db);
+                }
+                info!("Right trim: {:+.1} dB", db);
+                self.save_config();
+            }
+            // Reselecting the current device is a no-op rather than a restart
+            tray::TrayCommand::SelectSourceDevice(device)
+                if device == self.source_name && self.config.source_role.is_none() =>
+            {
+                info!("Source unchanged: {}", device);
+            }
+            tray::TrayCommand::SelectSourceDevice(device) => {
+                self.source_name = device.clone();
+                self.config.source_device = Some(device.clone());
+                self.config.source_role = None;
+                self.router.set_source_role(None);
+                if self.config.enabled {
+                    if let Err(e) = self.router.switch_source(&self.source_name, &self.target_name) {
+                        error!("Failed to start: {}", e);
+                    } else {
+                        info!("Source changed to: {}", device);
+                    }
+                } else {
+                    self.router.stop();
+                }
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_source_role(None);
+                    tray_manager.set_current_source(Some(&device));
+                }
+                self.save_config();
+            }
+            tray::TrayCommand::SelectSourceRole(role)
+                if self.config.source_role == Some(role)
+                    && audio::default_render_device_name(role).as_ref() == Some(&self.source_name) =>
+            {
+                info!("Source unchanged: default {:?} device", role);
+            }
+            tray::TrayCommand::SelectSourceRole(role) => {
+                if let Some(device) = audio::default_render_device_name(role) {
+                    self.source_name = device.clone();
+                    self.config.source_role = Some(role);
+                    self.router.set_source_role(Some(role));
+                    if self.config.enabled {
+                        if let Err(e) = self.router.switch_source(&self.source_name, &self.target_name) {
+                            error!("Failed to start: {}", e);
+                        } else {
+                            info!("Source changed to default {:?} device: {}", role, device);
                         }
-                        tray::TrayCommand::Quit => {
-                            info!("Quit requested");
-                            self.router.stop();
-                            let _ = self.config.save();
-                            event_loop.exit();
+                    } else {
+                        self.router.stop();
+                    }
+                    if let Some(ref mut tray_manager) = self.tray_manager {
+                        tray_manager.set_current_source(None);
+                        tray_manager.set_source_role(Some(role));
+                    }
+                    self.save_config();
+                } else {
+                    error!("No default {:?} render device", role);
+                }
+            }
+            tray::TrayCommand::SelectTargetDevice(device) if device == self.target_name => {
+                info!("Target unchanged: {}", device);
+            }
+            tray::TrayCommand::SelectTargetDevice(device) => {
+                self.target_name = device.clone();
+                self.config.target_device = Some(device.clone());
+                self.router.stop();
+                if self.config.enabled {
+                    if let Err(e) = self.router.start_loopback(&self.source_name, &self.target_name) {
+                        error!("Failed to start: {}", e);
+                    } else {
+                        info!("Target changed to: {}", device);
+                    }
+                }
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_current_target(Some(&device));
+                }
+                self.save_config();
+            }
+            tray::TrayCommand::SelectOutputSet(name) => self.select_output_set(&name),
+            tray::TrayCommand::TestMainLeft => {
+                let source = self.source_name.clone();
+                let router = self.router.clone_for_test();
+                std::thread::spawn(move || {
+                    if let Err(e) = router.play_test_tone_main(true, &source) {
+                        error!("Test tone error: {}", e);
+                    }
+                });
+            }
+            tray::TrayCommand::TestMainRight => {
+                let source = self.source_name.clone();
+                let router = self.router.clone_for_test();
+                std::thread::spawn(move || {
+                    if let Err(e) = router.play_test_tone_main(false, &source) {
+                        error!("Test tone error: {}", e);
+                    }
+                });
+            }
+            tray::TrayCommand::TestSubLeft => {
+                let router = self.router.clone_for_test();
+                std::thread::spawn(move || {
+                    if let Err(e) = router.play_test_tone_sub(true) {
+                        error!("Test tone error: {}", e);
+                    }
+                });
+            }
+            tray::TrayCommand::TestSubRight => {
+                let router = self.router.clone_for_test();
+                std::thread::spawn(move || {
+                    if let Err(e) = router.play_test_tone_sub(false) {
+                        error!("Test tone error: {}", e);
+                    }
+                });
+            }
+            tray::TrayCommand::MeasureLatency => {
+                let router = self.router.clone_for_test().with_target(&self.target_name);
+                std::thread::spawn(move || {
+                    match router.measure_latency() {
+                        Ok(m) => info!("Measured latency: {:.1} ms ({} samples @ {} Hz)", m.ms(), m.samples, m.sample_rate),
+                        Err(e) => error!("Latency measurement failed: {}", e),
+                    }
+                });
+            }
+            tray::TrayCommand::AnalyzeChain => {
+                info!("Signal chain: {}", self.router.analyze_chain());
+            }
+            tray::TrayCommand::ToggleContinuousNoise => {
+                if self.noise_loop.take().is_none() {
+                    if self.config.panic_muted {
+                        // The noise has its own stream, outside the panic-muted chain
+                        warn!("Pink noise not started: panic mute is on");
+                    } else {
+                        let player = self.router.clone_for_test().with_target(&self.target_name);
+                        match player.start_noise(self.config.noise_channel, self.config.noise_level_db) {
+                            Ok(noise) => self.noise_loop = Some(noise),
+                            Err(e) => error!("Failed to start pink noise: {}", e),
                         }
                     }
                 }
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_noise_running(self.noise_loop.is_some());
+                }
+            }
+            tray::TrayCommand::ToggleDiagPhaseFlip => {
+                self.diag_phase_flip = !self.diag_phase_flip;
+                self.router.set_diag_phase_flip_left(self.diag_phase_flip);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_diag_phase_flip(self.diag_phase_flip);
+                }
+                info!("Diagnostic left phase flip: {}", self.diag_phase_flip);
+            }
+            tray::TrayCommand::ToggleDiagMono => {
+                self.diag_mono = !self.diag_mono;
+                self.router.set_diag_mono(self.diag_mono);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_diag_mono(self.diag_mono);
+                }
+                info!("Diagnostic mono check: {}", self.diag_mono);
+            }
+            tray::TrayCommand::ToggleDspBypass => {
+                self.dsp_bypass = !self.dsp_bypass;
+                self.router.set_dsp_bypass(self.dsp_bypass);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_dsp_bypass(self.dsp_bypass);
+                }
+                info!("DSP bypass: {}", self.dsp_bypass);
+            }
+            tray::TrayCommand::SetInputGain(db) => {
+                self.config.input_gain_db = db;
+                self.router.set_input_gain_db(db);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_input_gain(db);
+                }
+                info!("Input gain set to {:+} dB", db);
+                self.save_config();
+            }
+            tray::TrayCommand::SetDelayMs(ms) => {
+                self.config.delay_ms = ms;
+                self.router.set_delay_ms(ms);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_delay_ms(ms);
+                }
+                info!("Delay set to {} ms", ms);
+                self.save_config();
+            }
+            tray::TrayCommand::ToggleEq => {
+                self.config.eq_enabled = !self.config.eq_enabled;
+                self.apply_levels();
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_eq_enabled(self.config.eq_enabled);
+                }
+                info!("EQ: {}", self.config.eq_enabled);
+                self.save_config();
+            }
+            tray::TrayCommand::SetEqLow(db) => {
+                self.config.eq_low = db;
+                self.apply_levels();
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_eq_low(db);
+                }
+                info!("EQ Low: {} dB", db);
+                self.save_config();
+            }
+            tray::TrayCommand::SetEqMid(db) => {
+                self.config.eq_mid = db;
+                self.apply_levels();
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_eq_mid(db);
+                }
+                info!("EQ Mid: {} dB", db);
+                self.save_config();
+            }
+            tray::TrayCommand::SetEqHigh(db) => {
+                self.config.eq_high = db;
+                self.apply_levels();
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_eq_high(db);
+                }
+                info!("EQ High: {} dB", db);
+                self.save_config();
+            }
+            tray::TrayCommand::ToggleMsEq => {
+                self.config.ms_eq_enabled = !self.config.ms_eq_enabled;
+                self.router.set_ms_eq_enabled(self.config.ms_eq_enabled);
+                if self.config.ms_eq_enabled {
+                    self.router.set_ms_eq(self.config.ms_eq_mid.as_array(), self.config.ms_eq_side.as_array());
+                }
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_ms_eq_enabled(self.config.ms_eq_enabled);
+                }
+                info!("Mid/Side EQ: {}", self.config.ms_eq_enabled);
+                self.save_config();
+            }
+            tray::TrayCommand::ToggleUpmix => {
+                self.config.upmix_enabled = !self.config.upmix_enabled;
+                self.router.set_upmix_enabled(self.config.upmix_enabled);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_upmix_enabled(self.config.upmix_enabled);
+                }
+                info!("Upmix: {}", self.config.upmix_enabled);
+                self.save_config();
+            }
+            tray::TrayCommand::ToggleCompander => {
+                self.config.compander_enabled = !self.config.compander_enabled;
+                self.router.set_compander_enabled(self.config.compander_enabled);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_compander_enabled(self.config.compander_enabled);
+                }
+                info!("Leveler: {}", self.config.compander_enabled);
+                self.save_config();
+            }
+            tray::TrayCommand::ToggleSubsonicFilter => {
+                self.config.subsonic_filter = !self.config.subsonic_filter;
+                self.router.set_subsonic_filter(self.config.subsonic_filter);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_subsonic_filter(self.config.subsonic_filter);
+                }
+                info!("Subsonic filter: {}", self.config.subsonic_filter);
+                self.save_config();
+            }
+            tray::TrayCommand::SetSubsonicCutoff(hz) => {
+                self.config.subsonic_cutoff_hz = hz;
+                self.router.set_subsonic_cutoff_hz(hz);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_subsonic_cutoff(hz);
+                }
+                info!("Subsonic cutoff: {} Hz", hz);
+                self.save_config();
+            }
+            tray::TrayCommand::ToggleLimiter => {
+                self.config.limiter_enabled = !self.config.limiter_enabled;
+                self.router.set_limiter_enabled(self.config.limiter_enabled);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_limiter_enabled(self.config.limiter_enabled);
+                }
+                info!("Output limiter: {}", self.config.limiter_enabled);
+                self.save_config();
+            }
+            tray::TrayCommand::SetLimiterCeiling(db) => {
+                self.config.limiter_ceiling_db = db;
+                self.router.set_limiter_ceiling_db(db);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_limiter_ceiling(db);
+                }
+                info!("Limiter ceiling: {} dBFS", db);
+                self.save_config();
+            }
+            tray::TrayCommand::SetUpmixStrength(strength) => {
+                self.config.upmix_strength = strength;
+                self.router.set_upmix_strength(strength);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_upmix_strength(strength);
+                }
+                info!("Upmix strength: {}x", strength);
+                self.save_config();
+            }
+            tray::TrayCommand::SetDryWet(mix) => {
+                self.config.dry_wet = mix;
+                self.router.set_dry_wet(mix);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_dry_wet(mix);
+                }
+                info!("DSP mix: {}% wet", (mix * 100.0).round() as i32);
+                self.save_config();
+            }
+            tray::TrayCommand::SetUpmixRearDelay(ms) => {
+                self.config.upmix_rear_delay_ms = ms;
+                self.router.set_upmix_rear_delay_ms(ms);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_upmix_rear_delay(ms);
+                }
+                info!("Upmix rear delay: {} ms", ms);
+                self.save_config();
+            }
+            tray::TrayCommand::ToggleSyncMasterVolume => {
+                self.config.sync_master_volume = !self.config.sync_master_volume;
+                self.router.set_sync_master_volume(self.config.sync_master_volume);
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_sync_master_volume(self.config.sync_master_volume);
+                }
+                info!("Sync master volume: {}", self.config.sync_master_volume);
+                self.save_config();
+            }
+            tray::TrayCommand::SetNightMode(mode) => {
+                self.config.night_mode = mode;
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_night_mode(mode);
+                }
+                info!("Night mode: {:?}", mode);
+                self.save_config();
+            }
+            tray::TrayCommand::CopyDiagnostics => {
+                match copy_to_clipboard(&self.diagnostics_text()) {
+                    Ok(()) => info!("Diagnostics copied to clipboard"),
+                    Err(e) => error!("Failed to copy diagnostics: {}", e),
+                }
+            }
+            tray::TrayCommand::Quit => {
+                info!("Quit requested");
+                self.noise_loop = None;
+                self.router.stop();
+                self.save_config();
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Periodic housekeeping: status display, reconnects, health checks and
+    /// the night schedule. Returns how long to wait before the next call.
+    fn poll(&mut self) -> Duration {
+        // Restart proactively after sleep rather than waiting for an error
+        if RESUMED_FROM_SLEEP.swap(false, Ordering::Relaxed) {
+            info!("System resumed from sleep, restarting routing in {:?}", RESUME_SETTLE);
+            self.resume_restart_at = Some(Instant::now() + RESUME_SETTLE);
+        }
+        if self.resume_restart_at.is_some_and(|at| Instant::now() >= at) {
+            self.resume_restart_at = None;
+            self.restart_after_resume();
+        }
+
+        // Level safety: lower the saved volume, so the tray and config agree
+        while let Ok(event) = self.events.try_recv() {
+            if let audio::RouterEvent::LevelSafetyReduction { db } = event {
+                self.config.volume *= dsp::db_to_linear(db);
+                self.apply_levels();
+                if let Some(ref mut tray_manager) = self.tray_manager {
+                    tray_manager.set_volume(self.config.volume);
+                }
+                warn!("Level safety: volume reduced by {} dB (now {}%)", -db, (self.config.volume * 100.0) as i32);
+                self.save_config();
+            }
+        }
+
+        // Temporarily follow the default device while the source is held exclusively
+        if let Some(source) = self.router.poll_exclusive_source() {
+            info!("Source temporarily switched to: {}", source);
+            self.source_name = source;
+            if let Some(ref mut tray_manager) = self.tray_manager {
+                tray_manager.set_source_role(None);
+                tray_manager.set_current_source(Some(&self.source_name));
+            }
+        }
+
+        // Mark whichever output set the current routing corresponds to
+        if let Some(ref mut tray_manager) = self.tray_manager {
+            let active = self.config.output_sets.iter()
+                .find(|s| s.matches(&self.source_name, &self.target_name))
+                .map(|s| s.name.as_str());
+            tray_manager.set_current_output_set(active);
+        }
+
+        // The endpoint really being captured, which name matching may have
+        // resolved to a different device than the one selected
+        let capture_device = self.router.capture_device_name();
+        if capture_device != self.capture_device {
+            if let Some(ref mut tray_manager) = self.tray_manager {
+                tray_manager.set_capture_device(capture_device.as_deref());
+            }
+            self.capture_device = capture_device;
+        }
+
+        // Persist the source endpoint/format the capture thread verified
+        let source_format_cache = self.router.source_format_cache();
+        if source_format_cache != self.config.source_format_cache {
+            self.config.source_format_cache = source_format_cache;
+            self.save_config();
+        }
+
+        // Reflect the detected loopback format in the tray, or why it's silent
+        let source_format = if self.router.source_in_exclusive_use() {
+            Some("silent (in exclusive use by another app)".to_string())
+        } else if let Some(expected) = self.router.source_channel_mismatch() {
+            let state = if self.config.pause_on_channel_mismatch { "paused" } else { "routing anyway" };
+            self.router.get_source_format()
+                .map(|f| format!("{} - expected {} ch, {}", f, expected, state))
+        } else if self.router.stereo_passthrough_active() {
+            self.router.get_source_format()
+                .map(|f| format!("{} - routed channels missing, stereo passthrough", f))
+        } else {
+            self.router.get_source_format()
+        };
+        if source_format != self.source_format {
+            if let Some(ref mut tray_manager) = self.tray_manager {
+                tray_manager.set_source_format(source_format.as_deref());
+            }
+            self.source_format = source_format;
+        }
+
+        // Reflect the estimated latency in the tray
+        let latency = self.router.latency_estimate_ms();
+        let latency_ms = latency.map(|ms| ms.round() as i32);
+        if latency_ms != self.latency_ms {
+            if let Some(ref mut tray_manager) = self.tray_manager {
+                tray_manager.set_latency(latency);
+            }
+            self.latency_ms = latency_ms;
+        }
+
+        // Resampling ratio and measured source clock, to diagnose off-pitch audio
+        let resampler = self.router.resampler_status().map(|s| s.to_string());
+        if resampler != self.resampler {
+            if let Some(ref mut tray_manager) = self.tray_manager {
+                tray_manager.set_resampler(resampler.as_deref());
             }
+            self.resampler = resampler;
+        }
+
+        // Any clipped frame since the last poll holds the tooltip's CLIP marker
+        let shared_levels = self.router.get_shared_levels();
+        let clip_count = shared_levels.clip_count();
+        if clip_count != self.clip_count {
+            self.clip_count = clip_count;
+            self.clip_until = Some(Instant::now() + Duration::from_secs_f32(self.config.clip_hold_secs.max(0.0)));
+        }
+        if self.clip_until.is_some_and(|until| Instant::now() >= until) {
+            self.clip_until = None;
+        }
+
+        // Uptime and dropouts change continuously, refresh on every wake-up
+        if let Some(ref mut tray_manager) = self.tray_manager {
+            tray_manager.set_uptime(self.router.uptime_status());
+            let levels = self.router.is_running().then(|| shared_levels.get_all());
+            tray_manager.set_levels(levels.as_deref(), self.clip_until.is_some());
+        }
+
+        self.router.poll_crossfade();
+
+        // Resume routing if the target was taken by another app
+        let waiting_for_target = self.config.enabled && self.router.poll_reconnect();
+
+        // Follow Windows switching the default device for the source role
+        if let Some(source) = self.router.poll_default_device_change() {
+            info!("Source follows the default device: {}", source);
+            self.source_name = source;
+        }
+
+        // Self-heal if the capture thread died silently
+        if self.config.enabled {
+            self.router.check_capture_health();
+        }
+
+        // Night mode schedule and the auto-enable process are checked on every
+        // wake-up. Following a role polls faster so a default device change is
+        // re-routed promptly.
+        self.update_night_mode();
+        self.update_auto_enable();
+        self.write_status_file();
+        let interval = if waiting_for_target || self.config.source_role.is_some() {
+            Duration::from_secs(1)
+        } else {
+            Duration::from_secs(5)
+        };
+        // Wake up again to clear the CLIP marker, look for the auto-enable
+        // process, write the status file or restart after resume
+        let process_check = self.config.auto_enable_process_name().is_some().then_some(self.next_process_check);
+        let status_write = self.config.status_file_path().is_some().then_some(self.next_status_write);
+        [self.resume_restart_at, self.clip_until, process_check, status_write]
+            .into_iter()
+            .flatten()
+            .fold(interval, |interval, at| interval.min(at.saturating_duration_since(Instant::now())))
+    }
+}
+
+/// Check the configured devices and channel layout against what's present
+/// and log a PASS/WARN line per check plus a summary
+fn run_self_test(config: &AppConfig, devices: &[audio::AudioDevice]) {
+    let mut checks: Vec<(bool, String)> = Vec::new();
+    checks.push((!devices.is_empty(), format!("{} output device(s) found", devices.len())));
+
+    // Names resolve like routing does (first device whose name contains the
+    // configured one); more than one match warns, as routing may open the wrong one
+    let mut find = |label: &str, name: Option<String>| {
+        let name = name?;
+        let matches: Vec<_> = devices.iter().filter(|d| d.name.contains(name.as_str())).collect();
+        let result = match matches.len() {
+            0 => format!("{} '{}' not found", label, name),
+            1 => format!("{} '{}' present ({})", label, name, matches[0].name),
+            n => format!("{} '{}' matches {} devices, routing opens '{}'", label, name, n, matches[0].name),
+        };
+        checks.push((matches.len() == 1, result));
+        matches.first().copied()
+    };
+    let source = match config.source_role {
+        Some(role) => find("source (default device)", audio::default_render_device_name(role)),
+        None => find("source", config.source_device.clone()),
+    };
+    let target = find("target", config.target_device.clone());
+
+    if let (Some(src), Some(tgt)) = (source, target) {
+        checks.push((src.name != tgt.name, "source and target are different devices".to_string()));
+    }
+    let needed = [config.left_channel.source, config.right_channel.source]
+        .iter()
+        .map(|s| match s {
+            config::ChannelSource::FL | config::ChannelSource::FR => 2,
+            config::ChannelSource::RL | config::ChannelSource::RR => 4,
+            config::ChannelSource::Index(i) => i.saturating_add(1),
+        })
+        .max()
+        .unwrap_or(2);
+    if let (true, Some(src)) = (needed > 2, source) {
+        checks.push((src.channels >= needed, format!("source has {} ch (routed channels need {}+)", src.channels, needed)));
+    }
+    if let (4 | 6, Some(tgt)) = (config.output_channels, target) {
+        let needed = config.output_channels;
+        checks.push((tgt.channels >= needed, format!("target has {} ch ({}ch output needs {}+)", tgt.channels, needed, needed)));
+    }
+
+    for (ok, what) in &checks {
+        if *ok {
+            info!("Self-test PASS: {}", what);
+        } else {
+            warn!("Self-test WARN: {}", what);
         }
     }
+    let warnings = checks.iter().filter(|(ok, _)| !ok).count();
+    info!("Self-test: {} passed, {} warning(s)", checks.len() - warnings, warnings);
+}
+
+/// Parse a `--test-tone` value into (main speakers, left channel)
+fn parse_test_tone(value: &str) -> Option<(bool, bool)> {
+    match value.to_ascii_lowercase().as_str() {
+        "target-left" => Some((false, true)),
+        "target-right" => Some((false, false)),
+        "main-left" => Some((true, true)),
+        "main-right" => Some((true, false)),
+        _ => None,
+    }
 }
 
 fn print_help() {
@@ -333,6 +1340,13 @@ fn print_help() {
     println!("    -v, --version    Show version");
     println!("    -l, --list       List available audio devices");
     println!("    -q, --quiet      Suppress startup messages");
+    println!("    -c, --config <PATH>  Use a specific config file (.toml or .json)");
+    println!("    --config-overlay <PATH>  Layer an override file on top; saves go there");
+    println!("    --test-tone <WHICH>  Play a test tone and exit");
+    println!("                         (target-left, target-right, main-left, main-right)");
+    println!("    --measure-latency    Measure output latency on the target and exit");
+    println!("    --analyze-chain      Run a 1 kHz test tone through the DSP chain and print the output levels");
+    println!("    --console            Text menu on the terminal instead of the tray icon");
     println!();
     println!("The application runs in the system tray. Right-click the icon for settings.");
 }
@@ -356,6 +1370,50 @@ fn main() -> Result<()> {
         return Ok(());
     }
     
+    // Handle --config <path> / --config=<path>
+    let config_arg = args.iter().enumerate().find_map(|(i, a)| {
+        if a == "-c" || a == "--config" {
+            args.get(i + 1).cloned()
+        } else {
+            a.strip_prefix("--config=").map(|p| p.to_string())
+        }
+    });
+    if let Some(path) = config_arg {
+        AppConfig::set_config_path(path.into());
+    }
+
+    // Handle --config-overlay <path> / --config-overlay=<path>
+    let overlay_arg = args.iter().enumerate().find_map(|(i, a)| {
+        if a == "--config-overlay" {
+            args.get(i + 1).cloned()
+        } else {
+            a.strip_prefix("--config-overlay=").map(|p| p.to_string())
+        }
+    });
+    if let Some(path) = overlay_arg {
+        AppConfig::set_overlay_path(path.into());
+    }
+
+    // Handle --test-tone <which> / --test-tone=<which>
+    let test_tone_arg = args.iter().enumerate().find_map(|(i, a)| {
+        if a == "--test-tone" {
+            Some(args.get(i + 1).cloned().unwrap_or_default())
+        } else {
+            a.strip_prefix("--test-tone=").map(|p| p.to_string())
+        }
+    });
+    let test_tone = match test_tone_arg.as_deref().map(parse_test_tone) {
+        Some(None) => {
+            eprintln!("Error: --test-tone expects target-left, target-right, main-left or main-right");
+            return Ok(());
+        }
+        Some(Some(which)) => Some(which),
+        None => None,
+    };
+
+    let measure_latency = args.iter().any(|a| a == "--measure-latency");
+    let analyze_chain = args.iter().any(|a| a == "--analyze-chain");
+    let console_mode = args.iter().any(|a| a == "--console");
     let quiet = args.iter().any(|a| a == "-q" || a == "--quiet");
     let list_only = args.iter().any(|a| a == "-l" || a == "--list");
 
@@ -364,15 +1422,39 @@ fn main() -> Result<()> {
     info!("split51 starting...");
 
     // Load config
-    let mut config = AppConfig::load().unwrap_or_else(|e| {
-        warn!("Failed to load config: {}, using defaults", e);
-        AppConfig::default()
-    });
+    // A config that exists but can't be read is an error: carrying on with
+    // defaults would overwrite it on the next save
+    let mut config = match AppConfig::load() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Failed to load config: {:#}", e);
+            eprintln!("Error: Failed to load config: {:#}", e);
+            return Err(e);
+        }
+    };
     info!("Config loaded: {:?}", config);
+    if config.start_disabled {
+        info!("start_disabled is set, routing stays off until enabled from the tray");
+        config.apply_start_disabled();
+    }
 
     // Initialize audio router
     let mut router = AudioRouter::new()?;
 
+    // At logon the target (e.g. a USB DAC) may not be enumerated yet
+    if config.startup_delay_ms > 0 {
+        let timeout = Duration::from_millis(config.startup_delay_ms as u64);
+        match config.target_device.as_deref() {
+            Some(target) => {
+                router.wait_for_output_device(target, timeout);
+            }
+            None => {
+                info!("Waiting {:?} before starting (startup_delay_ms)", timeout);
+                std::thread::sleep(timeout);
+            }
+        }
+    }
+
     // List available devices
     let output_devices = router.list_output_devices()?;
     let input_devices = router.list_input_devices()?;
@@ -380,9 +1462,10 @@ fn main() -> Result<()> {
     if !quiet || list_only {
         println!("\n=== Output Devices ===");
         for (i, device) in output_devices.iter().enumerate() {
+            let virtual_hint = if audio::is_likely_virtual(&device.name) { " [virtual]" } else { "" };
             println!(
-                "  [{}] {} ({} ch, {} Hz)",
-                i, device.name, device.channels, device.sample_rate
+                "  [{}] {} ({} ch, {} Hz){}",
+                i, device.name, device.channels, device.sample_rate, virtual_hint
             );
         }
         
@@ -399,10 +1482,17 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
+    if !quiet {
+        run_self_test(&config, &output_devices);
+    }
+
     // Find source device - now we use output devices for loopback!
-    // The source is the main speakers (output device) that we'll capture via WASAPI loopback
-    let source_device = output_devices.iter()
-        .find(|d| (d.name.contains("Speakers") || d.name.contains("Speaker")) && d.channels >= 4)
+    // The source is the main speakers (output device) that we'll capture via WASAPI loopback,
+    // or the Windows default device for the configured role
+    let role_source = config.source_role.and_then(audio::default_render_device_name);
+    let source_device = role_source.as_ref()
+        .and_then(|name| output_devices.iter().find(|d| &d.name == name))
+        .or_else(|| output_devices.iter().find(|d| (d.name.contains("Speakers") || d.name.contains("Speaker")) && d.channels >= 4))
         .or_else(|| output_devices.iter().find(|d| d.name.contains("Speakers") || d.name.contains("Speaker")))
         .or_else(|| config.source_device.as_ref().and_then(|name| 
             output_devices.iter().find(|d| d.name.contains(name))
@@ -456,16 +1546,113 @@ fn main() -> Result<()> {
     // Apply config settings
     router.set_volume(config.volume);
     router.set_swap_channels(config.swap_channels);
+    router.set_swap_scope(config.swap_scope);
     router.set_balance(config.balance);
     router.set_left_channel(&config.left_channel);
     router.set_right_channel(&config.right_channel);
+    router.set_left_trim_db(config.left_trim_db);
+    router.set_right_trim_db(config.right_trim_db);
+    router.set_missing_channel_behavior(config.missing_channel_behavior);
+    router.set_expected_source_channels(config.expected_source_channels);
+    router.set_pause_on_channel_mismatch(config.pause_on_channel_mismatch);
     // DSP settings
     router.set_delay_ms(config.delay_ms);
     router.set_eq_enabled(config.eq_enabled);
     router.set_eq(config.eq_low, config.eq_mid, config.eq_high);
+    router.set_eq_shelf_slope(config.eq_shelf_slope);
+    router.set_eq_freqs(config.eq_low_freq, config.eq_mid_freq, config.eq_high_freq);
+    router.set_ms_eq_enabled(config.ms_eq_enabled);
+    router.set_ms_eq(config.ms_eq_mid.as_array(), config.ms_eq_side.as_array());
+    router.set_emphasis_enabled(config.emphasis_enabled);
+    router.set_emphasis(config.emphasis_freq, config.emphasis_db);
+    router.set_declick(config.declick);
     router.set_upmix_enabled(config.upmix_enabled);
+    router.set_upmix_mode(config.upmix_mode);
     router.set_upmix_strength(config.upmix_strength);
+    router.set_upmix_ambience_ms(config.upmix_ambience_ms);
+    router.set_upmix_rear_delay_ms(config.upmix_rear_delay_ms);
+    router.set_normalize_upmix(config.normalize_upmix);
+    router.set_per_channel_limit(config.per_channel_limit);
+    router.set_upmix_dither(config.upmix_dither, config.upmix_dither_bits, config.upmix_dither_shaping);
+    router.set_upmix_center(config.upmix_center_enabled, config.upmix_center_level);
     router.set_sync_master_volume(config.sync_master_volume);
+    router.set_volume_slew_db_per_sec(config.volume_slew_db_per_sec);
+    router.set_source_crossfade_ms(config.source_crossfade_ms);
+    router.set_source_format_cache(config.source_format_cache.clone());
+    router.set_respect_system_mute(config.respect_system_mute);
+    router.set_panic_muted(config.panic_muted);
+    if config.panic_muted {
+        warn!("Panic mute is on from the last session, output stays silent until unmuted");
+    }
+    router.set_dry_wet(config.dry_wet);
+    router.set_compander_enabled(config.compander_enabled);
+    router.set_subsonic_filter(config.subsonic_filter);
+    router.set_subsonic_cutoff_hz(config.subsonic_cutoff_hz);
+    router.set_compander_times(config.compander_attack_ms, config.compander_release_ms);
+    router.set_agc_enabled(config.agc_enabled);
+    router.set_agc_target_db(config.agc_target_db);
+    router.set_limiter_enabled(config.limiter_enabled);
+    router.set_limiter_mode(config.limiter_mode);
+    router.set_limiter_ceiling_db(config.limiter_ceiling_db);
+    router.set_meter_weighting(config.meter_weighting);
+    router.set_meter_update_ms(config.meter_update_ms);
+    router.set_resampler_quality(config.resampler_quality);
+    router.set_match_source_rate(config.match_source_rate);
+    router.set_prefill_ms(config.prefill_ms);
+    router.set_soft_start_ms(config.soft_start_ms);
+    router.set_stop_ramp_ms(config.stop_ramp_ms);
+    router.set_output_channels(config.output_channels);
+    router.set_output_buffer_frames(config.output_buffer_frames);
+    router.set_preserve_dsp_state(config.preserve_dsp_state);
+    router.set_direct_mode(config.direct_mode);
+    router.set_keep_warm(config.keep_capture_warm);
+    router.set_keep_stream_alive(config.keep_stream_alive);
+    router.set_capturable_output(config.capturable_output);
+    if config.capturable_output && !audio::is_likely_virtual(&target_name) {
+        warn!("capturable_output is set but the target does not look like a virtual cable; other apps can only capture it through its loopback");
+    }
+    router.set_exclusive_fallback(config.exclusive_fallback);
+    router.set_source_role(config.source_role.filter(|_| role_source.is_some()));
+    router.set_capture_overrides(config.capture_overrides());
+    router.set_level_safety(config.level_safety);
+    router.set_disable_output_clamp(config.disable_output_clamp);
+    router.set_linked_clamp(config.linked_clamp);
+    router.set_input_gain_db(config.input_gain_db);
+    router.set_preset_fade_ms(config.preset_fade_ms);
+    router.set_device_change_mute_ms(config.device_change_mute_ms);
+
+    // One-shot test tone: play on the resolved devices and exit without the tray
+    if let Some((main_speakers, left)) = test_tone {
+        let player = router.clone_for_test().with_target(&target_name);
+        let result = if main_speakers {
+            player.play_test_tone_main(left, &source_name)
+        } else {
+            player.play_test_tone_sub(left)
+        };
+        if let Err(e) = result {
+            error!("Test tone failed: {}", e);
+            eprintln!("Error: Test tone failed: {}", e);
+        }
+        return Ok(());
+    }
+
+    // One-shot latency measurement
+    if measure_latency {
+        match router.clone_for_test().with_target(&target_name).measure_latency() {
+            Ok(m) => println!("Measured latency on {}: {:.1} ms ({} samples @ {} Hz)", target_name, m.ms(), m.samples, m.sample_rate),
+            Err(e) => {
+                error!("Latency measurement failed: {}", e);
+                eprintln!("Error: Latency measurement failed: {}", e);
+            }
+        }
+        return Ok(());
+    }
+
+    // One-shot signal chain analysis with the configured settings
+    if analyze_chain {
+        println!("{}", router.analyze_chain());
+        return Ok(());
+    }
 
     // Start routing if enabled (using WASAPI Loopback)
     if config.enabled {
@@ -485,52 +1672,126 @@ fn main() -> Result<()> {
         }
     }
 
-    // Set up tray icon
+    // Set up tray icon (not in console mode)
     let device_names: Vec<String> = output_devices.iter().map(|d| d.name.clone()).collect();
-    let tray_manager = tray::TrayManager::new(
-        &device_names,
-        &device_names,
-        Some(&source_name),
-        Some(&target_name),
-        config.volume,
-        config.balance,
-        config.left_channel.source,
-        config.right_channel.source,
-        config.left_channel.volume,
-        config.right_channel.volume,
-        config.left_channel.muted,
-        config.right_channel.muted,
-        config.enabled,
-        config.swap_channels,
-        config.clone_stereo,
-        is_startup_enabled(),
-        // DSP settings
-        config.delay_ms,
-        config.eq_enabled,
-        config.eq_low,
-        config.eq_mid,
-        config.eq_high,
-        config.upmix_enabled,
-        config.upmix_strength,
-        config.sync_master_volume,
-    )?;
-
-    info!("Tray icon initialized, entering main loop");
-    if !quiet {
-        println!("\nRunning in system tray. Right-click the icon for settings.");
-    }
+    let tray_manager = if console_mode {
+        None
+    } else {
+        Some(tray::TrayManager::new(
+            &device_names,
+            &device_names,
+            &config.output_sets.iter().map(|s| s.name.clone()).collect::<Vec<_>>(),
+            &tray::TraySettings {
+                current_source: Some(&source_name),
+                current_source_role: config.source_role,
+                current_target: Some(&target_name),
+                current_volume: config.volume,
+                current_balance: config.balance,
+                current_left_source: config.left_channel.source,
+                current_right_source: config.right_channel.source,
+                current_left_volume: config.left_channel.volume,
+                current_right_volume: config.right_channel.volume,
+                left_trim_db: config.left_trim_db,
+                right_trim_db: config.right_trim_db,
+                left_muted: config.left_channel.muted,
+                right_muted: config.right_channel.muted,
+                enabled: config.enabled,
+                panic_muted: config.panic_muted,
+                swap_channels: config.swap_channels,
+                clone_stereo: config.clone_stereo,
+                startup_enabled: is_startup_enabled(),
+                // DSP settings
+                input_gain_db: config.input_gain_db,
+                delay_ms: config.delay_ms,
+                eq_enabled: config.eq_enabled,
+                eq_low: config.eq_low,
+                eq_mid: config.eq_mid,
+                eq_high: config.eq_high,
+                eq_freqs: router.eq_freqs(),
+                ms_eq_enabled: config.ms_eq_enabled,
+                upmix_enabled: config.upmix_enabled,
+                upmix_strength: config.upmix_strength,
+                upmix_rear_delay_ms: config.upmix_rear_delay_ms,
+                dry_wet: config.dry_wet,
+                compander_enabled: config.compander_enabled,
+                subsonic_filter: config.subsonic_filter,
+                subsonic_cutoff_hz: config.subsonic_cutoff_hz,
+                limiter_enabled: config.limiter_enabled,
+                limiter_ceiling_db: config.limiter_ceiling_db,
+                sync_master_volume: config.sync_master_volume,
+                night_mode: config.night_mode,
+                capturable_output: config.capturable_output,
+            },
+        )?)
+    };
 
     // Create app state
+    let events = router.subscribe();
     let mut app = App {
         router,
+        events,
         config,
         source_name,
         target_name,
-        tray_manager: Some(tray_manager),
+        tray_manager,
+        night_active: false,
+        last_night_window: None,
+        capture_device: None,
+        source_format: None,
+        latency_ms: None,
+        resampler: None,
+        clip_count: 0,
+        clip_until: None,
+        diag_phase_flip: false,
+        diag_mono: false,
+        dsp_bypass: false,
+        noise_loop: None,
+        resume_restart_at: None,
+        next_process_check: Instant::now(),
+        auto_process_seen: None,
+        auto_process_state: None,
+        next_status_write: Instant::now(),
+        status_write_failed: false,
     };
 
+    if console_mode {
+        // Release builds have no console of their own: reuse the terminal we
+        // were launched from, else open a new one (a no-op when one exists)
+        unsafe {
+            use windows::Win32::System::Console::{ATTACH_PARENT_PROCESS, AllocConsole, AttachConsole};
+            if AttachConsole(ATTACH_PARENT_PROCESS).is_err() {
+                let _ = AllocConsole();
+            }
+        }
+        info!("Console mode, entering menu loop");
+        console::run(&mut app, &device_names);
+        info!("split51 stopped");
+        return Ok(());
+    }
+
+    info!("Tray icon initialized, entering main loop");
+    if !quiet {
+        println!("\nRunning in system tray. Right-click the icon for settings.");
+    }
+
     // Run winit event loop for Windows message pump
-    let event_loop = EventLoop::new()?;
+    // The message hook catches the thread-level WM_HOTKEY, which has no window to dispatch to
+    let event_loop = {
+        use windows::Win32::UI::WindowsAndMessaging::{MSG, WM_HOTKEY};
+        use winit::platform::windows::EventLoopBuilderExtWindows;
+        EventLoop::builder()
+            .with_msg_hook(|msg| {
+                let msg = unsafe { &*(msg as *const MSG) };
+                if msg.message == WM_HOTKEY && msg.wParam.0 == PANIC_HOTKEY_ID as usize {
+                    PANIC_HOTKEY_PRESSED.store(true, Ordering::Relaxed);
+                    return true;
+                }
+                false
+            })
+            .build()?
+    };
+    register_panic_hotkey();
+    create_power_window();
     event_loop.set_control_flow(ControlFlow::Wait);
     event_loop.run_app(&mut app)?;
 
@@ -2,10 +2,13 @@ use anyhow::Result;
 use muda::{Menu, MenuEvent, MenuItem, PredefinedMenuItem, Submenu, CheckMenuItem, MenuId};
 use tray_icon::{TrayIcon, TrayIconBuilder, Icon};
 use std::collections::HashMap;
-use crate::config::ChannelSource;
+use std::time::Duration;
+use crate::config::{ChannelSource, NightMode, SourceRole};
+use crate::dsp::output_channel_names;
 
 pub enum TrayCommand {
     ToggleEnabled,
+    TogglePanicMute,
     ToggleSwapChannels,
     ToggleCloneStereo,
     ToggleStartup,
@@ -15,41 +18,85 @@ pub enum TrayCommand {
     TestMainRight,    // Test FR on main speakers
     TestSubLeft,      // Test L on 2nd output (routed)
     TestSubRight,     // Test R on 2nd output (routed)
+    MeasureLatency,   // Loopback click on 2nd output
+    AnalyzeChain,     // Offline test tone through the DSP chain, logs output levels
+    ToggleContinuousNoise,  // Looping pink noise on the configured target channel
+    ToggleDiagPhaseFlip,  // Invert left output phase (not saved)
+    ToggleDiagMono,       // Collapse output to mono (not saved)
     SetLeftSource(ChannelSource),
     SetRightSource(ChannelSource),
     ToggleLeftMute,
     ToggleRightMute,
     SetLeftVolume(f32),
     SetRightVolume(f32),
+    SetLeftTrim(f32),
+    SetRightTrim(f32),
     SelectSourceDevice(String),
+    SelectSourceRole(SourceRole),
     SelectTargetDevice(String),
+    SelectOutputSet(String),  // Named source/target pairing from config
     // DSP commands
+    ToggleDspBypass,  // Skip all DSP stages for an A/B (not saved)
+    SetInputGain(f32),
     SetDelayMs(f32),
     ToggleEq,
     SetEqLow(f32),
     SetEqMid(f32),
     SetEqHigh(f32),
+    ToggleMsEq,
     ToggleUpmix,
     SetUpmixStrength(f32),
+    SetUpmixRearDelay(f32),
+    SetDryWet(f32),
+    ToggleCompander,
+    ToggleSubsonicFilter,
+    ToggleLimiter,
+    SetLimiterCeiling(f32),
+    SetSubsonicCutoff(f32),
     ToggleSyncMasterVolume,
+    SetNightMode(NightMode),
+    CopyDiagnostics,
     Quit,
 }
 
 pub struct TrayManager {
     tray_icon: TrayIcon,
     toggle_item: MenuItem,
+    panic_mute_item: CheckMenuItem,
+    capture_device_item: MenuItem,
+    /// How other apps can capture the processed output (capturable_output)
+    capturable_item: MenuItem,
+    capturable_output: bool,
+    source_format_item: MenuItem,
+    latency_item: MenuItem,
+    resampler_item: MenuItem,
+    uptime_item: MenuItem,
+    levels_item: MenuItem,
     swap_item: CheckMenuItem,
     clone_stereo_item: CheckMenuItem,
+    left_source_items: Vec<(ChannelSource, MenuItem)>,
+    right_source_items: Vec<(ChannelSource, MenuItem)>,
     startup_item: CheckMenuItem,
     left_mute_item: CheckMenuItem,
     right_mute_item: CheckMenuItem,
     eq_item: CheckMenuItem,
+    ms_eq_item: CheckMenuItem,
     sync_master_item: CheckMenuItem,
     upmix_item: CheckMenuItem,
+    compander_item: CheckMenuItem,
+    subsonic_item: CheckMenuItem,
+    limiter_item: CheckMenuItem,
+    diag_phase_flip_item: CheckMenuItem,
+    diag_mono_item: CheckMenuItem,
+    dsp_bypass_item: CheckMenuItem,
+    noise_item: CheckMenuItem,
     volume_items: HashMap<MenuId, f32>,
     balance_items: HashMap<MenuId, f32>,
     left_volume_items: HashMap<MenuId, f32>,
     right_volume_items: HashMap<MenuId, f32>,
+    left_trim_items: HashMap<MenuId, f32>,
+    right_trim_items: HashMap<MenuId, f32>,
+    input_gain_items: HashMap<MenuId, f32>,
     delay_items: HashMap<MenuId, f32>,
     eq_low_items: HashMap<MenuId, f32>,
     eq_mid_items: HashMap<MenuId, f32>,
@@ -57,23 +104,56 @@ pub struct TrayManager {
     source_device_items: HashMap<MenuId, String>,
     target_device_items: HashMap<MenuId, String>,
     source_menu_items: Vec<(MenuId, MenuItem, String)>,
+    source_role_items: Vec<(MenuId, MenuItem, SourceRole)>,
     target_menu_items: Vec<(MenuId, MenuItem, String)>,
-    // For updating checkmarks
+    output_set_items: Vec<(MenuId, MenuItem, String)>,
+    current_output_set: Option<String>,
+    // Device submenus, rebuilt by `set_devices`
+    source_submenu: Submenu,
+    target_submenu: Submenu,
+    // For updating checkmarks and titles (volumes in %, balance in % right)
+    volume_submenu: Submenu,
+    balance_submenu: Submenu,
+    left_vol_submenu: Submenu,
+    right_vol_submenu: Submenu,
+    volume_menu_items: Vec<(MenuId, MenuItem, i32)>,
+    balance_menu_items: Vec<(MenuId, MenuItem, i32)>,
+    left_volume_menu_items: Vec<(MenuId, MenuItem, i32)>,
+    right_volume_menu_items: Vec<(MenuId, MenuItem, i32)>,
+    left_trim_menu_items: Vec<(MenuId, MenuItem, i32)>,
+    right_trim_menu_items: Vec<(MenuId, MenuItem, i32)>,
+    input_gain_menu_items: Vec<(MenuId, MenuItem, i32)>,
     delay_menu_items: Vec<(MenuId, MenuItem, i32)>,
     eq_low_menu_items: Vec<(MenuId, MenuItem, i32)>,
     eq_mid_menu_items: Vec<(MenuId, MenuItem, i32)>,
     eq_high_menu_items: Vec<(MenuId, MenuItem, i32)>,
     upmix_strength_items: HashMap<MenuId, f32>,
     upmix_strength_menu_items: Vec<(MenuId, MenuItem, i32)>,
+    upmix_rear_delay_items: HashMap<MenuId, f32>,
+    upmix_rear_delay_menu_items: Vec<(MenuId, MenuItem, i32)>,
+    dry_wet_items: HashMap<MenuId, f32>,
+    dry_wet_menu_items: Vec<(MenuId, MenuItem, i32)>,
+    subsonic_cutoff_items: HashMap<MenuId, f32>,
+    subsonic_cutoff_menu_items: Vec<(MenuId, MenuItem, i32)>,
+    limiter_ceiling_items: HashMap<MenuId, f32>,
+    limiter_ceiling_menu_items: Vec<(MenuId, MenuItem, i32)>,
+    night_mode_items: Vec<(MenuId, MenuItem, NightMode)>,
     toggle_id: MenuId,
     swap_id: MenuId,
     clone_stereo_id: MenuId,
     startup_id: MenuId,
     quit_id: MenuId,
+    copy_diagnostics_id: MenuId,
     test_main_left_id: MenuId,
     test_main_right_id: MenuId,
     test_sub_left_id: MenuId,
     test_sub_right_id: MenuId,
+    measure_latency_id: MenuId,
+    analyze_chain_id: MenuId,
+    noise_id: MenuId,
+    diag_phase_flip_id: MenuId,
+    diag_mono_id: MenuId,
+    dsp_bypass_id: MenuId,
     left_fl_id: MenuId,
     left_fr_id: MenuId,
     left_rl_id: MenuId,
@@ -85,42 +165,125 @@ pub struct TrayManager {
     left_mute_id: MenuId,
     right_mute_id: MenuId,
     eq_id: MenuId,
+    ms_eq_id: MenuId,
     upmix_id: MenuId,
+    compander_id: MenuId,
+    subsonic_id: MenuId,
+    limiter_id: MenuId,
     sync_master_id: MenuId,
+    panic_mute_id: MenuId,
+    save_failed: bool,
+    /// Routing and panic mute state, both shown by the icon and tooltip
+    enabled: bool,
+    panic_muted: bool,
+    /// Output levels and clip marker shown in the tooltip while routing
+    tooltip_levels: Option<String>,
+}
+
+/// Current settings the tray menu is built with (see `TrayManager::new`)
+#[derive(Debug, Clone, Copy)]
+pub struct TraySettings<'a> {
+    pub current_source: Option<&'a str>,
+    pub current_source_role: Option<SourceRole>,
+    pub current_target: Option<&'a str>,
+    pub current_volume: f32,
+    pub current_balance: f32,
+    pub current_left_source: ChannelSource,
+    pub current_right_source: ChannelSource,
+    pub current_left_volume: f32,
+    pub current_right_volume: f32,
+    pub left_trim_db: f32,
+    pub right_trim_db: f32,
+    pub left_muted: bool,
+    pub right_muted: bool,
+    pub enabled: bool,
+    pub panic_muted: bool,
+    pub swap_channels: bool,
+    pub clone_stereo: bool,
+    pub startup_enabled: bool,
+    // DSP settings
+    pub input_gain_db: f32,
+    pub delay_ms: f32,
+    pub eq_enabled: bool,
+    pub eq_low: f32,
+    pub eq_mid: f32,
+    pub eq_high: f32,
+    pub eq_freqs: (f32, f32, f32),
+    pub ms_eq_enabled: bool,
+    pub upmix_enabled: bool,
+    pub upmix_strength: f32,
+    pub upmix_rear_delay_ms: f32,
+    pub dry_wet: f32,
+    pub compander_enabled: bool,
+    pub subsonic_filter: bool,
+    pub subsonic_cutoff_hz: f32,
+    pub limiter_enabled: bool,
+    pub limiter_ceiling_db: f32,
+    pub sync_master_volume: bool,
+    pub night_mode: NightMode,
+    pub capturable_output: bool,
 }
 
 impl TrayManager {
-    #[allow(clippy::too_many_arguments)]
     pub fn new(
         source_devices: &[String],
         target_devices: &[String],
-        current_source: Option<&str>,
-        current_target: Option<&str>,
-        current_volume: f32,
-        current_balance: f32,
-        current_left_source: ChannelSource,
-        current_right_source: ChannelSource,
-        current_left_volume: f32,
-        current_right_volume: f32,
-        left_muted: bool,
-        right_muted: bool,
-        enabled: bool,
-        swap_channels: bool,
-        clone_stereo: bool,
-        startup_enabled: bool,
-        // DSP settings
-        delay_ms: f32,
-        eq_enabled: bool,
-        eq_low: f32,
-        eq_mid: f32,
-        eq_high: f32,
-        upmix_enabled: bool,
-        upmix_strength: f32,
-        sync_master_volume: bool,
+        output_sets: &[String],
+        settings: &TraySettings,
     ) -> Result<Self> {
+        let TraySettings {
+            current_source,
+            current_source_role,
+            current_target,
+            current_volume,
+            current_balance,
+            current_left_source,
+            current_right_source,
+            current_left_volume,
+            current_right_volume,
+            left_trim_db,
+            right_trim_db,
+            left_muted,
+            right_muted,
+            enabled,
+            panic_muted,
+            swap_channels,
+            clone_stereo,
+            startup_enabled,
+            input_gain_db,
+            delay_ms,
+            eq_enabled,
+            eq_low,
+            eq_mid,
+            eq_high,
+            eq_freqs,
+            ms_eq_enabled,
+            upmix_enabled,
+            upmix_strength,
+            upmix_rear_delay_ms,
+            dry_wet,
+            compander_enabled,
+            subsonic_filter,
+            subsonic_cutoff_hz,
+            limiter_enabled,
+            limiter_ceiling_db,
+            sync_master_volume,
+            night_mode,
+            capturable_output,
+        } = *settings;
         // Create menu items
         let toggle_text = if enabled { "Disable Routing" } else { "Enable Routing" };
         let toggle_item = MenuItem::new(toggle_text, true, None);
+        let panic_mute_item = CheckMenuItem::new(PANIC_MUTE_TEXT, true, panic_muted, None);
+
+        // Endpoint actually captured and its detected format (informational, not clickable)
+        let capture_device_item = MenuItem::new("Capturing: -", false, None);
+        let capturable_item = MenuItem::new(capturable_text(capturable_output, current_target), false, None);
+        let source_format_item = MenuItem::new("Source: (not detected)", false, None);
+        let latency_item = MenuItem::new("Latency: -", false, None);
+        let resampler_item = MenuItem::new("Resampler: -", false, None);
+        let uptime_item = MenuItem::new("Uptime: -", false, None);
+        let levels_item = MenuItem::new("Levels: -", false, None);
 
         // Swap channels checkbox
         let swap_item = CheckMenuItem::new("Swap L/R Channels", true, swap_channels, None);
@@ -135,9 +298,16 @@ impl TrayManager {
         let source_submenu = Submenu::new("Source Device (Loopback)", true);
         let mut source_device_items = HashMap::new();
         let mut source_menu_items = Vec::new();
+        let mut source_role_items = Vec::new();
+        for role in [SourceRole::Media, SourceRole::Comms] {
+            let item = MenuItem::new(source_role_label(role, current_source_role == Some(role)), true, None);
+            source_role_items.push((item.id().clone(), item.clone(), role));
+            source_submenu.append(&item)?;
+        }
+        source_submenu.append(&PredefinedMenuItem::separator())?;
         for device in source_devices {
-            let is_current = current_source.map(|s| s == device).unwrap_or(false);
-            let label = if is_current { format!("[*] {}", device) } else { device.clone() };
+            let is_current = current_source_role.is_none() && current_source.map(|s| s == device).unwrap_or(false);
+            let label = device_label(device, is_current);
             let item = MenuItem::new(&label, true, None);
             source_device_items.insert(item.id().clone(), device.clone());
             source_menu_items.push((item.id().clone(), item.clone(), device.clone()));
@@ -150,53 +320,55 @@ impl TrayManager {
         let mut target_menu_items = Vec::new();
         for device in target_devices {
             let is_current = current_target.map(|t| t == device).unwrap_or(false);
-            let label = if is_current { format!("[*] {}", device) } else { device.clone() };
+            let label = device_label(device, is_current);
             let item = MenuItem::new(&label, true, None);
             target_device_items.insert(item.id().clone(), device.clone());
             target_menu_items.push((item.id().clone(), item.clone(), device.clone()));
             target_submenu.append(&item)?;
         }
 
-        // Master Volume submenu
-        let volume_submenu = Submenu::new("Master Volume", true);
+        // Saved source/target pairings, only shown when some are configured
+        let output_set_submenu = Submenu::new("Outputs", true);
+        let mut output_set_items = Vec::new();
+        for name in output_sets {
+            let item = MenuItem::new(output_set_label(name, false), true, None);
+            output_set_items.push((item.id().clone(), item.clone(), name.clone()));
+            output_set_submenu.append(&item)?;
+        }
+
+        // Master Volume submenu. Hand-edited values mark the nearest preset
+        // and show the exact value in the title.
+        let volume_submenu = Submenu::new(volume_title("Master Volume", current_volume), true);
         let mut volume_items = HashMap::new();
-        let current_vol_pct = (current_volume * 100.0).round() as i32;
-        for v in [25, 50, 75, 100, 125, 150] {
+        let mut volume_menu_items = Vec::new();
+        let current_vol_pct = nearest_preset(&VOLUME_PRESETS, percent(current_volume));
+        for v in VOLUME_PRESETS {
             let is_current = v == current_vol_pct;
             let label = if is_current { format!("[*] {}%", v) } else { format!("{}%", v) };
             let item = MenuItem::new(&label, true, None);
             volume_items.insert(item.id().clone(), v as f32 / 100.0);
+            volume_menu_items.push((item.id().clone(), item.clone(), v));
             volume_submenu.append(&item)?;
         }
 
         // Balance submenu
-        let balance_submenu = Submenu::new("Balance", true);
+        let balance_submenu = Submenu::new(balance_title(current_balance), true);
         let mut balance_items = HashMap::new();
-        let balance_values = [
-            ("Full Left", -1.0),
-            ("50% Left", -0.5),
-            ("Center", 0.0),
-            ("50% Right", 0.5),
-            ("Full Right", 1.0),
-        ];
-        for (label, value) in balance_values {
-            let is_current = (current_balance - value).abs() < 0.1;
-            let text = if is_current { format!("[*] {}", label) } else { label.to_string() };
-            let item = MenuItem::new(&text, true, None);
-            balance_items.insert(item.id().clone(), value);
+        let mut balance_menu_items = Vec::new();
+        let current_balance_pct = nearest_preset(&BALANCE_PRESETS, percent(current_balance));
+        for value in BALANCE_PRESETS {
+            let item = MenuItem::new(balance_label(value, value == current_balance_pct), true, None);
+            balance_items.insert(item.id().clone(), value as f32 / 100.0);
+            balance_menu_items.push((item.id().clone(), item.clone(), value));
             balance_submenu.append(&item)?;
         }
 
         // Left Speaker submenu
         let left_submenu = Submenu::new("Left Speaker", true);
-        let left_fl_label = if matches!(current_left_source, ChannelSource::FL) { "[*] Source: FL (Front Left)" } else { "Source: FL (Front Left)" };
-        let left_fr_label = if matches!(current_left_source, ChannelSource::FR) { "[*] Source: FR (Front Right)" } else { "Source: FR (Front Right)" };
-        let left_rl_label = if matches!(current_left_source, ChannelSource::RL) { "[*] Source: RL (Rear Left)" } else { "Source: RL (Rear Left)" };
-        let left_rr_label = if matches!(current_left_source, ChannelSource::RR) { "[*] Source: RR (Rear Right)" } else { "Source: RR (Rear Right)" };
-        let left_fl = MenuItem::new(left_fl_label, true, None);
-        let left_fr = MenuItem::new(left_fr_label, true, None);
-        let left_rl = MenuItem::new(left_rl_label, true, None);
-        let left_rr = MenuItem::new(left_rr_label, true, None);
+        let left_fl = MenuItem::new(source_label(ChannelSource::FL, current_left_source), true, None);
+        let left_fr = MenuItem::new(source_label(ChannelSource::FR, current_left_source), true, None);
+        let left_rl = MenuItem::new(source_label(ChannelSource::RL, current_left_source), true, None);
+        let left_rr = MenuItem::new(source_label(ChannelSource::RR, current_left_source), true, None);
         let left_mute = CheckMenuItem::new("Mute", true, left_muted, None);
         left_submenu.append(&left_fl)?;
         left_submenu.append(&left_fr)?;
@@ -206,28 +378,42 @@ impl TrayManager {
         left_submenu.append(&left_mute)?;
         
         // Left volume
-        let left_vol_submenu = Submenu::new("Volume", true);
+        let left_vol_submenu = Submenu::new(volume_title("Volume", current_left_volume), true);
         let mut left_volume_items = HashMap::new();
-        let current_left_vol_pct = (current_left_volume * 100.0).round() as i32;
-        for v in [25, 50, 75, 100, 125, 150] {
+        let mut left_volume_menu_items = Vec::new();
+        let current_left_vol_pct = nearest_preset(&VOLUME_PRESETS, percent(current_left_volume));
+        for v in VOLUME_PRESETS {
             let is_current = v == current_left_vol_pct;
             let label = if is_current { format!("[*] {}%", v) } else { format!("{}%", v) };
             let item = MenuItem::new(&label, true, None);
             left_volume_items.insert(item.id().clone(), v as f32 / 100.0);
+            left_volume_menu_items.push((item.id().clone(), item.clone(), v));
             left_vol_submenu.append(&item)?;
         }
         left_submenu.append(&left_vol_submenu)?;
 
+        // Left trim (stored as half-dB steps)
+        let left_trim_submenu = Submenu::new("Trim", true);
+        let mut left_trim_items = HashMap::new();
+        let mut left_trim_menu_items = Vec::new();
+        let current_left_trim = (left_trim_db * 2.0).round() as i32;
+        for half_db in -12..=12 {
+            let is_current = half_db == current_left_trim;
+            let db = half_db as f32 / 2.0;
+            let label = if is_current { format!("[*] {:+.1} dB", db) } else { format!("{:+.1} dB", db) };
+            let item = MenuItem::new(&label, true, None);
+            left_trim_items.insert(item.id().clone(), db);
+            left_trim_menu_items.push((item.id().clone(), item.clone(), half_db));
+            left_trim_submenu.append(&item)?;
+        }
+        left_submenu.append(&left_trim_submenu)?;
+
         // Right Speaker submenu
         let right_submenu = Submenu::new("Right Speaker", true);
-        let right_fl_label = if matches!(current_right_source, ChannelSource::FL) { "[*] Source: FL (Front Left)" } else { "Source: FL (Front Left)" };
-        let right_fr_label = if matches!(current_right_source, ChannelSource::FR) { "[*] Source: FR (Front Right)" } else { "Source: FR (Front Right)" };
-        let right_rl_label = if matches!(current_right_source, ChannelSource::RL) { "[*] Source: RL (Rear Left)" } else { "Source: RL (Rear Left)" };
-        let right_rr_label = if matches!(current_right_source, ChannelSource::RR) { "[*] Source: RR (Rear Right)" } else { "Source: RR (Rear Right)" };
-        let right_fl = MenuItem::new(right_fl_label, true, None);
-        let right_fr = MenuItem::new(right_fr_label, true, None);
-        let right_rl = MenuItem::new(right_rl_label, true, None);
-        let right_rr = MenuItem::new(right_rr_label, true, None);
+        let right_fl = MenuItem::new(source_label(ChannelSource::FL, current_right_source), true, None);
+        let right_fr = MenuItem::new(source_label(ChannelSource::FR, current_right_source), true, None);
+        let right_rl = MenuItem::new(source_label(ChannelSource::RL, current_right_source), true, None);
+        let right_rr = MenuItem::new(source_label(ChannelSource::RR, current_right_source), true, None);
         let right_mute = CheckMenuItem::new("Mute", true, right_muted, None);
         right_submenu.append(&right_fl)?;
         right_submenu.append(&right_fr)?;
@@ -237,18 +423,36 @@ impl TrayManager {
         right_submenu.append(&right_mute)?;
 
         // Right volume
-        let right_vol_submenu = Submenu::new("Volume", true);
+        let right_vol_submenu = Submenu::new(volume_title("Volume", current_right_volume), true);
         let mut right_volume_items = HashMap::new();
-        let current_right_vol_pct = (current_right_volume * 100.0).round() as i32;
-        for v in [25, 50, 75, 100, 125, 150] {
+        let mut right_volume_menu_items = Vec::new();
+        let current_right_vol_pct = nearest_preset(&VOLUME_PRESETS, percent(current_right_volume));
+        for v in VOLUME_PRESETS {
             let is_current = v == current_right_vol_pct;
             let label = if is_current { format!("[*] {}%", v) } else { format!("{}%", v) };
             let item = MenuItem::new(&label, true, None);
             right_volume_items.insert(item.id().clone(), v as f32 / 100.0);
+            right_volume_menu_items.push((item.id().clone(), item.clone(), v));
             right_vol_submenu.append(&item)?;
         }
         right_submenu.append(&right_vol_submenu)?;
 
+        // Right trim (stored as half-dB steps)
+        let right_trim_submenu = Submenu::new("Trim", true);
+        let mut right_trim_items = HashMap::new();
+        let mut right_trim_menu_items = Vec::new();
+        let current_right_trim = (right_trim_db * 2.0).round() as i32;
+        for half_db in -12..=12 {
+            let is_current = half_db == current_right_trim;
+            let db = half_db as f32 / 2.0;
+            let label = if is_current { format!("[*] {:+.1} dB", db) } else { format!("{:+.1} dB", db) };
+            let item = MenuItem::new(&label, true, None);
+            right_trim_items.insert(item.id().clone(), db);
+            right_trim_menu_items.push((item.id().clone(), item.clone(), half_db));
+            right_trim_submenu.append(&item)?;
+        }
+        right_submenu.append(&right_trim_submenu)?;
+
         // Speaker test submenu
         let test_submenu = Submenu::new("Speaker Test", true);
         let test_main_left = MenuItem::new("Main Left (FL)", true, None);
@@ -260,9 +464,41 @@ impl TrayManager {
         test_submenu.append(&PredefinedMenuItem::separator())?;
         test_submenu.append(&test_sub_left)?;
         test_submenu.append(&test_sub_right)?;
+        let measure_latency_item = MenuItem::new("Measure Latency", true, None);
+        test_submenu.append(&measure_latency_item)?;
+        let analyze_chain_item = MenuItem::new("Analyze Signal Chain", true, None);
+        test_submenu.append(&analyze_chain_item)?;
+        let noise_item = CheckMenuItem::new("Continuous Pink Noise", true, false, None);
+        test_submenu.append(&noise_item)?;
+        test_submenu.append(&PredefinedMenuItem::separator())?;
+        // Temporary diagnostics, always off at startup
+        let diag_phase_flip_item = CheckMenuItem::new("Flip Left Phase", true, false, None);
+        let diag_mono_item = CheckMenuItem::new("Mono Check", true, false, None);
+        test_submenu.append(&diag_phase_flip_item)?;
+        test_submenu.append(&diag_mono_item)?;
 
         // DSP submenu
         let dsp_submenu = Submenu::new("DSP Effects", true);
+
+        // Momentary A/B against the raw routing, always off at startup
+        let dsp_bypass_item = CheckMenuItem::new("Bypass DSP", true, false, None);
+        dsp_submenu.append(&dsp_bypass_item)?;
+        dsp_submenu.append(&PredefinedMenuItem::separator())?;
+
+        // Input gain submenu (gain staging before everything else)
+        let input_gain_submenu = Submenu::new("Input Gain", true);
+        let mut input_gain_items = HashMap::new();
+        let mut input_gain_menu_items = Vec::new();
+        let current_input_gain = input_gain_db.round() as i32;
+        for db in [-12, -6, 0, 6, 12, 18, 24] {
+            let is_current = db == current_input_gain;
+            let label = if is_current { format!("[*] {:+} dB", db) } else { format!("{:+} dB", db) };
+            let item = MenuItem::new(&label, true, None);
+            input_gain_items.insert(item.id().clone(), db as f32);
+            input_gain_menu_items.push((item.id().clone(), item.clone(), db));
+            input_gain_submenu.append(&item)?;
+        }
+        dsp_submenu.append(&input_gain_submenu)?;
         
         // Delay submenu
         let delay_submenu = Submenu::new("Delay", true);
@@ -284,7 +520,7 @@ impl TrayManager {
         dsp_submenu.append(&eq_item)?;
         
         // EQ Low submenu
-        let eq_low_submenu = Submenu::new("EQ Low (200Hz)", true);
+        let eq_low_submenu = Submenu::new(format!("EQ Low ({})", freq_label(eq_freqs.0)), true);
         let mut eq_low_items = HashMap::new();
         let mut eq_low_menu_items = Vec::new();
         let current_low = eq_low.round() as i32;
@@ -299,7 +535,7 @@ impl TrayManager {
         dsp_submenu.append(&eq_low_submenu)?;
         
         // EQ Mid submenu
-        let eq_mid_submenu = Submenu::new("EQ Mid (1kHz)", true);
+        let eq_mid_submenu = Submenu::new(format!("EQ Mid ({})", freq_label(eq_freqs.1)), true);
         let mut eq_mid_items = HashMap::new();
         let mut eq_mid_menu_items = Vec::new();
         let current_mid = eq_mid.round() as i32;
@@ -314,7 +550,7 @@ impl TrayManager {
         dsp_submenu.append(&eq_mid_submenu)?;
         
         // EQ High submenu
-        let eq_high_submenu = Submenu::new("EQ High (4kHz)", true);
+        let eq_high_submenu = Submenu::new(format!("EQ High ({})", freq_label(eq_freqs.2)), true);
         let mut eq_high_items = HashMap::new();
         let mut eq_high_menu_items = Vec::new();
         let current_high = eq_high.round() as i32;
@@ -327,6 +563,10 @@ impl TrayManager {
             eq_high_submenu.append(&item)?;
         }
         dsp_submenu.append(&eq_high_submenu)?;
+
+        // Mid/side EQ checkbox (gains are set in config)
+        let ms_eq_item = CheckMenuItem::new("Mid/Side EQ", true, ms_eq_enabled, None);
+        dsp_submenu.append(&ms_eq_item)?;
         
         dsp_submenu.append(&PredefinedMenuItem::separator())?;
         
@@ -348,6 +588,72 @@ impl TrayManager {
             upmix_strength_submenu.append(&item)?;
         }
         dsp_submenu.append(&upmix_strength_submenu)?;
+
+        // Upmix rear alignment delay submenu
+        let upmix_rear_delay_submenu = Submenu::new("Upmix Rear Delay", true);
+        let mut upmix_rear_delay_items = HashMap::new();
+        let mut upmix_rear_delay_menu_items = Vec::new();
+        let current_rear_delay = upmix_rear_delay_ms.round() as i32;
+        for ms in [0, 5, 10, 15, 20, 30] {
+            let is_current = ms == current_rear_delay;
+            let label = if is_current { format!("[*] {} ms", ms) } else { format!("{} ms", ms) };
+            let item = MenuItem::new(&label, true, None);
+            upmix_rear_delay_items.insert(item.id().clone(), ms as f32);
+            upmix_rear_delay_menu_items.push((item.id().clone(), item.clone(), ms));
+            upmix_rear_delay_submenu.append(&item)?;
+        }
+        dsp_submenu.append(&upmix_rear_delay_submenu)?;
+
+        dsp_submenu.append(&PredefinedMenuItem::separator())?;
+
+        // Dry/wet mix submenu
+        let dry_wet_submenu = Submenu::new("DSP Mix", true);
+        let mut dry_wet_items = HashMap::new();
+        let mut dry_wet_menu_items = Vec::new();
+        let current_mix = (dry_wet * 100.0).round() as i32;
+        for pct in [0, 25, 50, 75, 100] {
+            let item = MenuItem::new(dry_wet_label(pct, pct == current_mix), true, None);
+            dry_wet_items.insert(item.id().clone(), pct as f32 / 100.0);
+            dry_wet_menu_items.push((item.id().clone(), item.clone(), pct));
+            dry_wet_submenu.append(&item)?;
+        }
+        dsp_submenu.append(&dry_wet_submenu)?;
+
+        // Loudness leveler (compander) checkbox
+        let compander_item = CheckMenuItem::new("Leveler", true, compander_enabled, None);
+        dsp_submenu.append(&compander_item)?;
+
+        // Subsonic protection checkbox and cutoff submenu
+        let subsonic_item = CheckMenuItem::new("Subsonic Filter", true, subsonic_filter, None);
+        dsp_submenu.append(&subsonic_item)?;
+        let subsonic_cutoff_submenu = Submenu::new("Subsonic Cutoff", true);
+        let mut subsonic_cutoff_items = HashMap::new();
+        let mut subsonic_cutoff_menu_items = Vec::new();
+        let current_cutoff = subsonic_cutoff_hz.round() as i32;
+        for hz in [20, 30, 40, 60, 80] {
+            let is_current = hz == current_cutoff;
+            let label = if is_current { format!("[*] {} Hz", hz) } else { format!("{} Hz", hz) };
+            let item = MenuItem::new(&label, true, None);
+            subsonic_cutoff_items.insert(item.id().clone(), hz as f32);
+            subsonic_cutoff_menu_items.push((item.id().clone(), item.clone(), hz));
+            subsonic_cutoff_submenu.append(&item)?;
+        }
+        dsp_submenu.append(&subsonic_cutoff_submenu)?;
+
+        // Output limiter checkbox and ceiling submenu
+        let limiter_item = CheckMenuItem::new("Output Limiter", true, limiter_enabled, None);
+        dsp_submenu.append(&limiter_item)?;
+        let limiter_ceiling_submenu = Submenu::new("Limiter Ceiling", true);
+        let mut limiter_ceiling_items = HashMap::new();
+        let mut limiter_ceiling_menu_items = Vec::new();
+        let current_ceiling = limiter_ceiling_db.round() as i32;
+        for db in [0, -1, -3, -6] {
+            let item = MenuItem::new(limiter_ceiling_label(db, db == current_ceiling), true, None);
+            limiter_ceiling_items.insert(item.id().clone(), db as f32);
+            limiter_ceiling_menu_items.push((item.id().clone(), item.clone(), db));
+            limiter_ceiling_submenu.append(&item)?;
+        }
+        dsp_submenu.append(&limiter_ceiling_submenu)?;
         
         dsp_submenu.append(&PredefinedMenuItem::separator())?;
         
@@ -355,18 +661,47 @@ impl TrayManager {
         let sync_master_item = CheckMenuItem::new("Sync Master Volume", true, sync_master_volume, None);
         dsp_submenu.append(&sync_master_item)?;
 
+        dsp_submenu.append(&PredefinedMenuItem::separator())?;
+
+        // Night mode submenu
+        let night_submenu = Submenu::new("Night Mode", true);
+        let mut night_mode_items = Vec::new();
+        for mode in [NightMode::Auto, NightMode::On, NightMode::Off] {
+            let item = MenuItem::new(night_mode_label(mode, mode == night_mode), true, None);
+            night_mode_items.push((item.id().clone(), item.clone(), mode));
+            night_submenu.append(&item)?;
+        }
+        dsp_submenu.append(&night_submenu)?;
+
+        let copy_diagnostics_item = MenuItem::new("Copy Diagnostics", true, None);
         let quit_item = MenuItem::new("Quit", true, None);
 
         // Store IDs for event handling
         let toggle_id = toggle_item.id().clone();
+        let panic_mute_id = panic_mute_item.id().clone();
         let swap_id = swap_item.id().clone();
         let clone_stereo_id = clone_stereo_item.id().clone();
         let startup_id = startup_item.id().clone();
         let quit_id = quit_item.id().clone();
+        let copy_diagnostics_id = copy_diagnostics_item.id().clone();
         let test_main_left_id = test_main_left.id().clone();
         let test_main_right_id = test_main_right.id().clone();
         let test_sub_left_id = test_sub_left.id().clone();
         let test_sub_right_id = test_sub_right.id().clone();
+        let measure_latency_id = measure_latency_item.id().clone();
+        let analyze_chain_id = analyze_chain_item.id().clone();
+        let noise_id = noise_item.id().clone();
+        let diag_phase_flip_id = diag_phase_flip_item.id().clone();
+        let diag_mono_id = diag_mono_item.id().clone();
+        let dsp_bypass_id = dsp_bypass_item.id().clone();
+        let source_items = |items: [&MenuItem; 4]| {
+            [ChannelSource::FL, ChannelSource::FR, ChannelSource::RL, ChannelSource::RR]
+                .into_iter()
+                .zip(items.into_iter().cloned())
+                .collect::<Vec<_>>()
+        };
+        let left_source_items = source_items([&left_fl, &left_fr, &left_rl, &left_rr]);
+        let right_source_items = source_items([&right_fl, &right_fr, &right_rl, &right_rr]);
         let left_fl_id = left_fl.id().clone();
         let left_fr_id = left_fr.id().clone();
         let left_rl_id = left_rl.id().clone();
@@ -378,16 +713,33 @@ impl TrayManager {
         let left_mute_id = left_mute.id().clone();
         let right_mute_id = right_mute.id().clone();
         let eq_id = eq_item.id().clone();
+        let ms_eq_id = ms_eq_item.id().clone();
         let upmix_id = upmix_item.id().clone();
+        let compander_id = compander_item.id().clone();
+        let subsonic_id = subsonic_item.id().clone();
+        let limiter_id = limiter_item.id().clone();
         let sync_master_id = sync_master_item.id().clone();
 
         // Build menu
         let menu = Menu::new();
         menu.append(&toggle_item)?;
+        menu.append(&panic_mute_item)?;
+        menu.append(&capture_device_item)?;
+        if capturable_output {
+            menu.append(&capturable_item)?;
+        }
+        menu.append(&source_format_item)?;
+        menu.append(&latency_item)?;
+        menu.append(&resampler_item)?;
+        menu.append(&uptime_item)?;
+        menu.append(&levels_item)?;
         menu.append(&swap_item)?;
         menu.append(&clone_stereo_item)?;
         menu.append(&startup_item)?;
         menu.append(&PredefinedMenuItem::separator())?;
+        if !output_sets.is_empty() {
+            menu.append(&output_set_submenu)?;
+        }
         menu.append(&source_submenu)?;
         menu.append(&target_submenu)?;
         menu.append(&PredefinedMenuItem::separator())?;
@@ -401,10 +753,11 @@ impl TrayManager {
         menu.append(&PredefinedMenuItem::separator())?;
         menu.append(&test_submenu)?;
         menu.append(&PredefinedMenuItem::separator())?;
+        menu.append(&copy_diagnostics_item)?;
         menu.append(&quit_item)?;
 
         // Create tray icon
-        let icon = create_default_icon()?;
+        let icon = if panic_muted { create_panic_icon(tray_icon_size())? } else { create_default_icon()? };
         let tray_icon = TrayIconBuilder::new()
             .with_tooltip("split51 - 5.1ch Audio Splitter")
             .with_icon(icon)
@@ -414,8 +767,19 @@ impl TrayManager {
         Ok(Self {
             tray_icon,
             toggle_item,
+            panic_mute_item,
+            capture_device_item,
+            capturable_item,
+            capturable_output,
+            source_format_item,
+            latency_item,
+            resampler_item,
+            uptime_item,
+            levels_item,
             swap_item,
             clone_stereo_item,
+            left_source_items,
+            right_source_items,
             startup_item,
             left_mute_item: left_mute,
             right_mute_item: right_mute,
@@ -423,19 +787,43 @@ impl TrayManager {
             balance_items,
             left_volume_items,
             right_volume_items,
+            volume_submenu,
+            balance_submenu,
+            left_vol_submenu,
+            right_vol_submenu,
+            volume_menu_items,
+            balance_menu_items,
+            left_volume_menu_items,
+            right_volume_menu_items,
+            left_trim_items,
+            right_trim_items,
+            left_trim_menu_items,
+            right_trim_menu_items,
             source_device_items,
             target_device_items,
             source_menu_items,
+            source_submenu,
+            target_submenu,
+            source_role_items,
             target_menu_items,
+            output_set_items,
+            current_output_set: None,
             toggle_id,
             swap_id,
             clone_stereo_id,
             startup_id,
             quit_id,
+            copy_diagnostics_id,
             test_main_left_id,
             test_main_right_id,
             test_sub_left_id,
             test_sub_right_id,
+            measure_latency_id,
+            analyze_chain_id,
+            noise_id,
+            diag_phase_flip_id,
+            diag_mono_id,
+            dsp_bypass_id,
             left_fl_id,
             left_fr_id,
             left_rl_id,
@@ -447,24 +835,86 @@ impl TrayManager {
             left_mute_id,
             right_mute_id,
             eq_item,
+            ms_eq_item,
             upmix_item,
+            compander_item,
+            subsonic_item,
+            limiter_item,
+            diag_phase_flip_item,
+            diag_mono_item,
+            dsp_bypass_item,
+            noise_item,
+            input_gain_items,
             delay_items,
             eq_low_items,
             eq_mid_items,
             eq_high_items,
+            input_gain_menu_items,
             delay_menu_items,
             eq_low_menu_items,
             eq_mid_menu_items,
             eq_high_menu_items,
             upmix_strength_items,
             upmix_strength_menu_items,
+            upmix_rear_delay_items,
+            upmix_rear_delay_menu_items,
+            dry_wet_items,
+            dry_wet_menu_items,
+            subsonic_cutoff_items,
+            subsonic_cutoff_menu_items,
+            limiter_ceiling_items,
+            limiter_ceiling_menu_items,
+            night_mode_items,
             eq_id,
+            ms_eq_id,
             upmix_id,
+            compander_id,
+            subsonic_id,
+            limiter_id,
             sync_master_item,
             sync_master_id,
+            panic_mute_id,
+            save_failed: false,
+            enabled,
+            panic_muted,
+            tooltip_levels: None,
         })
     }
 
+    /// Update master volume checkmark and title
+    pub fn set_volume(&mut self, volume: f32) {
+        update_volume_menu(&self.volume_submenu, &self.volume_menu_items, "Master Volume", volume);
+    }
+
+    /// Update left speaker volume checkmark and title
+    pub fn set_left_volume(&mut self, volume: f32) {
+        update_volume_menu(&self.left_vol_submenu, &self.left_volume_menu_items, "Volume", volume);
+    }
+
+    /// Update right speaker volume checkmark and title
+    pub fn set_right_volume(&mut self, volume: f32) {
+        update_volume_menu(&self.right_vol_submenu, &self.right_volume_menu_items, "Volume", volume);
+    }
+
+    /// Update balance checkmark and title
+    pub fn set_balance(&mut self, balance: f32) {
+        self.balance_submenu.set_text(balance_title(balance));
+        let current = nearest_preset(&BALANCE_PRESETS, percent(balance));
+        for (_, item, value) in &self.balance_menu_items {
+            item.set_text(balance_label(*value, *value == current));
+        }
+    }
+
+    /// Update input gain checkmarks
+    pub fn set_input_gain(&mut self, db: f32) {
+        let current = db.round() as i32;
+        for (_, item, value) in &self.input_gain_menu_items {
+            let is_current = *value == current;
+            let label = if is_current { format!("[*] {:+} dB", value) } else { format!("{:+} dB", value) };
+            item.set_text(&label);
+        }
+    }
+
     /// Update delay menu checkmarks
     pub fn set_delay_ms(&mut self, ms: f32) {
         let current = ms.round() as i32;
@@ -475,6 +925,28 @@ impl TrayManager {
         }
     }
 
+    /// Update left trim checkmarks
+    pub fn set_left_trim(&mut self, db: f32) {
+        let current = (db * 2.0).round() as i32;
+        for (_, item, value) in &self.left_trim_menu_items {
+            let is_current = *value == current;
+            let db = *value as f32 / 2.0;
+            let label = if is_current { format!("[*] {:+.1} dB", db) } else { format!("{:+.1} dB", db) };
+            item.set_text(&label);
+        }
+    }
+
+    /// Update right trim checkmarks
+    pub fn set_right_trim(&mut self, db: f32) {
+        let current = (db * 2.0).round() as i32;
+        for (_, item, value) in &self.right_trim_menu_items {
+            let is_current = *value == current;
+            let db = *value as f32 / 2.0;
+            let label = if is_current { format!("[*] {:+.1} dB", db) } else { format!("{:+.1} dB", db) };
+            item.set_text(&label);
+        }
+    }
+
     /// Update Upmix strength checkmarks
     pub fn set_upmix_strength(&mut self, strength: f32) {
         let current = (strength * 10.0).round() as i32;
@@ -485,6 +957,31 @@ impl TrayManager {
         }
     }
 
+    /// Update upmix rear delay checkmarks
+    pub fn set_upmix_rear_delay(&mut self, ms: f32) {
+        let current = ms.round() as i32;
+        for (_, item, value) in &self.upmix_rear_delay_menu_items {
+            let is_current = *value == current;
+            let label = if is_current { format!("[*] {} ms", value) } else { format!("{} ms", value) };
+            item.set_text(&label);
+        }
+    }
+
+    /// Update DSP mix checkmarks
+    pub fn set_dry_wet(&mut self, mix: f32) {
+        let current = (mix * 100.0).round() as i32;
+        for (_, item, value) in &self.dry_wet_menu_items {
+            item.set_text(dry_wet_label(*value, *value == current));
+        }
+    }
+
+    /// Update night mode checkmarks
+    pub fn set_night_mode(&mut self, mode: NightMode) {
+        for (_, item, value) in &self.night_mode_items {
+            item.set_text(night_mode_label(*value, *value == mode));
+        }
+    }
+
     pub fn set_sync_master_volume(&mut self, enabled: bool) {
         self.sync_master_item.set_checked(enabled);
     }
@@ -521,22 +1018,131 @@ impl TrayManager {
 
     /// Update tray icon and tooltip based on enabled state
     pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
         let text = if enabled { "Disable Routing" } else { "Enable Routing" };
         self.toggle_item.set_text(text);
+        self.update_tooltip();
         
-        let tooltip = if enabled {
-            "split51 - Routing Active"
+        // Change icon color based on state
+        let size = tray_icon_size();
+        let icon = if self.panic_muted {
+            create_panic_icon(size)
+        } else if enabled {
+            create_enabled_icon(size)
         } else {
-            "split51 - Routing Disabled"
+            create_disabled_icon(size)
         };
-        self.tray_icon.set_tooltip(Some(tooltip)).ok();
-        
-        // Change icon color based on state
-        if let Ok(icon) = if enabled { create_enabled_icon() } else { create_disabled_icon() } {
+        if let Ok(icon) = icon {
             self.tray_icon.set_icon(Some(icon)).ok();
         }
     }
 
+    /// Routing state, with the output levels (and a clip marker) while active
+    fn update_tooltip(&self) {
+        let tooltip = if self.panic_muted {
+            "split51 - PANIC MUTED".to_string()
+        } else if self.enabled {
+            match self.tooltip_levels {
+                Some(ref levels) => format!("split51 · {}", levels),
+                None => "split51 - Routing Active".to_string(),
+            }
+        } else {
+            "split51 - Routing Disabled".to_string()
+        };
+        if self.save_failed {
+            self.tray_icon.set_tooltip(Some(format!("{}\n{}", tooltip, SAVE_FAILED_TEXT))).ok();
+        } else {
+            self.tray_icon.set_tooltip(Some(tooltip)).ok();
+        }
+    }
+
+    /// Update the panic mute checkmark; the icon turns red while muted
+    pub fn set_panic_muted(&mut self, muted: bool) {
+        self.panic_muted = muted;
+        self.panic_mute_item.set_checked(muted);
+        self.set_enabled(self.enabled);
+    }
+
+    /// Show a persistent warning in the tooltip after a config save fails
+    pub fn notify_save_failed(&mut self) {
+        if self.save_failed {
+            return;
+        }
+        self.save_failed = true;
+        self.update_tooltip();
+    }
+
+    /// Update detected source format display
+    pub fn set_source_format(&mut self, format: Option<&str>) {
+        let text = match format {
+            Some(f) => format!("Source: {}", f),
+            None => "Source: (not detected)".to_string(),
+        };
+        self.source_format_item.set_text(&text);
+    }
+
+    /// Show the endpoint the capture thread actually opened
+    pub fn set_capture_device(&mut self, name: Option<&str>) {
+        self.capture_device_item.set_text(format!("Capturing: {}", name.unwrap_or("-")));
+    }
+
+    /// Update estimated latency display
+    pub fn set_latency(&mut self, latency_ms: Option<f32>) {
+        let text = match latency_ms {
+            Some(ms) => format!("Latency: ~{} ms", ms.round() as i32),
+            None => "Latency: -".to_string(),
+        };
+        self.latency_item.set_text(&text);
+    }
+
+    /// Update the resampler display (rates, ratio and measured source clock)
+    pub fn set_resampler(&mut self, status: Option<&str>) {
+        self.resampler_item.set_text(format!("Resampler: {}", status.unwrap_or("-")));
+    }
+
+    /// Update routing uptime and dropout count display
+    pub fn set_uptime(&mut self, status: Option<(Duration, u64)>) {
+        let text = match status {
+            Some((uptime, dropouts)) => {
+                let minutes = uptime.as_secs() / 60;
+                let plural = if dropouts == 1 { "" } else { "s" };
+                if minutes >= 60 {
+                    format!("Uptime: {}h{:02}m, {} dropout{}", minutes / 60, minutes % 60, dropouts, plural)
+                } else {
+                    format!("Uptime: {}m, {} dropout{}", minutes, dropouts, plural)
+                }
+            }
+            None => "Uptime: -".to_string(),
+        };
+        self.uptime_item.set_text(&text);
+    }
+
+    /// Update the output level display, one reading per output channel, and
+    /// the tooltip; `clipping` adds a CLIP marker to the tooltip
+    pub fn set_levels(&mut self, levels_db: Option<&[f32]>, clipping: bool) {
+        let readings: Vec<String> = match levels_db {
+            Some(levels) => {
+                let names = output_channel_names(levels.len());
+                levels.iter().enumerate().map(|(i, db)| {
+                    let name = names.get(i).map_or_else(|| format!("{}", i + 1), |n| n.to_string());
+                    format!("{} {:.0}", name, db)
+                }).collect()
+            }
+            None => Vec::new(),
+        };
+        let text = if readings.is_empty() { "Levels: -".to_string() } else { format!("Levels: {} dB", readings.join(", ")) };
+        self.levels_item.set_text(&text);
+
+        let tooltip_levels = (!readings.is_empty()).then(|| {
+            let levels = readings.join(" ");
+            if clipping { format!("{} · CLIP", levels) } else { levels }
+        });
+        if tooltip_levels != self.tooltip_levels {
+            self.tooltip_levels = tooltip_levels;
+            self.update_tooltip();
+        }
+    }
+
     /// Update startup checkbox
     pub fn set_startup(&mut self, enabled: bool) {
         self.startup_item.set_checked(enabled);
@@ -552,16 +1158,83 @@ impl TrayManager {
         self.clone_stereo_item.set_checked(enabled);
     }
 
+    /// Update the source checkmarks in the Left/Right Speaker submenus
+    pub fn set_channel_sources(&mut self, left: ChannelSource, right: ChannelSource) {
+        for (items, current) in [(&self.left_source_items, left), (&self.right_source_items, right)] {
+            for (source, item) in items {
+                item.set_text(source_label(*source, current));
+            }
+        }
+    }
+
     /// Update EQ checkbox
     pub fn set_eq_enabled(&mut self, enabled: bool) {
         self.eq_item.set_checked(enabled);
     }
 
+    /// Update mid/side EQ checkbox
+    pub fn set_ms_eq_enabled(&mut self, enabled: bool) {
+        self.ms_eq_item.set_checked(enabled);
+    }
+
+    /// Update diagnostic phase flip checkbox
+    pub fn set_diag_phase_flip(&mut self, enabled: bool) {
+        self.diag_phase_flip_item.set_checked(enabled);
+    }
+
+    /// Update diagnostic mono checkbox
+    pub fn set_diag_mono(&mut self, enabled: bool) {
+        self.diag_mono_item.set_checked(enabled);
+    }
+
+    /// Update DSP bypass checkbox
+    pub fn set_dsp_bypass(&mut self, enabled: bool) {
+        self.dsp_bypass_item.set_checked(enabled);
+    }
+
+    /// Update continuous noise checkbox
+    pub fn set_noise_running(&mut self, running: bool) {
+        self.noise_item.set_checked(running);
+    }
+
     /// Update upmix checkbox
     pub fn set_upmix_enabled(&mut self, enabled: bool) {
         self.upmix_item.set_checked(enabled);
     }
 
+    /// Update leveler checkbox
+    pub fn set_compander_enabled(&mut self, enabled: bool) {
+        self.compander_item.set_checked(enabled);
+    }
+
+    /// Update subsonic filter checkbox
+    pub fn set_subsonic_filter(&mut self, enabled: bool) {
+        self.subsonic_item.set_checked(enabled);
+    }
+
+    /// Update subsonic cutoff checkmarks
+    pub fn set_subsonic_cutoff(&mut self, hz: f32) {
+        let current = hz.round() as i32;
+        for (_, item, value) in &self.subsonic_cutoff_menu_items {
+            let is_current = *value == current;
+            let label = if is_current { format!("[*] {} Hz", value) } else { format!("{} Hz", value) };
+            item.set_text(&label);
+        }
+    }
+
+    /// Update output limiter checkbox
+    pub fn set_limiter_enabled(&mut self, enabled: bool) {
+        self.limiter_item.set_checked(enabled);
+    }
+
+    /// Update limiter ceiling checkmarks
+    pub fn set_limiter_ceiling(&mut self, db: f32) {
+        let current = db.round() as i32;
+        for (_, item, value) in &self.limiter_ceiling_menu_items {
+            item.set_text(limiter_ceiling_label(*value, *value == current));
+        }
+    }
+
     /// Update mute checkboxes
     pub fn set_left_mute(&mut self, muted: bool) {
         self.left_mute_item.set_checked(muted);
@@ -575,23 +1248,68 @@ impl TrayManager {
     pub fn set_current_source(&mut self, device: Option<&str>) {
         for (_, item, name) in &self.source_menu_items {
             let is_current = device.map(|d| d == name).unwrap_or(false);
-            let label = if is_current { format!("[*] {}", name) } else { name.clone() };
+            let label = device_label(name, is_current);
             item.set_text(&label);
         }
     }
 
+    /// Replace the device entries after devices re-enumerated (e.g. on resume)
+    pub fn set_devices(&mut self, devices: &[String], current_source: Option<&str>, current_target: Option<&str>) -> Result<()> {
+        for (_, item, _) in self.source_menu_items.drain(..) {
+            self.source_submenu.remove(&item)?;
+        }
+        for (_, item, _) in self.target_menu_items.drain(..) {
+            self.target_submenu.remove(&item)?;
+        }
+        self.source_device_items.clear();
+        self.target_device_items.clear();
+        for device in devices {
+            let item = MenuItem::new(device_label(device, current_source == Some(device.as_str())), true, None);
+            self.source_device_items.insert(item.id().clone(), device.clone());
+            self.source_menu_items.push((item.id().clone(), item.clone(), device.clone()));
+            self.source_submenu.append(&item)?;
+
+            let item = MenuItem::new(device_label(device, current_target == Some(device.as_str())), true, None);
+            self.target_device_items.insert(item.id().clone(), device.clone());
+            self.target_menu_items.push((item.id().clone(), item.clone(), device.clone()));
+            self.target_submenu.append(&item)?;
+        }
+        Ok(())
+    }
+
+    /// Update checkmarks on the "System Default" source entries
+    pub fn set_source_role(&mut self, role: Option<SourceRole>) {
+        for (_, item, r) in &self.source_role_items {
+            item.set_text(source_role_label(*r, role == Some(*r)));
+        }
+    }
+
     /// Update target device menu checkmarks
     pub fn set_current_target(&mut self, device: Option<&str>) {
         for (_, item, name) in &self.target_menu_items {
             let is_current = device.map(|d| d == name).unwrap_or(false);
-            let label = if is_current { format!("[*] {}", name) } else { name.clone() };
+            let label = device_label(name, is_current);
             item.set_text(&label);
         }
+        self.capturable_item.set_text(capturable_text(self.capturable_output, device));
+    }
+
+    /// Mark the active output set (no-op when unchanged)
+    pub fn set_current_output_set(&mut self, name: Option<&str>) {
+        if self.current_output_set.as_deref() == name {
+            return;
+        }
+        for (_, item, set_name) in &self.output_set_items {
+            item.set_text(output_set_label(set_name, Some(set_name.as_str()) == name));
+        }
+        self.current_output_set = name.map(str::to_string);
     }
 
     pub fn handle_menu_event(&self, event: &MenuEvent) -> Option<TrayCommand> {
         if event.id == self.toggle_id {
             Some(TrayCommand::ToggleEnabled)
+        } else if event.id == self.panic_mute_id {
+            Some(TrayCommand::TogglePanicMute)
         } else if event.id == self.swap_id {
             Some(TrayCommand::ToggleSwapChannels)
         } else if event.id == self.clone_stereo_id {
@@ -600,6 +1318,8 @@ impl TrayManager {
             Some(TrayCommand::ToggleStartup)
         } else if event.id == self.quit_id {
             Some(TrayCommand::Quit)
+        } else if event.id == self.copy_diagnostics_id {
+            Some(TrayCommand::CopyDiagnostics)
         } else if event.id == self.test_main_left_id {
             Some(TrayCommand::TestMainLeft)
         } else if event.id == self.test_main_right_id {
@@ -608,6 +1328,18 @@ impl TrayManager {
             Some(TrayCommand::TestSubLeft)
         } else if event.id == self.test_sub_right_id {
             Some(TrayCommand::TestSubRight)
+        } else if event.id == self.measure_latency_id {
+            Some(TrayCommand::MeasureLatency)
+        } else if event.id == self.analyze_chain_id {
+            Some(TrayCommand::AnalyzeChain)
+        } else if event.id == self.noise_id {
+            Some(TrayCommand::ToggleContinuousNoise)
+        } else if event.id == self.diag_phase_flip_id {
+            Some(TrayCommand::ToggleDiagPhaseFlip)
+        } else if event.id == self.diag_mono_id {
+            Some(TrayCommand::ToggleDiagMono)
+        } else if event.id == self.dsp_bypass_id {
+            Some(TrayCommand::ToggleDspBypass)
         } else if event.id == self.left_fl_id {
             Some(TrayCommand::SetLeftSource(ChannelSource::FL))
         } else if event.id == self.left_fr_id {
@@ -630,8 +1362,16 @@ impl TrayManager {
             Some(TrayCommand::ToggleRightMute)
         } else if event.id == self.eq_id {
             Some(TrayCommand::ToggleEq)
+        } else if event.id == self.ms_eq_id {
+            Some(TrayCommand::ToggleMsEq)
         } else if event.id == self.upmix_id {
             Some(TrayCommand::ToggleUpmix)
+        } else if event.id == self.compander_id {
+            Some(TrayCommand::ToggleCompander)
+        } else if event.id == self.subsonic_id {
+            Some(TrayCommand::ToggleSubsonicFilter)
+        } else if event.id == self.limiter_id {
+            Some(TrayCommand::ToggleLimiter)
         } else if event.id == self.sync_master_id {
             Some(TrayCommand::ToggleSyncMasterVolume)
         } else if let Some(&vol) = self.volume_items.get(&event.id) {
@@ -642,6 +1382,12 @@ impl TrayManager {
             Some(TrayCommand::SetLeftVolume(vol))
         } else if let Some(&vol) = self.right_volume_items.get(&event.id) {
             Some(TrayCommand::SetRightVolume(vol))
+        } else if let Some(&db) = self.left_trim_items.get(&event.id) {
+            Some(TrayCommand::SetLeftTrim(db))
+        } else if let Some(&db) = self.right_trim_items.get(&event.id) {
+            Some(TrayCommand::SetRightTrim(db))
+        } else if let Some(&db) = self.input_gain_items.get(&event.id) {
+            Some(TrayCommand::SetInputGain(db))
         } else if let Some(&delay) = self.delay_items.get(&event.id) {
             Some(TrayCommand::SetDelayMs(delay))
         } else if let Some(&db) = self.eq_low_items.get(&event.id) {
@@ -652,64 +1398,267 @@ impl TrayManager {
             Some(TrayCommand::SetEqHigh(db))
         } else if let Some(&strength) = self.upmix_strength_items.get(&event.id) {
             Some(TrayCommand::SetUpmixStrength(strength))
+        } else if let Some(&ms) = self.upmix_rear_delay_items.get(&event.id) {
+            Some(TrayCommand::SetUpmixRearDelay(ms))
+        } else if let Some(&mix) = self.dry_wet_items.get(&event.id) {
+            Some(TrayCommand::SetDryWet(mix))
+        } else if let Some(&hz) = self.subsonic_cutoff_items.get(&event.id) {
+            Some(TrayCommand::SetSubsonicCutoff(hz))
+        } else if let Some(&db) = self.limiter_ceiling_items.get(&event.id) {
+            Some(TrayCommand::SetLimiterCeiling(db))
+        } else if let Some((_, _, mode)) = self.night_mode_items.iter().find(|(id, _, _)| *id == event.id) {
+            Some(TrayCommand::SetNightMode(*mode))
         } else if let Some(device) = self.source_device_items.get(&event.id) {
             Some(TrayCommand::SelectSourceDevice(device.clone()))
+        } else if let Some((_, _, role)) = self.source_role_items.iter().find(|(id, _, _)| *id == event.id) {
+            Some(TrayCommand::SelectSourceRole(*role))
         } else if let Some(device) = self.target_device_items.get(&event.id) {
             Some(TrayCommand::SelectTargetDevice(device.clone()))
+        } else if let Some((_, _, name)) = self.output_set_items.iter().find(|(id, _, _)| *id == event.id) {
+            Some(TrayCommand::SelectOutputSet(name.clone()))
         } else {
             None
         }
     }
 }
 
-fn create_default_icon() -> Result<Icon> {
-    create_enabled_icon()
+fn night_mode_label(mode: NightMode, is_current: bool) -> String {
+    let name = match mode {
+        NightMode::Auto => "Auto (Schedule)",
+        NightMode::On => "On",
+        NightMode::Off => "Off",
+    };
+    if is_current { format!("[*] {}", name) } else { name.to_string() }
 }
 
-fn create_enabled_icon() -> Result<Icon> {
-    // Create a simple 16x16 RGBA icon (green - active)
-    let size = 16;
-    let mut rgba = vec![0u8; size * size * 4];
-    for y in 0..size {
-        for x in 0..size {
-            let idx = (y * size + x) * 4;
-            // Create a simple speaker-like pattern
-            let in_speaker = (x >= 2 && x <= 6 && y >= 4 && y <= 11) ||
-                            (x >= 6 && x <= 10 && y >= 2 && y <= 13) ||
-                            (x >= 10 && x <= 13 && (y == 4 || y == 7 || y == 10));
-            if in_speaker {
-                rgba[idx] = 50;      // R
-                rgba[idx + 1] = 200; // G (brighter green for enabled)
-                rgba[idx + 2] = 80;  // B
-                rgba[idx + 3] = 255; // A
-            } else {
-                rgba[idx + 3] = 0; // Transparent
-            }
+/// Device menu label, flagging likely-virtual devices (e.g. for chaining into OBS)
+fn limiter_ceiling_label(db: i32, is_current: bool) -> String {
+    if is_current { format!("[*] {} dBFS", db) } else { format!("{} dBFS", db) }
+}
+
+fn output_set_label(name: &str, is_current: bool) -> String {
+    if is_current { format!("[*] {}", name) } else { name.to_string() }
+}
+
+fn device_label(name: &str, is_current: bool) -> String {
+    let name = if crate::audio::is_likely_virtual(name) { format!("{} [virtual]", name) } else { name.to_string() };
+    if is_current { format!("[*] {}", name) } else { name }
+}
+
+fn source_role_label(role: SourceRole, is_current: bool) -> String {
+    let name = match role {
+        SourceRole::Media => "System Default (Media)",
+        SourceRole::Comms => "System Default (Comms)",
+    };
+    if is_current { format!("[*] {}", name) } else { name.to_string() }
+}
+
+const VOLUME_PRESETS: [i32; 6] = [25, 50, 75, 100, 125, 150];
+/// Balance presets in percent (negative = left)
+const BALANCE_PRESETS: [i32; 5] = [-100, -50, 0, 50, 100];
+
+fn percent(value: f32) -> i32 {
+    (value * 100.0).round() as i32
+}
+
+/// Preset closest to `value` (ties go to the lower preset)
+fn nearest_preset(presets: &[i32], value: i32) -> i32 {
+    presets.iter().copied().min_by_key(|p| (p - value).abs()).unwrap_or(value)
+}
+
+/// Submenu title, with the exact value appended when it isn't a preset
+fn volume_title(name: &str, volume: f32) -> String {
+    let pct = percent(volume);
+    if VOLUME_PRESETS.contains(&pct) { name.to_string() } else { format!("{} ({}%)", name, pct) }
+}
+
+fn update_volume_menu(submenu: &Submenu, items: &[(MenuId, MenuItem, i32)], name: &str, volume: f32) {
+    submenu.set_text(volume_title(name, volume));
+    let current = nearest_preset(&VOLUME_PRESETS, percent(volume));
+    for (_, item, value) in items {
+        let label = if *value == current { format!("[*] {}%", value) } else { format!("{}%", value) };
+        item.set_text(&label);
+    }
+}
+
+fn balance_name(pct: i32) -> String {
+    match pct {
+        0 => "Center".to_string(),
+        -100 => "Full Left".to_string(),
+        100 => "Full Right".to_string(),
+        p if p < 0 => format!("{}% Left", -p),
+        p => format!("{}% Right", p),
+    }
+}
+
+/// Note on where other apps can pick up the processed output: a virtual
+/// cable's render side is capturable through its paired recording endpoint
+/// or its loopback
+fn capturable_text(capturable_output: bool, target: Option<&str>) -> String {
+    match target {
+        _ if !capturable_output => "Capturable: off".to_string(),
+        Some(target) if crate::audio::is_likely_virtual(target) => {
+            format!("Capturable: record the cable paired with {}", target)
         }
+        _ => "Capturable: target is not a virtual cable".to_string(),
     }
-    Icon::from_rgba(rgba, size as u32, size as u32).map_err(|e| anyhow::anyhow!("Icon error: {}", e))
 }
 
-fn create_disabled_icon() -> Result<Icon> {
-    // Create a simple 16x16 RGBA icon (gray - disabled)
-    let size = 16;
-    let mut rgba = vec![0u8; size * size * 4];
-    for y in 0..size {
-        for x in 0..size {
-            let idx = (y * size + x) * 4;
-            // Create a simple speaker-like pattern
-            let in_speaker = (x >= 2 && x <= 6 && y >= 4 && y <= 11) ||
-                            (x >= 6 && x <= 10 && y >= 2 && y <= 13) ||
-                            (x >= 10 && x <= 13 && (y == 4 || y == 7 || y == 10));
+/// Label of a speaker source item, marked when it is the current source
+fn source_label(source: ChannelSource, current: ChannelSource) -> String {
+    let name = match source {
+        ChannelSource::FL => "FL (Front Left)".to_string(),
+        ChannelSource::FR => "FR (Front Right)".to_string(),
+        ChannelSource::RL => "RL (Rear Left)".to_string(),
+        ChannelSource::RR => "RR (Rear Right)".to_string(),
+        ChannelSource::Index(i) => format!("Channel {}", i),
+    };
+    if source == current { format!("[*] Source: {}", name) } else { format!("Source: {}", name) }
+}
+
+fn balance_label(pct: i32, is_current: bool) -> String {
+    let name = balance_name(pct);
+    if is_current { format!("[*] {}", name) } else { name }
+}
+
+fn balance_title(balance: f32) -> String {
+    let pct = percent(balance);
+    if BALANCE_PRESETS.contains(&pct) { "Balance".to_string() } else { format!("Balance ({})", balance_name(pct)) }
+}
+
+fn dry_wet_label(pct: i32, is_current: bool) -> String {
+    let name = match pct {
+        0 => "Dry (Bypass)".to_string(),
+        100 => "Wet (100%)".to_string(),
+        _ => format!("{}% Wet", pct),
+    };
+    if is_current { format!("[*] {}", name) } else { name }
+}
+
+/// EQ band frequency for a submenu title, e.g. "150Hz" or "2.5kHz"
+fn freq_label(hz: f32) -> String {
+    if hz >= 1000.0 { format!("{}kHz", (hz / 100.0).round() / 10.0) } else { format!("{}Hz", hz.round()) }
+}
+
+fn create_default_icon() -> Result<Icon> {
+    create_enabled_icon(tray_icon_size())
+}
+
+/// Recommended small-icon size for the system DPI (16 at 100%, 32 at 200%).
+/// Never below 32 so the glyph stays sharp when the shell scales it up.
+fn tray_icon_size() -> u32 {
+    use windows::Win32::UI::HiDpi::{GetDpiForSystem, GetSystemMetricsForDpi};
+    use windows::Win32::UI::WindowsAndMessaging::SM_CXSMICON;
+    let size = unsafe { GetSystemMetricsForDpi(SM_CXSMICON, GetDpiForSystem()) };
+    (size.max(0) as u32).clamp(32, 256)
+}
+
+fn create_enabled_icon(size: u32) -> Result<Icon> {
+    // Green speaker - active
+    create_speaker_icon(size, [50, 200, 80])
+}
+
+fn create_disabled_icon(size: u32) -> Result<Icon> {
+    // Gray speaker - disabled
+    create_speaker_icon(size, [120, 120, 120])
+}
+
+fn create_panic_icon(size: u32) -> Result<Icon> {
+    // Red speaker - panic muted
+    create_speaker_icon(size, [220, 40, 40])
+}
+
+/// Render the speaker glyph at `size` x `size`. The pattern is laid out on a
+/// 16-unit grid and sampled at pixel centres, so it scales without blurring.
+fn create_speaker_icon(size: u32, rgb: [u8; 3]) -> Result<Icon> {
+    let px = size as usize;
+    let scale = 16.0 / size as f32;
+    let mut rgba = vec![0u8; px * px * 4];
+    for y in 0..px {
+        for x in 0..px {
+            let idx = (y * px + x) * 4;
+            let u = (x as f32 + 0.5) * scale;
+            let v = (y as f32 + 0.5) * scale;
+            // Simple speaker-like pattern: box, cone, sound lines
+            let in_speaker = ((2.0..7.0).contains(&u) && (4.0..12.0).contains(&v)) ||
+                            ((6.0..11.0).contains(&u) && (2.0..14.0).contains(&v)) ||
+                            ((10.0..14.0).contains(&u) &&
+                                ((4.0..5.0).contains(&v) || (7.0..8.0).contains(&v) || (10.0..11.0).contains(&v)));
             if in_speaker {
-                rgba[idx] = 120;     // R (gray)
-                rgba[idx + 1] = 120; // G
-                rgba[idx + 2] = 120; // B
+                rgba[idx..idx + 3].copy_from_slice(&rgb);
                 rgba[idx + 3] = 255; // A
             } else {
                 rgba[idx + 3] = 0; // Transparent
             }
         }
     }
-    Icon::from_rgba(rgba, size as u32, size as u32).map_err(|e| anyhow::anyhow!("Icon error: {}", e))
+    Icon::from_rgba(rgba, size, size).map_err(|e| anyhow::anyhow!("Icon error: {}", e))
+}
+
+const PANIC_MUTE_TEXT: &str = "Panic Mute (Ctrl+Alt+M)";
+const SAVE_FAILED_TEXT: &str = "Settings could not be saved (path not writable)";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Builds the real tray icon and menus; run with `cargo test -- --ignored`
+    // from a desktop session
+    #[cfg(windows)]
+    #[test]
+    #[ignore = "needs an interactive desktop"]
+    fn test_tray_manager_smoke() {
+        let devices = ["Speakers (Realtek Audio)".to_string(), "CABLE Input (VB-Audio Virtual Cable)".to_string()];
+        let mut tray = TrayManager::new(
+            &devices,
+            &devices,
+            &["Desk".to_string()],
+            &TraySettings {
+                current_source: Some("Speakers"),
+                current_source_role: Some(SourceRole::Media),
+                current_target: Some("CABLE Input"),
+                current_volume: 0.8,
+                current_balance: -0.25,
+                current_left_source: ChannelSource::RL,
+                current_right_source: ChannelSource::RR,
+                current_left_volume: 1.0,
+                current_right_volume: 0.9,
+                left_trim_db: 0.0,
+                right_trim_db: -1.5,
+                left_muted: false,
+                right_muted: true,
+                enabled: true,
+                panic_muted: false,
+                swap_channels: false,
+                clone_stereo: false,
+                startup_enabled: false,
+                // DSP settings
+                input_gain_db: 3.0,
+                delay_ms: 12.0,
+                eq_enabled: true,
+                eq_low: -3.0,
+                eq_mid: 0.0,
+                eq_high: 2.0,
+                eq_freqs: (100.0, 1000.0, 8000.0),
+                ms_eq_enabled: false,
+                upmix_enabled: true,
+                upmix_strength: 0.5,
+                upmix_rear_delay_ms: 15.0,
+                dry_wet: 1.0,
+                compander_enabled: false,
+                subsonic_filter: true,
+                subsonic_cutoff_hz: 30.0,
+                limiter_enabled: true,
+                limiter_ceiling_db: -1.0,
+                sync_master_volume: false,
+                night_mode: NightMode::Auto,
+                capturable_output: true,
+            },
+        ).expect("tray manager");
+
+        tray.set_volume(0.5);
+        tray.set_enabled(false);
+        tray.set_night_mode(NightMode::On);
+        tray.set_current_target(Some("Speakers"));
+    }
 }